@@ -0,0 +1,99 @@
+// Copyright 2019-2022 Tauri Programme within The Commons Conservancy
+// SPDX-License-Identifier: Apache-2.0
+// SPDX-License-Identifier: MIT
+
+// A dependency-free harness: `cargo bench --bench software [filter]` prints the median
+// time of each renderer over a 1280x800 background.
+
+use std::time::{Duration, Instant};
+
+use window_vibrancy::software::{
+    box_blur, gaussian_blur, mica_background, noise_texture, render_acrylic, render_blur,
+    render_mica, AcrylicLayers, Image,
+};
+
+const WIDTH: u32 = 1280;
+const HEIGHT: u32 = 800;
+
+fn background() -> Image {
+    let mut data = Vec::with_capacity((WIDTH * HEIGHT * 4) as usize);
+    for y in 0..HEIGHT {
+        for x in 0..WIDTH {
+            data.extend_from_slice(&[(x % 256) as u8, (y % 256) as u8, ((x ^ y) % 256) as u8, 255]);
+        }
+    }
+    Image::from_rgba(WIDTH, HEIGHT, data).unwrap()
+}
+
+/// Times `f`, whose output is folded into `sink` so the work can't be optimized away.
+fn bench(filter: Option<&str>, sink: &mut u64, name: &str, mut f: impl FnMut() -> Image) {
+    if filter.map_or(false, |filter| !name.contains(filter)) {
+        return;
+    }
+    let mut run = || {
+        let image = f();
+        *sink = image
+            .as_rgba()
+            .iter()
+            .step_by(4099)
+            .fold(*sink, |sink, &byte| {
+                sink.wrapping_mul(31).wrapping_add(u64::from(byte))
+            });
+    };
+    run();
+    let mut samples = Vec::new();
+    let start = Instant::now();
+    while samples.len() < 5 || (start.elapsed() < Duration::from_secs(3) && samples.len() < 100) {
+        let sample = Instant::now();
+        run();
+        samples.push(sample.elapsed());
+    }
+    samples.sort_unstable();
+    println!(
+        "{:<24} {:>10.2?} (median of {})",
+        name,
+        samples[samples.len() / 2],
+        samples.len()
+    );
+}
+
+fn main() {
+    // cargo passes `--bench` along with the filter
+    let filter = std::env::args().skip(1).find(|arg| !arg.starts_with('-'));
+    let filter = filter.as_deref();
+    let background = background();
+    let sink = &mut 0;
+
+    bench(filter, sink, "gaussian_blur sigma=8", || {
+        let mut image = background.clone();
+        gaussian_blur(&mut image, 8.0);
+        image
+    });
+    bench(filter, sink, "box_blur sigma=8", || {
+        let mut image = background.clone();
+        box_blur(&mut image, 8.0);
+        image
+    });
+    bench(filter, sink, "box_blur sigma=60", || {
+        let mut image = background.clone();
+        box_blur(&mut image, 60.0);
+        image
+    });
+    bench(filter, sink, "render_blur", || {
+        render_blur(&background, Some((18, 18, 18, 125)))
+    });
+    bench(filter, sink, "render_acrylic", || {
+        render_acrylic(&background, Some((18, 18, 18, 125)))
+    });
+    bench(filter, sink, "acrylic_layers", || {
+        AcrylicLayers::new((32, 32, 32, 255), 0.8).render(&background)
+    });
+    bench(filter, sink, "render_mica", || {
+        render_mica(&background, true)
+    });
+    bench(filter, sink, "mica_background", || {
+        mica_background(&background, true)
+    });
+    bench(filter, sink, "noise_texture", || noise_texture(0));
+    println!("(checksum {:x})", sink);
+}
@@ -0,0 +1,26 @@
+// Copyright 2019-2022 Tauri Programme within The Commons Conservancy
+// SPDX-License-Identifier: Apache-2.0
+// SPDX-License-Identifier: MIT
+
+//! Run with `cargo +nightly fuzz run descriptor` from the crate root.
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use window_vibrancy::EffectDescriptor;
+
+// Every string parses to a descriptor whose canonical form parses back to it, or fails with
+// an error inside the input.
+fuzz_target!(|input: &str| {
+    match input.parse::<EffectDescriptor>() {
+        Ok(descriptor) => {
+            let printed = descriptor.to_string();
+            assert_eq!(printed.parse(), Ok(descriptor), "{:?} printed as {:?}", input, printed);
+        }
+        Err(error) => {
+            let span = error.span();
+            assert!(span.start <= span.end && span.end <= input.len());
+            assert!(input.is_char_boundary(span.start) && input.is_char_boundary(span.end));
+        }
+    }
+});
@@ -0,0 +1,123 @@
+// Copyright 2019-2022 Tauri Programme within The Commons Conservancy
+// SPDX-License-Identifier: Apache-2.0
+// SPDX-License-Identifier: MIT
+
+// KWin's background contrast effect, declared on every platform so presets can carry it.
+// https://invent.kde.org/frameworks/kwindowsystem/-/blob/master/src/kwindoweffects.cpp
+
+/// The color adjustments KWin applies to what is behind a window, like Plasma panels.
+///
+/// `1.0` leaves the channel as is.
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize), serde(default))]
+pub struct BackgroundContrast {
+    pub contrast: f32,
+    pub intensity: f32,
+    pub saturation: f32,
+}
+
+impl Default for BackgroundContrast {
+    fn default() -> Self {
+        Self {
+            contrast: 1.0,
+            intensity: 1.0,
+            saturation: 1.0,
+        }
+    }
+}
+
+impl BackgroundContrast {
+    /// The color matrix, computed like `KWindowEffects::enableBackgroundContrast`, in the
+    /// row-major order `_KDE_NET_WM_BACKGROUND_CONTRAST_REGION` carries and KWin gives
+    /// `QMatrix4x4`.
+    #[cfg_attr(not(target_os = "linux"), allow(dead_code))]
+    pub(crate) fn color_matrix(&self) -> [f32; 16] {
+        // multiplied as contrast * saturation * intensity
+        let s = self.saturation;
+        let (r, g, b) = ((1.0 - s) * 0.2126, (1.0 - s) * 0.7152, (1.0 - s) * 0.0722);
+        let saturation = [
+            [r + s, r, r, 0.0],
+            [g, g + s, g, 0.0],
+            [b, b, b + s, 0.0],
+            [0.0, 0.0, 0.0, 1.0],
+        ];
+        let i = self.intensity;
+        let intensity = [
+            [i, 0.0, 0.0, 0.0],
+            [0.0, i, 0.0, 0.0],
+            [0.0, 0.0, i, 0.0],
+            [0.0, 0.0, 0.0, 1.0],
+        ];
+        let c = self.contrast;
+        let t = (1.0 - c) / 2.0;
+        let contrast = [
+            [c, 0.0, 0.0, 0.0],
+            [0.0, c, 0.0, 0.0],
+            [0.0, 0.0, c, 0.0],
+            [t, t, t, 1.0],
+        ];
+
+        let matrix = multiply(&multiply(&contrast, &saturation), &intensity);
+        let mut row_major = [0.0; 16];
+        for (values, row) in row_major.chunks_exact_mut(4).zip(&matrix) {
+            values.copy_from_slice(row);
+        }
+        row_major
+    }
+}
+
+fn multiply(a: &[[f32; 4]; 4], b: &[[f32; 4]; 4]) -> [[f32; 4]; 4] {
+    let mut out = [[0.0; 4]; 4];
+    for (row, out_row) in out.iter_mut().enumerate() {
+        for (column, value) in out_row.iter_mut().enumerate() {
+            *value = (0..4).map(|k| a[row][k] * b[k][column]).sum();
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn default_matrix_is_the_identity() {
+        let matrix = BackgroundContrast::default().color_matrix();
+        for (i, value) in matrix.iter().enumerate() {
+            let expected = if i % 5 == 0 { 1.0 } else { 0.0 };
+            assert!((value - expected).abs() < 1e-6, "{}: {}", i, value);
+        }
+    }
+
+    #[test]
+    fn matrix_is_row_major() {
+        let matrix = BackgroundContrast {
+            contrast: 2.0,
+            ..Default::default()
+        }
+        .color_matrix();
+        assert_eq!(
+            matrix,
+            [
+                2.0, 0.0, 0.0, 0.0, //
+                0.0, 2.0, 0.0, 0.0, //
+                0.0, 0.0, 2.0, 0.0, //
+                -0.5, -0.5, -0.5, 1.0,
+            ]
+        );
+
+        // the translation, scaled by the intensity, stays on the last row and the alpha
+        // column is untouched
+        let matrix = BackgroundContrast {
+            contrast: 0.8,
+            intensity: 0.6,
+            saturation: 1.2,
+        }
+        .color_matrix();
+        assert!(matrix[12..15].iter().all(|&t| (t - 0.06).abs() < 1e-6));
+        assert_eq!(
+            [matrix[3], matrix[7], matrix[11], matrix[15]],
+            [0.0, 0.0, 0.0, 1.0]
+        );
+    }
+}
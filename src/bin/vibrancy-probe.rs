@@ -0,0 +1,281 @@
+// Copyright 2019-2022 Tauri Programme within The Commons Conservancy
+// SPDX-License-Identifier: Apache-2.0
+// SPDX-License-Identifier: MIT
+
+//! Prints what window-vibrancy sees of the current session, and tries the effects on a test window.
+
+use window_vibrancy::{
+    apply_effect, clear_effect, diagnostics, Effect, EffectDescriptor, EffectStep,
+    NSVisualEffectMaterial,
+};
+use winit::{
+    event::{ElementState, Event, KeyEvent, WindowEvent},
+    event_loop::{ControlFlow, EventLoop},
+    keyboard::{Key, NamedKey},
+    window::{Window, WindowBuilder},
+};
+
+const USAGE: &str = "\
+Usage: vibrancy-probe [COMMAND]
+
+Commands:
+  report [--json]   Print the diagnostics report of a hidden test window (default)
+  apply <EFFECT>    Apply an effect to a hidden test window, exit with 1 if it fails
+  try               Open a test window, press 1-5 to apply an effect, 0 to clear it, q to quit
+  x11 <ACTION> <WINDOW>
+                    Act on windows of other X11 clients (Linux only)
+
+X11 actions: list, blur, clear, opacity <0.0-1.0>, reset-opacity
+X11 windows: --id <XID>, --class <WM_CLASS>, --pid <PID>, --title <TEXT>
+
+Effects: blur, acrylic, mica, tabbed, vibrancy, or a descriptor like
+  'acrylic(tint=#121212cc)', 'vibrancy(sidebar, active, radius=12)' or 'kde-blur(contrast=1.2) | opacity(0.9)'";
+
+/// The effects in the order of the `try` keys.
+const EFFECTS: [(&str, Effect); 5] = [
+    ("blur", Effect::Blur(None)),
+    ("acrylic", Effect::Acrylic(None)),
+    ("mica", Effect::Mica { dark: None }),
+    ("tabbed", Effect::Tabbed { dark: None }),
+    (
+        "vibrancy",
+        Effect::Vibrancy {
+            material: NSVisualEffectMaterial::HudWindow,
+            state: None,
+            radius: None,
+        },
+    ),
+];
+
+fn main() {
+    std::process::exit(run());
+}
+
+/// Returns the exit code: 1 when the effect failed to apply, 2 for usage or window errors.
+fn run() -> i32 {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let args: Vec<&str> = args.iter().map(String::as_str).collect();
+    match args.as_slice() {
+        [] | ["report"] => report(false),
+        ["report", "--json"] => report(true),
+        ["apply", name] => match parse_effect(name) {
+            Ok(descriptor) => apply(name, descriptor),
+            Err(e) => usage_error(&format!("invalid effect \"{}\": {}", name, e)),
+        },
+        ["try"] => interactive(),
+        ["x11", rest @ ..] => x11(rest),
+        ["-h"] | ["--help"] => {
+            println!("{}", USAGE);
+            0
+        }
+        _ => usage_error("invalid arguments"),
+    }
+}
+
+fn report(json: bool) -> i32 {
+    with_hidden_window(|window| {
+        let report = diagnostics::report(window);
+        if json {
+            println!("{}", report.to_json());
+        } else {
+            print!("{}", report);
+        }
+        0
+    })
+}
+
+fn apply(name: &str, descriptor: EffectDescriptor) -> i32 {
+    with_hidden_window(|window| match descriptor.apply(window) {
+        Ok(()) => {
+            println!("{}: applied", name);
+            0
+        }
+        Err(e) => {
+            println!("{}: {}", name, e);
+            1
+        }
+    })
+}
+
+fn interactive() -> i32 {
+    let (event_loop, window) = match create_window(true) {
+        Ok(created) => created,
+        Err(code) => return code,
+    };
+    println!("{}\n", diagnostics::report(&window));
+    println!("press 1-5 to apply blur, acrylic, mica, tabbed or vibrancy, 0 to clear, q to quit");
+
+    let mut current: Option<(&str, Effect)> = None;
+    let result = event_loop.run(move |event, event_loop| {
+        event_loop.set_control_flow(ControlFlow::Wait);
+        let key = match event {
+            Event::WindowEvent {
+                event: WindowEvent::CloseRequested,
+                ..
+            } => return event_loop.exit(),
+            Event::WindowEvent {
+                event:
+                    WindowEvent::KeyboardInput {
+                        event:
+                            KeyEvent {
+                                logical_key,
+                                state: ElementState::Pressed,
+                                ..
+                            },
+                        ..
+                    },
+                ..
+            } => logical_key,
+            _ => return,
+        };
+
+        match key {
+            Key::Named(NamedKey::Escape) => event_loop.exit(),
+            Key::Character(c) if c == "q" => event_loop.exit(),
+            Key::Character(c) => {
+                let index = match c.parse::<usize>() {
+                    Ok(index) if index <= EFFECTS.len() => index,
+                    _ => return,
+                };
+                if let Some((name, effect)) = current.take() {
+                    print_result(&format!("clear {}", name), clear_effect(&window, &effect));
+                }
+                if index > 0 {
+                    let (name, effect) = EFFECTS[index - 1];
+                    print_result(name, apply_effect(&window, &effect));
+                    current = Some((name, effect));
+                }
+                window.request_redraw();
+            }
+            _ => {}
+        }
+    });
+
+    match result {
+        Ok(()) => 0,
+        Err(e) => {
+            eprintln!("error: {}", e);
+            1
+        }
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn x11(args: &[&str]) -> i32 {
+    use window_vibrancy::{ForeignWindow, WindowMatch};
+
+    let (action, opacity, selector) = match args {
+        ["opacity", value, selector @ ..] => match value.parse::<f64>() {
+            Ok(value) => ("opacity", Some(value), selector),
+            Err(_) => return usage_error(&format!("invalid opacity \"{}\"", value)),
+        },
+        [action, selector @ ..] => (*action, None, selector),
+        [] => return usage_error("missing x11 action"),
+    };
+    if !["list", "blur", "clear", "opacity", "reset-opacity"].contains(&action) {
+        return usage_error(&format!("unknown x11 action \"{}\"", action));
+    }
+    let query = match selector {
+        ["--id", id] => parse_id(id).map(WindowMatch::Id),
+        ["--class", class] => Some(WindowMatch::Class(class.to_string())),
+        ["--pid", pid] => pid.parse().ok().map(WindowMatch::Pid),
+        ["--title", title] => Some(WindowMatch::Title(title.to_string())),
+        _ => None,
+    };
+    let query = match query {
+        Some(query) => query,
+        None => return usage_error("invalid x11 window selector"),
+    };
+
+    let windows = match ForeignWindow::find(&query) {
+        Ok(windows) => windows,
+        Err(e) => return fail("can't list the X11 windows", e),
+    };
+    if windows.is_empty() {
+        eprintln!("no window matches {:?}", query);
+        return 1;
+    }
+
+    let mut code = 0;
+    for window in windows {
+        let result = match action {
+            "list" => {
+                let title = window.title().ok().flatten().unwrap_or_default();
+                println!("{:#x} {}", window.id(), title);
+                continue;
+            }
+            "blur" => window.apply_blur(),
+            "clear" => window.clear_blur(),
+            "opacity" => window.set_opacity(opacity),
+            _ => window.set_opacity(None),
+        };
+        if result.is_err() {
+            code = 1;
+        }
+        print_result(&format!("{} {:#x}", action, window.id()), result);
+    }
+    code
+}
+
+#[cfg(not(target_os = "linux"))]
+fn x11(_args: &[&str]) -> i32 {
+    eprintln!("error: the x11 command is only available on Linux");
+    2
+}
+
+/// Accepts decimal and `0x` prefixed XIDs, as printed by `xwininfo` and `xprop`.
+#[cfg(target_os = "linux")]
+fn parse_id(id: &str) -> Option<u32> {
+    match id.strip_prefix("0x") {
+        Some(hex) => u32::from_str_radix(hex, 16).ok(),
+        None => id.parse().ok(),
+    }
+}
+
+fn print_result(name: &str, result: Result<(), window_vibrancy::Error>) {
+    match result {
+        Ok(()) => println!("{}: ok", name),
+        Err(e) => println!("{}: {}", name, e),
+    }
+}
+
+/// One of the `try` effects by name, or an effect descriptor.
+fn parse_effect(name: &str) -> Result<EffectDescriptor, window_vibrancy::DescriptorError> {
+    match EFFECTS
+        .iter()
+        .find(|(effect, _)| effect.eq_ignore_ascii_case(name))
+    {
+        Some((_, effect)) => Ok(EffectDescriptor {
+            steps: vec![EffectStep::Effect(*effect)],
+        }),
+        None => name.parse(),
+    }
+}
+
+fn with_hidden_window(f: impl FnOnce(&Window) -> i32) -> i32 {
+    match create_window(false) {
+        Ok((_event_loop, window)) => f(&window),
+        Err(code) => code,
+    }
+}
+
+fn create_window(visible: bool) -> Result<(EventLoop<()>, Window), i32> {
+    let event_loop = EventLoop::new().map_err(|e| fail("can't connect to the display", e))?;
+    let window = WindowBuilder::new()
+        .with_title("vibrancy-probe")
+        .with_transparent(true)
+        .with_visible(visible)
+        .build(&event_loop)
+        .map_err(|e| fail("can't create the test window", e))?;
+    Ok((event_loop, window))
+}
+
+fn fail(context: &str, error: impl std::fmt::Display) -> i32 {
+    eprintln!("error: {}: {}", context, error);
+    2
+}
+
+fn usage_error(message: &str) -> i32 {
+    eprintln!("error: {}\n\n{}", message, USAGE);
+    2
+}
@@ -0,0 +1,83 @@
+// Copyright 2019-2022 Tauri Programme within The Commons Conservancy
+// SPDX-License-Identifier: Apache-2.0
+// SPDX-License-Identifier: MIT
+
+// WCAG 2 contrast, https://www.w3.org/TR/WCAG21/#dfn-contrast-ratio
+
+use crate::Color;
+
+/// The contrast WCAG AA requires for normal text.
+pub const WCAG_AA: f32 = 4.5;
+/// The contrast WCAG AAA requires for normal text.
+pub const WCAG_AAA: f32 = 7.0;
+
+/// The WCAG relative luminance of `color`, between `0.0` (black) and `1.0` (white). Alpha is ignored.
+pub fn relative_luminance(color: Color) -> f32 {
+    let linear = |c: u8| {
+        let c = c as f32 / 255.0;
+        if c <= 0.04045 {
+            c / 12.92
+        } else {
+            ((c + 0.055) / 1.055).powf(2.4)
+        }
+    };
+    0.2126 * linear(color.0) + 0.7152 * linear(color.1) + 0.0722 * linear(color.2)
+}
+
+/// The WCAG contrast ratio between two opaque colors, from `1.0` to `21.0`. Alpha is ignored.
+pub fn contrast_ratio(a: Color, b: Color) -> f32 {
+    luminance_contrast(relative_luminance(a), relative_luminance(b))
+}
+
+/// The lowest contrast `foreground` can have over `tint` drawn on top of any backdrop.
+///
+/// Luminance grows with every channel of the backdrop, so whatever is behind the window,
+/// the tinted result lies between the tint over pure black and the tint over pure white.
+pub fn worst_case_contrast(tint: Color, foreground: Color) -> f32 {
+    let over_black = relative_luminance(composite(tint, (0, 0, 0, 255)));
+    let over_white = relative_luminance(composite(tint, (255, 255, 255, 255)));
+    let foreground = relative_luminance(foreground);
+    if (over_black..=over_white).contains(&foreground) {
+        1.0
+    } else {
+        luminance_contrast(over_black, foreground).min(luminance_contrast(over_white, foreground))
+    }
+}
+
+/// Returns `base` with the lowest alpha that keeps `foreground` at `target_ratio` contrast
+/// over any backdrop, to pass to `apply_blur` or `apply_acrylic`.
+///
+/// The alpha of `base` is ignored. If even a fully opaque `base` doesn't reach `target_ratio`,
+/// the returned tint is fully opaque, check it with [`worst_case_contrast`] when that matters.
+///
+/// ```
+/// use window_vibrancy::{recommend_tint, worst_case_contrast, WCAG_AA};
+///
+/// let tint = recommend_tint((18, 18, 18, 0), (255, 255, 255, 255), WCAG_AA);
+/// assert!(tint.3 < 255);
+/// assert!(worst_case_contrast(tint, (255, 255, 255, 255)) >= WCAG_AA);
+/// ```
+pub fn recommend_tint(base: Color, foreground: Color, target_ratio: f32) -> Color {
+    // the range of possible luminances only shrinks toward the tint as its alpha grows,
+    // so the worst case contrast never decreases and the first alpha that passes is the lowest
+    (0..=255)
+        .map(|alpha| (base.0, base.1, base.2, alpha))
+        .find(|&tint| worst_case_contrast(tint, foreground) >= target_ratio)
+        .unwrap_or((base.0, base.1, base.2, 255))
+}
+
+fn luminance_contrast(a: f32, b: f32) -> f32 {
+    (a.max(b) + 0.05) / (a.min(b) + 0.05)
+}
+
+/// `color` drawn over the opaque `backdrop`, blended in sRGB like the compositors do.
+fn composite(color: Color, backdrop: Color) -> Color {
+    let a = color.3 as f32 / 255.0;
+    let mix = |c: u8, b: u8| (c as f32 * a + b as f32 * (1.0 - a)).round() as u8;
+    (
+        mix(color.0, backdrop.0),
+        mix(color.1, backdrop.1),
+        mix(color.2, backdrop.2),
+        255,
+    )
+}
@@ -0,0 +1,689 @@
+// Copyright 2019-2022 Tauri Programme within The Commons Conservancy
+// SPDX-License-Identifier: Apache-2.0
+// SPDX-License-Identifier: MIT
+
+use std::time::Instant;
+
+use raw_window_handle::HasWindowHandle;
+
+use crate::{
+    apply_blur_region, apply_effect, clear_effect, region, window_state, Color, Effect,
+    EffectCommand, EffectMachine, Error, FocusPolicy, MoveSizeCommand, MoveSizeMachine,
+    MoveSizePolicy, Rect, RegionLayout, WindowState, WindowStatePolicy,
+};
+
+/// Keeps the effects of a window in sync with its state.
+///
+/// Forward your windowing library's events to the `on_*` hooks and the controller
+/// re-applies whatever needs to change, and nothing else.
+///
+/// ```no_run
+/// use window_vibrancy::{AnchoredRect, Length, RegionLayout, VibrancyController};
+///
+/// # let window: &dyn raw_window_handle::HasWindowHandle = unsafe { std::mem::zeroed() };
+/// let mut controller = VibrancyController::new(window);
+/// controller
+///     .set_blur_region(
+///         RegionLayout::new().with(AnchoredRect::left(Length::Logical(240.0))),
+///         None,
+///     )
+///     .unwrap();
+///
+/// // on every resize or scale factor change
+/// controller.on_resize(1600, 1200, 2.0).unwrap();
+/// ```
+///
+/// It can also dim the window's effect while it is not focused:
+///
+/// ```no_run
+/// use window_vibrancy::{Effect, FocusPolicy, InactiveEffect, VibrancyController};
+///
+/// # let window: &dyn raw_window_handle::HasWindowHandle = unsafe { std::mem::zeroed() };
+/// let mut controller = VibrancyController::new(window);
+/// controller
+///     .set_focus_policy(FocusPolicy {
+///         inactive_effect: InactiveEffect::Same,
+///         inactive_tint: Some((32, 32, 32, 230)),
+///     })
+///     .unwrap();
+/// controller.set_effect(Some(Effect::Acrylic(None))).unwrap();
+///
+/// // on every focus change
+/// controller.on_focus_changed(false).unwrap();
+/// ```
+///
+/// Or drop it while the window is maximized or fullscreen, where there is nothing to blur:
+///
+/// ```no_run
+/// use window_vibrancy::{Effect, StateEffect, VibrancyController, WindowStatePolicy};
+///
+/// # let window: &dyn raw_window_handle::HasWindowHandle = unsafe { std::mem::zeroed() };
+/// let mut controller = VibrancyController::new(window);
+/// controller
+///     .set_window_state_policy(WindowStatePolicy {
+///         maximized: StateEffect::Solid((32, 32, 32, 255)),
+///         fullscreen: StateEffect::Disable,
+///     })
+///     .unwrap();
+/// controller.set_effect(Some(Effect::Acrylic(None))).unwrap();
+///
+/// // on every resize
+/// controller.refresh_window_state().unwrap();
+/// if let Some(color) = controller.solid_color() {
+///     // paint `color` behind the content
+/// }
+/// ```
+///
+/// And swap it for a solid tint while the user drags or resizes the window, where blur and
+/// acrylic lag on some Windows builds:
+///
+/// ```no_run
+/// use window_vibrancy::{Effect, MoveSizePolicy, VibrancyController};
+///
+/// # let window: &dyn raw_window_handle::HasWindowHandle = unsafe { std::mem::zeroed() };
+/// let mut controller = VibrancyController::new(window);
+/// controller.set_effect(Some(Effect::Acrylic(None))).unwrap();
+/// controller
+///     .set_move_size_policy(Some(MoveSizePolicy::new((32, 32, 32, 255))))
+///     .unwrap();
+///
+/// // on every event loop iteration, waking up at `deadline` at the latest
+/// let deadline = controller.poll_move_size().unwrap();
+/// ```
+pub struct VibrancyController<W> {
+    window: W,
+    region: RegionTracker,
+    /// The scale factor of the last resize, for resize events without one.
+    #[cfg_attr(not(any(feature = "winit", feature = "tao")), allow(dead_code))]
+    scale: f64,
+    effects: EffectMachine,
+    /// Whether the decorations follow the system theme, see `set_dark_mode`.
+    follows_system_theme: bool,
+    move_size: Option<MoveSizeMachine>,
+    #[cfg(target_os = "linux")]
+    move_size_tracker: Option<crate::linux::MoveSizeTracker>,
+}
+
+impl<W: HasWindowHandle> VibrancyController<W> {
+    pub fn new(window: W) -> Self {
+        Self {
+            window,
+            region: RegionTracker::default(),
+            scale: 1.0,
+            effects: EffectMachine::new(FocusPolicy::default()),
+            follows_system_theme: false,
+            move_size: None,
+            #[cfg(target_os = "linux")]
+            move_size_tracker: None,
+        }
+    }
+
+    /// A controller for a window whose size, focus and state are already known, see
+    /// [`WindowVibrancyExt::vibrancy_controller`](crate::WindowVibrancyExt::vibrancy_controller).
+    #[cfg(any(feature = "winit", feature = "tao"))]
+    pub(crate) fn with_window_state(
+        window: W,
+        (width, height, scale): (u32, u32, f64),
+        focused: bool,
+        state: WindowState,
+    ) -> Self {
+        let mut controller = Self::new(window);
+        controller.region.size = Some((width, height, scale));
+        controller.scale = scale;
+        // without an effect, there is nothing to apply
+        controller.effects.on_focus_changed(focused);
+        controller.effects.on_window_state_changed(state);
+        controller
+    }
+
+    pub fn window(&self) -> &W {
+        &self.window
+    }
+
+    /// Blurs the parts of the window described by `layout`, see [`apply_blur_region`].
+    ///
+    /// The region is applied right away if the window size is already known,
+    /// otherwise on the next [`on_resize`](Self::on_resize).
+    pub fn set_blur_region(
+        &mut self,
+        layout: RegionLayout,
+        tint: Option<Color>,
+    ) -> Result<(), Error> {
+        self.region.set_region(Some((layout, tint)));
+        self.sync_region()
+    }
+
+    /// Removes the blur region set by [`set_blur_region`](Self::set_blur_region).
+    pub fn clear_blur_region(&mut self) -> Result<(), Error> {
+        if self.region.set_region(None) {
+            apply_blur_region(&self.window, &[], None)?;
+        }
+        Ok(())
+    }
+
+    /// Notifies the controller that the window was resized to `width` x `height`
+    /// physical pixels, or that its `scale` factor changed.
+    pub fn on_resize(&mut self, width: u32, height: u32, scale: f64) -> Result<(), Error> {
+        self.region.size = Some((width, height, scale));
+        self.scale = scale;
+        self.sync_region()
+    }
+
+    /// Applies `effect` to the whole window, clearing the previous one, or only clears it
+    /// when `effect` is `None`. While the window is not focused, the [`FocusPolicy`] decides
+    /// what is applied, and while it is maximized or fullscreen the [`WindowStatePolicy`].
+    pub fn set_effect(&mut self, effect: Option<Effect>) -> Result<(), Error> {
+        let commands = self.effects.set_effect(effect);
+        self.run(commands)
+    }
+
+    pub fn set_focus_policy(&mut self, policy: FocusPolicy) -> Result<(), Error> {
+        let commands = self.effects.set_focus_policy(policy);
+        self.run(commands)
+    }
+
+    /// Notifies the controller that the window gained or lost focus.
+    ///
+    /// ## Platform-specific
+    ///
+    /// - **macOS**: Prefer [`NSVisualEffectState::FollowsWindowActiveState`](crate::NSVisualEffectState),
+    ///   vibrancy can't be cleared nor replaced. Changes that would replace it return
+    ///   [`Error::UnsupportedPlatform`] and keep the applied one.
+    pub fn on_focus_changed(&mut self, focused: bool) -> Result<(), Error> {
+        let commands = self.effects.on_focus_changed(focused);
+        self.run(commands)
+    }
+
+    /// Draws the window decorations dark or light, see [`set_dark_mode`](crate::set_dark_mode).
+    /// With `None` and if it succeeds, they follow the system again on every
+    /// [`on_theme_changed`](Self::on_theme_changed).
+    pub fn set_dark_mode(&mut self, dark: Option<bool>) -> Result<(), Error> {
+        crate::set_dark_mode(&self.window, dark)?;
+        self.follows_system_theme = dark.is_none();
+        Ok(())
+    }
+
+    /// Notifies the controller that the system switched between dark and light mode,
+    /// re-applying Mica and Tabbed if they follow it, and the decorations if they follow
+    /// it since [`set_dark_mode(None)`](Self::set_dark_mode).
+    pub fn on_theme_changed(&mut self, dark: bool) -> Result<(), Error> {
+        let commands = self.effects.on_theme_changed(dark);
+        let result = self.run(commands);
+        if self.follows_system_theme {
+            crate::set_dark_mode(&self.window, None)?;
+        }
+        result
+    }
+
+    pub fn set_window_state_policy(&mut self, policy: WindowStatePolicy) -> Result<(), Error> {
+        let commands = self.effects.set_state_policy(policy);
+        self.run(commands)
+    }
+
+    /// Notifies the controller that the window was maximized, restored, or entered or left
+    /// fullscreen.
+    pub fn on_window_state_changed(&mut self, state: WindowState) -> Result<(), Error> {
+        let commands = self.effects.on_window_state_changed(state);
+        self.run(commands)
+    }
+
+    /// Reads the state of the window with [`window_state`] and notifies the controller
+    /// if it changed.
+    pub fn refresh_window_state(&mut self) -> Result<(), Error> {
+        let state = window_state(&self.window)?;
+        if state == self.effects.window_state() {
+            return Ok(());
+        }
+        self.on_window_state_changed(state)
+    }
+
+    /// The color to paint behind the window's content while the [`WindowStatePolicy`] replaces
+    /// the effect with [`StateEffect::Solid`](crate::StateEffect::Solid).
+    pub fn solid_color(&self) -> Option<Color> {
+        self.effects.solid_color()
+    }
+
+    /// Swaps the effect of [`set_effect`](Self::set_effect) for `policy.tint` while the user
+    /// moves or resizes the window. `None` stops, restoring the effect.
+    ///
+    /// ## Platform-specific
+    ///
+    /// - **Windows**: The window is subclassed to follow `WM_ENTERSIZEMOVE` and `WM_EXITSIZEMOVE`
+    ///   and DWM draws the tint, the controller doesn't need to be polled. Effects changed
+    ///   while the tint is drawn are applied once the move or resize ends.
+    /// - **Linux**: On X11, moves and resizes started with `_NET_WM_MOVERESIZE`, like the ones of
+    ///   client-side decorations, are found by [`poll_move_size`](Self::poll_move_size). They end
+    ///   once the window stopped moving for [`MoveSizePolicy::idle_timeout`].
+    /// - **macOS**: Report moves and resizes with [`on_move_size_started`](Self::on_move_size_started)
+    ///   and [`on_move_size_ended`](Self::on_move_size_ended).
+    ///
+    /// Except on Windows, the application paints [`solid_color`](Self::solid_color) while the
+    /// effect is swapped.
+    pub fn set_move_size_policy(&mut self, policy: Option<MoveSizePolicy>) -> Result<(), Error> {
+        if self
+            .move_size
+            .take()
+            .map_or(false, |machine| machine.is_swapped())
+        {
+            let commands = self.effects.set_swap_tint(None);
+            self.run(commands)?;
+        }
+        #[cfg(target_os = "linux")]
+        {
+            self.move_size_tracker = None;
+        }
+
+        self.move_size = match self.window.window_handle()?.as_raw() {
+            // the subclass owns the machine and the swap, the controller only tells it which
+            // effect the window has, see `run`
+            #[cfg(target_os = "windows")]
+            raw_window_handle::RawWindowHandle::Win32(handle) => {
+                let hwnd = handle.hwnd.get() as _;
+                match policy {
+                    Some(policy) => {
+                        crate::windows::enable_move_size_swap(hwnd, policy, self.effects.applied())?
+                    }
+                    None => crate::windows::disable_move_size_swap(hwnd),
+                }
+                None
+            }
+            #[cfg(target_os = "linux")]
+            handle @ (raw_window_handle::RawWindowHandle::Xlib(_)
+            | raw_window_handle::RawWindowHandle::Xcb(_))
+                if policy.is_some() =>
+            {
+                self.move_size_tracker = Some(crate::linux::move_size_tracker(handle)?);
+                policy.map(MoveSizeMachine::new)
+            }
+            _ => policy.map(MoveSizeMachine::new),
+        };
+        Ok(())
+    }
+
+    /// Notifies the controller that the user started moving or resizing the window, where
+    /// it can't find out by itself. Returns like [`poll_move_size`](Self::poll_move_size).
+    pub fn on_move_size_started(&mut self) -> Result<Option<Instant>, Error> {
+        if let Some(machine) = &mut self.move_size {
+            machine.on_move_size_started(Instant::now());
+        }
+        self.poll_move_size()
+    }
+
+    /// Notifies the controller that the move or resize ended.
+    pub fn on_move_size_ended(&mut self) -> Result<Option<Instant>, Error> {
+        if let Some(machine) = &mut self.move_size {
+            machine.on_move_size_ended(Instant::now());
+        }
+        self.poll_move_size()
+    }
+
+    /// Swaps or restores the effect if it is due, and returns when to call it again at the
+    /// latest, if there is something left to do.
+    pub fn poll_move_size(&mut self) -> Result<Option<Instant>, Error> {
+        let now = Instant::now();
+        #[cfg(target_os = "linux")]
+        if let (Some(machine), Some(tracker)) = (&mut self.move_size, &self.move_size_tracker) {
+            for event in tracker.poll_events()? {
+                match event {
+                    crate::linux::MoveSizeEvent::Started => {
+                        machine.on_move_size_started(now);
+                        machine.on_move_size_activity(now);
+                    }
+                    crate::linux::MoveSizeEvent::Cancelled => machine.on_move_size_ended(now),
+                    crate::linux::MoveSizeEvent::Configured => machine.on_move_size_activity(now),
+                }
+            }
+        }
+
+        while let Some(command) = self
+            .move_size
+            .as_mut()
+            .and_then(|machine| machine.poll(now))
+        {
+            let commands = self.effects.set_swap_tint(match command {
+                MoveSizeCommand::Swap(tint) => Some(tint),
+                MoveSizeCommand::Restore => None,
+            });
+            self.run(commands)?;
+        }
+        Ok(self.move_size.as_ref().and_then(MoveSizeMachine::deadline))
+    }
+
+    /// Forwards the `Focused`, `Resized`, `ScaleFactorChanged` and `ThemeChanged` events of
+    /// winit, which also refresh the window state and poll the move and resize policy.
+    /// Other events are ignored.
+    ///
+    /// Where [`window_state`] is not supported, like on Wayland, the state is not refreshed
+    /// and has to be given to [`on_window_state_changed`](Self::on_window_state_changed).
+    #[cfg(feature = "winit")]
+    pub fn on_winit_event(&mut self, event: &winit::event::WindowEvent) -> Result<(), Error> {
+        match event {
+            winit::event::WindowEvent::Focused(focused) => self.on_focus_changed(*focused),
+            winit::event::WindowEvent::Moved(_) => self.poll_move_size().map(|_| ()),
+            winit::event::WindowEvent::Resized(size) => {
+                self.on_resize(size.width, size.height, self.scale)?;
+                self.poll_move_size()?;
+                self.refresh_window_state_if_supported()
+            }
+            // winit sends the new size in a `Resized` event right after
+            winit::event::WindowEvent::ScaleFactorChanged { scale_factor, .. } => {
+                match self.region.size {
+                    Some((width, height, _)) => self.on_resize(width, height, *scale_factor),
+                    None => {
+                        self.scale = *scale_factor;
+                        Ok(())
+                    }
+                }
+            }
+            winit::event::WindowEvent::ThemeChanged(theme) => {
+                self.on_theme_changed(*theme == winit::window::Theme::Dark)
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Forwards the `Focused`, `Resized`, `ScaleFactorChanged` and `ThemeChanged` events of
+    /// tao, which also refresh the window state and poll the move and resize policy.
+    /// Other events are ignored.
+    ///
+    /// Where [`window_state`] is not supported, like on Wayland, the state is not refreshed
+    /// and has to be given to [`on_window_state_changed`](Self::on_window_state_changed).
+    #[cfg(feature = "tao")]
+    pub fn on_tao_event(&mut self, event: &tao::event::WindowEvent<'_>) -> Result<(), Error> {
+        match event {
+            tao::event::WindowEvent::Focused(focused) => self.on_focus_changed(*focused),
+            tao::event::WindowEvent::Moved(_) => self.poll_move_size().map(|_| ()),
+            tao::event::WindowEvent::Resized(size) => {
+                self.on_resize(size.width, size.height, self.scale)?;
+                self.poll_move_size()?;
+                self.refresh_window_state_if_supported()
+            }
+            tao::event::WindowEvent::ScaleFactorChanged {
+                scale_factor,
+                new_inner_size,
+            } => self.on_resize(new_inner_size.width, new_inner_size.height, *scale_factor),
+            tao::event::WindowEvent::ThemeChanged(theme) => {
+                self.on_theme_changed(*theme == tao::window::Theme::Dark)
+            }
+            _ => Ok(()),
+        }
+    }
+
+    #[cfg(any(feature = "winit", feature = "tao"))]
+    fn refresh_window_state_if_supported(&mut self) -> Result<(), Error> {
+        match self.refresh_window_state() {
+            Err(Error::UnsupportedPlatform(_)) => Ok(()),
+            result => result,
+        }
+    }
+
+    /// Runs the commands of the effect machine, telling it which one failed so the next
+    /// change starts from the effect the window still has.
+    fn run(&mut self, commands: Vec<EffectCommand>) -> Result<(), Error> {
+        #[cfg(target_os = "windows")]
+        let hwnd = match self.window.window_handle().map(|handle| handle.as_raw()) {
+            Ok(raw_window_handle::RawWindowHandle::Win32(handle)) => Some(handle.hwnd.get() as _),
+            _ => None,
+        };
+        // the subclass swapped the effect for its tint, it applies the new one when it restores it
+        #[cfg(target_os = "windows")]
+        if let Some(hwnd) = hwnd.filter(|&hwnd| crate::windows::is_move_size_swapped(hwnd)) {
+            crate::windows::set_move_size_effect(hwnd, self.effects.applied());
+            return Ok(());
+        }
+
+        // AppKit would stack another view over the vibrancy instead of replacing it
+        #[cfg(target_os = "macos")]
+        if let Some(&command) = commands
+            .first()
+            .filter(|command| replaces_vibrancy(self.effects.previous(), command))
+        {
+            self.effects.on_command_failed(command);
+            return Err(Error::UnsupportedPlatform(
+                "replacing vibrancy is only supported on Linux.",
+            ));
+        }
+
+        let mut result = Ok(());
+        for command in commands {
+            result = match command {
+                EffectCommand::Apply(effect) => apply_effect(&self.window, &effect),
+                EffectCommand::Clear(effect) => clear_effect(&self.window, &effect),
+            };
+            if result.is_err() {
+                self.effects.on_command_failed(command);
+                break;
+            }
+        }
+        // the move and resize subclass swaps the effect the window has now
+        #[cfg(target_os = "windows")]
+        if let Some(hwnd) = hwnd {
+            crate::windows::set_move_size_effect(hwnd, self.effects.applied());
+        }
+        result
+    }
+
+    /// Submits the region if what it blurs changed since last time.
+    fn sync_region(&mut self) -> Result<(), Error> {
+        let update = match self.region.update() {
+            Some(update) => update,
+            None => return Ok(()),
+        };
+        if update.blur_changed {
+            apply_blur_region(&self.window, &update.rects, update.tint)?;
+        } else {
+            // the blur did not move, but the opaque region around it grew or shrank
+            #[cfg(target_os = "linux")]
+            crate::linux::update_opaque_region(
+                self.window.window_handle()?.as_raw(),
+                update.size.0,
+                update.size.1,
+                &update.rects,
+            )?;
+        }
+        self.region.submitted(&update);
+        Ok(())
+    }
+}
+
+/// Whether `command` applies vibrancy over the vibrancy the window had.
+#[cfg_attr(not(target_os = "macos"), allow(dead_code))]
+fn replaces_vibrancy(previous: Option<Effect>, command: &EffectCommand) -> bool {
+    matches!(
+        (previous, command),
+        (
+            Some(Effect::Vibrancy { .. }),
+            EffectCommand::Apply(Effect::Vibrancy { .. })
+        )
+    )
+}
+
+/// The blur region of a [`VibrancyController`] and what the compositor blurs of it, so it is
+/// only submitted again when that changes.
+#[derive(Debug, Default)]
+struct RegionTracker {
+    region: Option<(RegionLayout, Option<Color>)>,
+    size: Option<(u32, u32, f64)>,
+    /// The visible part of the last submitted region, and the window size then.
+    submitted: Option<(Vec<Rect>, (u32, u32))>,
+}
+
+/// What brings the submitted region up to date, see [`RegionTracker::update`].
+#[derive(Debug, PartialEq)]
+struct RegionUpdate {
+    rects: Vec<Rect>,
+    tint: Option<Color>,
+    size: (u32, u32),
+    /// Whether the blur changed, rather than only the window size around it.
+    blur_changed: bool,
+}
+
+impl RegionTracker {
+    /// Replaces the region, returns whether one was submitted.
+    fn set_region(&mut self, region: Option<(RegionLayout, Option<Color>)>) -> bool {
+        self.region = region;
+        // the tint may have changed even if the rects did not
+        self.submitted.take().is_some()
+    }
+
+    fn update(&self) -> Option<RegionUpdate> {
+        let ((layout, tint), (width, height, scale)) = match (&self.region, self.size) {
+            (Some(region), Some(size)) => (region, size),
+            _ => return None,
+        };
+        let rects = layout.resolve(width, height, scale);
+        let blur_changed = match &self.submitted {
+            Some((visible, size)) if *visible == region::visible(&rects, width, height) => {
+                if *size == (width, height) {
+                    return None;
+                }
+                false
+            }
+            _ => true,
+        };
+        Some(RegionUpdate {
+            rects,
+            tint: *tint,
+            size: (width, height),
+            blur_changed,
+        })
+    }
+
+    fn submitted(&mut self, update: &RegionUpdate) {
+        let (width, height) = update.size;
+        self.submitted = Some((region::visible(&update.rects, width, height), update.size));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{AnchoredRect, Length};
+    use raw_window_handle::{HandleError, RawWindowHandle, WebWindowHandle, WindowHandle};
+
+    /// Runs the update like the controller, returns it.
+    fn sync(tracker: &mut RegionTracker) -> Option<RegionUpdate> {
+        let update = tracker.update()?;
+        tracker.submitted(&update);
+        Some(update)
+    }
+
+    fn sidebar() -> RegionTracker {
+        let mut tracker = RegionTracker::default();
+        tracker.set_region(Some((
+            RegionLayout::new().with(AnchoredRect::left(Length::Logical(240.0))),
+            None,
+        )));
+        tracker
+    }
+
+    /// A window of a kind `set_dark_mode` doesn't support on any platform.
+    struct WebWindow;
+
+    impl HasWindowHandle for WebWindow {
+        fn window_handle(&self) -> Result<WindowHandle<'_>, HandleError> {
+            let handle = RawWindowHandle::Web(WebWindowHandle::new(1));
+            Ok(unsafe { WindowHandle::borrow_raw(handle) })
+        }
+    }
+
+    #[test]
+    fn unsupported_dark_mode_does_not_follow_the_system() {
+        let mut controller = VibrancyController::new(WebWindow);
+        assert!(matches!(
+            controller.set_dark_mode(None),
+            Err(Error::UnsupportedPlatform(_))
+        ));
+        assert!(!controller.follows_system_theme);
+        assert!(controller.on_theme_changed(true).is_ok());
+        assert!(controller.on_theme_changed(false).is_ok());
+    }
+
+    #[test]
+    fn vibrancy_is_not_replaced() {
+        let vibrancy = |material| Effect::Vibrancy {
+            material,
+            state: None,
+            radius: None,
+        };
+        let sidebar = vibrancy(crate::NSVisualEffectMaterial::Sidebar);
+        let menu = vibrancy(crate::NSVisualEffectMaterial::Menu);
+
+        let mut machine = EffectMachine::new(crate::FocusPolicy {
+            inactive_effect: crate::InactiveEffect::Effect(menu),
+            inactive_tint: None,
+        });
+        let commands = machine.set_effect(Some(sidebar));
+        assert!(!replaces_vibrancy(machine.previous(), &commands[0]));
+        let commands = machine.on_focus_changed(false);
+        assert_eq!(commands, [EffectCommand::Apply(menu)]);
+        assert!(replaces_vibrancy(machine.previous(), &commands[0]));
+
+        // refused, the window keeps its vibrancy and focusing it again changes nothing
+        machine.on_command_failed(commands[0]);
+        assert_eq!(machine.applied(), Some(sidebar));
+        assert_eq!(machine.on_focus_changed(true), []);
+    }
+
+    #[test]
+    fn submits_once_the_size_is_known() {
+        let mut tracker = sidebar();
+        assert_eq!(sync(&mut tracker), None);
+        tracker.size = Some((800, 600, 1.0));
+        let update = sync(&mut tracker).unwrap();
+        assert_eq!(update.rects, [Rect::new(0, 0, 240, 600)]);
+        assert!(update.blur_changed);
+
+        // an identical resize
+        assert_eq!(sync(&mut tracker), None);
+        // a new tint is submitted even with the same rects
+        tracker.set_region(Some((
+            RegionLayout::new().with(AnchoredRect::left(Length::Logical(240.0))),
+            Some((0, 0, 0, 128)),
+        )));
+        assert!(sync(&mut tracker).unwrap().blur_changed);
+        assert!(tracker.set_region(None));
+        assert_eq!(sync(&mut tracker), None);
+    }
+
+    #[test]
+    fn resolves_again_on_resize_and_scale_changes() {
+        let mut tracker = sidebar();
+        tracker.size = Some((800, 600, 1.0));
+        sync(&mut tracker).unwrap();
+
+        // the strip follows the height
+        tracker.size = Some((800, 700, 1.0));
+        let update = sync(&mut tracker).unwrap();
+        assert_eq!(update.rects, [Rect::new(0, 0, 240, 700)]);
+        assert!(update.blur_changed);
+
+        // only the opaque region around the strip grows
+        tracker.size = Some((900, 700, 1.0));
+        let update = sync(&mut tracker).unwrap();
+        assert_eq!(update.size, (900, 700));
+        assert!(!update.blur_changed);
+
+        tracker.size = Some((900, 700, 2.0));
+        let update = sync(&mut tracker).unwrap();
+        assert_eq!(update.rects, [Rect::new(0, 0, 480, 700)]);
+        assert!(update.blur_changed);
+    }
+
+    #[test]
+    fn submits_again_when_a_clipped_region_grows() {
+        let mut tracker = sidebar();
+        tracker.size = Some((100, 600, 1.0));
+        sync(&mut tracker).unwrap();
+        // the strip is as wide, but more of it is visible
+        tracker.size = Some((200, 600, 1.0));
+        let update = sync(&mut tracker).unwrap();
+        assert_eq!(update.rects, [Rect::new(0, 0, 240, 600)]);
+        assert!(update.blur_changed);
+        // past the strip, only the opaque region changes
+        tracker.size = Some((300, 600, 1.0));
+        sync(&mut tracker).unwrap();
+        tracker.size = Some((400, 600, 1.0));
+        assert!(!sync(&mut tracker).unwrap().blur_changed);
+    }
+}
@@ -0,0 +1,767 @@
+// Copyright 2019-2022 Tauri Programme within The Commons Conservancy
+// SPDX-License-Identifier: Apache-2.0
+// SPDX-License-Identifier: MIT
+
+// The grammar, whitespace being allowed around every token:
+//
+//   descriptor = "none" | step ("|" step)*
+//   step       = name ["(" [argument ("," argument)* [","]] ")"]
+//   argument   = [key "="] word
+//
+// A word is a run of anything but whitespace and `(),|=`: names, numbers and `#` colors.
+
+use std::{fmt, ops::Range, str::FromStr};
+
+use raw_window_handle::HasWindowHandle;
+
+use crate::{
+    apply_effect, clear_effect, set_opacity, trace, user_config, BackgroundContrast, Color, Effect,
+    Error,
+};
+
+/// One step of an [`EffectDescriptor`].
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum EffectStep {
+    /// `blur`, `acrylic(tint=#121212cc)`, `mica(dark)`, `tabbed(light)`,
+    /// `vibrancy(hud-window, active, radius=12)`.
+    Effect(Effect),
+    /// `kde-blur(contrast=1.2, intensity=1, saturation=1.5)`: blur with a background
+    /// contrast, on KDE Plasma. Only blurs on SwayFX.
+    KdeBlur(BackgroundContrast),
+    /// `opacity(0.9)`: the opacity of the whole window, its content included, between `0.0`
+    /// and `1.0`. On Windows, macOS and X11 with a compositing window manager.
+    Opacity(f64),
+}
+
+/// Effects written as a string, for configuration files and environment variables.
+///
+/// Steps are separated by `|` and applied in order, `none` has no step:
+///
+/// ```
+/// use window_vibrancy::{Effect, EffectDescriptor, EffectStep};
+///
+/// let descriptor: EffectDescriptor = "kde-blur(contrast=1.2) | opacity(0.9)".parse().unwrap();
+/// assert_eq!(descriptor.steps[1], EffectStep::Opacity(0.9));
+/// assert_eq!(descriptor.to_string(), "kde-blur(contrast=1.2) | opacity(0.9)");
+///
+/// let descriptor: EffectDescriptor = "acrylic(tint = #121212CC)".parse().unwrap();
+/// assert_eq!(descriptor.steps, [EffectStep::Effect(Effect::Acrylic(Some((18, 18, 18, 204))))]);
+///
+/// let error = "vibrancy(hud-window, radius=twelve)".parse::<EffectDescriptor>().unwrap_err();
+/// assert_eq!(error.span(), 28..34);
+/// ```
+///
+/// [`Display`](fmt::Display) prints the canonical form, which parses back to the same descriptor.
+/// Numbers must be finite and opacities between `0.0` and `1.0`: steps built in code that
+/// break these rules print to a string that doesn't parse, like `opacity(2)`.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct EffectDescriptor {
+    pub steps: Vec<EffectStep>,
+}
+
+impl EffectDescriptor {
+    /// Applies the steps in order, stopping at the first failure.
+    pub fn apply(&self, window: impl HasWindowHandle) -> Result<(), Error> {
+        for step in &self.steps {
+            match *step {
+                EffectStep::Effect(effect) => apply_effect(&window, &effect)?,
+                EffectStep::KdeBlur(contrast) => apply_kde_blur(&window, contrast)?,
+                EffectStep::Opacity(opacity) => set_opacity(&window, Some(opacity))?,
+            }
+        }
+        Ok(())
+    }
+
+    /// Clears the steps in reverse order, stopping at the first failure.
+    pub fn clear(&self, window: impl HasWindowHandle) -> Result<(), Error> {
+        for step in self.steps.iter().rev() {
+            match *step {
+                EffectStep::Effect(effect) => clear_effect(&window, &effect)?,
+                EffectStep::KdeBlur(_) => clear_kde_blur(&window)?,
+                EffectStep::Opacity(_) => set_opacity(&window, None)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+fn apply_kde_blur(window: impl HasWindowHandle, contrast: BackgroundContrast) -> Result<(), Error> {
+    #[cfg(not(target_os = "linux"))]
+    let _ = contrast;
+    if !user_config::permits("apply_kde_blur", user_config::EffectLevel::Blur) {
+        return Ok(());
+    }
+    let handle = window.window_handle()?.as_raw();
+    trace::instrument("apply_kde_blur", handle, || match handle {
+        #[cfg(target_os = "linux")]
+        handle @ (raw_window_handle::RawWindowHandle::Xlib(_)
+        | raw_window_handle::RawWindowHandle::Xcb(_)
+        | raw_window_handle::RawWindowHandle::Wayland(_)) => crate::linux::apply_preset(
+            handle,
+            &crate::LinuxPreset {
+                blur: true,
+                radius: None,
+                contrast: Some(contrast),
+                tint: None,
+            },
+        ),
+        _ => Err(Error::UnsupportedPlatform(
+            "\"kde-blur\" is only supported on Linux.",
+        )),
+    })
+}
+
+fn clear_kde_blur(window: impl HasWindowHandle) -> Result<(), Error> {
+    let handle = window.window_handle()?.as_raw();
+    trace::instrument("clear_kde_blur", handle, || match handle {
+        #[cfg(target_os = "linux")]
+        handle @ (raw_window_handle::RawWindowHandle::Xlib(_)
+        | raw_window_handle::RawWindowHandle::Xcb(_)
+        | raw_window_handle::RawWindowHandle::Wayland(_)) => crate::linux::clear_preset(handle),
+        _ => Err(Error::UnsupportedPlatform(
+            "\"kde-blur\" is only supported on Linux.",
+        )),
+    })
+}
+
+/// Why a string is not an [`EffectDescriptor`], and where.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DescriptorError {
+    span: Range<usize>,
+    message: String,
+}
+
+impl DescriptorError {
+    fn new(span: Range<usize>, message: impl Into<String>) -> Self {
+        Self {
+            span,
+            message: message.into(),
+        }
+    }
+
+    /// The byte range of the input the error is about, empty at the end of the input.
+    pub fn span(&self) -> Range<usize> {
+        self.span.clone()
+    }
+
+    pub fn message(&self) -> &str {
+        &self.message
+    }
+}
+
+impl fmt::Display for DescriptorError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} at byte {}", self.message, self.span.start)
+    }
+}
+
+impl std::error::Error for DescriptorError {}
+
+impl From<DescriptorError> for Error {
+    fn from(err: DescriptorError) -> Self {
+        Error::Config(format!("invalid effect descriptor: {}", err))
+    }
+}
+
+impl FromStr for EffectDescriptor {
+    type Err = DescriptorError;
+
+    fn from_str(s: &str) -> Result<Self, DescriptorError> {
+        let mut parser = Parser { input: s, pos: 0 };
+        let mut steps = Vec::new();
+        loop {
+            let name = parser.word("an effect")?;
+            if name.text.eq_ignore_ascii_case("none") && steps.is_empty() {
+                parser.skip_whitespace();
+                if parser.pos == s.len() {
+                    return Ok(Self::default());
+                }
+            }
+            let arguments = parser.arguments()?;
+            steps.push(step(name, arguments)?);
+
+            match parser.peek() {
+                Some('|') => parser.pos += 1,
+                None => return Ok(Self { steps }),
+                Some(_) => return Err(parser.unexpected("`|` or the end")),
+            }
+        }
+    }
+}
+
+/// A run of characters without whitespace nor punctuation, and where it is in the input.
+#[derive(Clone, Copy)]
+struct Word<'a> {
+    text: &'a str,
+    start: usize,
+}
+
+impl Word<'_> {
+    fn span(&self) -> Range<usize> {
+        self.start..self.start + self.text.len()
+    }
+
+    fn error(&self, message: impl Into<String>) -> DescriptorError {
+        DescriptorError::new(self.span(), message)
+    }
+}
+
+struct Argument<'a> {
+    key: Option<Word<'a>>,
+    value: Word<'a>,
+}
+
+struct Parser<'a> {
+    input: &'a str,
+    pos: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn rest(&self) -> &'a str {
+        &self.input[self.pos..]
+    }
+
+    fn skip_whitespace(&mut self) {
+        let rest = self.rest();
+        self.pos += rest.len() - rest.trim_start().len();
+    }
+
+    fn peek(&mut self) -> Option<char> {
+        self.skip_whitespace();
+        self.rest().chars().next()
+    }
+
+    fn word(&mut self, expected: &str) -> Result<Word<'a>, DescriptorError> {
+        self.skip_whitespace();
+        let rest = self.rest();
+        let len = rest
+            .find(|c: char| c.is_whitespace() || is_punctuation(c))
+            .unwrap_or(rest.len());
+        if len == 0 {
+            return Err(self.unexpected(expected));
+        }
+        let word = Word {
+            text: &rest[..len],
+            start: self.pos,
+        };
+        self.pos += len;
+        Ok(word)
+    }
+
+    /// The error for the character at the current position.
+    fn unexpected(&mut self, expected: &str) -> DescriptorError {
+        match self.peek() {
+            Some(c) => DescriptorError::new(
+                self.pos..self.pos + c.len_utf8(),
+                format!("expected {}, found `{}`", expected, c),
+            ),
+            None => DescriptorError::new(
+                self.pos..self.pos,
+                format!("expected {}, found the end", expected),
+            ),
+        }
+    }
+
+    /// The parenthesized arguments of a step, if any.
+    fn arguments(&mut self) -> Result<Vec<Argument<'a>>, DescriptorError> {
+        let mut arguments = Vec::new();
+        if self.peek() != Some('(') {
+            return Ok(arguments);
+        }
+        self.pos += 1;
+        loop {
+            if self.peek() == Some(')') {
+                self.pos += 1;
+                return Ok(arguments);
+            }
+            let first = self.word("an argument or `)`")?;
+            let argument = if self.peek() == Some('=') {
+                self.pos += 1;
+                Argument {
+                    key: Some(first),
+                    value: self.word("a value")?,
+                }
+            } else {
+                Argument {
+                    key: None,
+                    value: first,
+                }
+            };
+            arguments.push(argument);
+            match self.peek() {
+                Some(',') => self.pos += 1,
+                Some(')') => {}
+                _ => return Err(self.unexpected("`,` or `)`")),
+            }
+        }
+    }
+}
+
+fn is_punctuation(c: char) -> bool {
+    matches!(c, '(' | ')' | ',' | '|' | '=')
+}
+
+const EFFECTS: &str = "blur, acrylic, mica, tabbed, vibrancy, kde-blur or opacity";
+
+fn step(name: Word<'_>, arguments: Vec<Argument<'_>>) -> Result<EffectStep, DescriptorError> {
+    let effect = name.text.to_ascii_lowercase();
+    let mut arguments = Arguments {
+        name,
+        list: arguments.into_iter().map(Some).collect(),
+    };
+    let step = match effect.as_str() {
+        "blur" => EffectStep::Effect(Effect::Blur(arguments.keyed("tint", parse_color)?)),
+        "acrylic" => EffectStep::Effect(Effect::Acrylic(arguments.keyed("tint", parse_color)?)),
+        "mica" => EffectStep::Effect(Effect::Mica {
+            dark: arguments.positional(parse_theme)?,
+        }),
+        "tabbed" => EffectStep::Effect(Effect::Tabbed {
+            dark: arguments.positional(parse_theme)?,
+        }),
+        "vibrancy" => {
+            let material = match arguments.positional(|value| parse_name(value, "material"))? {
+                Some(material) => material,
+                None => return Err(name.error("\"vibrancy\" needs a material")),
+            };
+            EffectStep::Effect(Effect::Vibrancy {
+                material,
+                state: arguments.positional(|value| parse_name(value, "state"))?,
+                radius: arguments.keyed("radius", parse_number)?,
+            })
+        }
+        "kde-blur" => {
+            let mut contrast = BackgroundContrast::default();
+            for (key, value) in [
+                ("contrast", &mut contrast.contrast),
+                ("intensity", &mut contrast.intensity),
+                ("saturation", &mut contrast.saturation),
+            ] {
+                if let Some(parsed) = arguments.keyed(key, parse_f32)? {
+                    *value = parsed;
+                }
+            }
+            EffectStep::KdeBlur(contrast)
+        }
+        "opacity" => match arguments.positional(parse_opacity)? {
+            Some(opacity) => EffectStep::Opacity(opacity),
+            None => return Err(name.error("\"opacity\" needs a value")),
+        },
+        _ => {
+            return Err(name.error(format!(
+                "unknown effect \"{}\", expected {}",
+                name.text, EFFECTS
+            )))
+        }
+    };
+    arguments.finish()?;
+    Ok(step)
+}
+
+/// The arguments of a step, taken out as the step reads them.
+struct Arguments<'a> {
+    name: Word<'a>,
+    list: Vec<Option<Argument<'a>>>,
+}
+
+impl<'a> Arguments<'a> {
+    /// Takes the first positional argument left.
+    fn positional<T>(
+        &mut self,
+        parse: impl FnOnce(Word<'a>) -> Result<T, DescriptorError>,
+    ) -> Result<Option<T>, DescriptorError> {
+        let slot = self
+            .list
+            .iter_mut()
+            .find(|slot| slot.as_ref().map_or(false, |a| a.key.is_none()));
+        match slot.and_then(Option::take) {
+            Some(argument) => parse(argument.value).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    /// Takes the argument named `key`.
+    fn keyed<T>(
+        &mut self,
+        key: &str,
+        parse: impl Fn(Word<'a>) -> Result<T, DescriptorError>,
+    ) -> Result<Option<T>, DescriptorError> {
+        let mut found = None;
+        for slot in &mut self.list {
+            let matches = slot
+                .as_ref()
+                .and_then(|a| a.key)
+                .map_or(false, |k| k.text.eq_ignore_ascii_case(key));
+            if !matches {
+                continue;
+            }
+            let argument = slot.take().expect("the slot matched");
+            if found.is_some() {
+                let key = argument.key.expect("the argument is keyed");
+                return Err(key.error(format!("duplicate argument \"{}\"", key.text)));
+            }
+            found = Some(parse(argument.value)?);
+        }
+        Ok(found)
+    }
+
+    /// Fails on the first argument the step didn't take.
+    fn finish(self) -> Result<(), DescriptorError> {
+        match self.list.into_iter().flatten().next() {
+            Some(Argument { key: Some(key), .. }) => Err(key.error(format!(
+                "unknown argument \"{}\" for \"{}\"",
+                key.text, self.name.text
+            ))),
+            Some(Argument { value, .. }) => Err(value.error(format!(
+                "unexpected argument \"{}\" for \"{}\"",
+                value.text, self.name.text
+            ))),
+            None => Ok(()),
+        }
+    }
+}
+
+fn parse_number(value: Word<'_>) -> Result<f64, DescriptorError> {
+    match value.text.parse::<f64>() {
+        Ok(number) if number.is_finite() => Ok(number),
+        _ => Err(value.error(format!("expected a number, found \"{}\"", value.text))),
+    }
+}
+
+/// A number that stays finite as an `f32`, for [`BackgroundContrast`].
+///
+/// It is parsed as an `f32` right away, going through an `f64` could round the printed form
+/// of an `f32` to a neighbour.
+fn parse_f32(value: Word<'_>) -> Result<f32, DescriptorError> {
+    parse_number(value)?;
+    match value.text.parse::<f32>() {
+        Ok(number) if number.is_finite() => Ok(number),
+        _ => Err(value.error(format!("the number \"{}\" is too large", value.text))),
+    }
+}
+
+fn parse_opacity(value: Word<'_>) -> Result<f64, DescriptorError> {
+    let opacity = parse_number(value)?;
+    if (0.0..=1.0).contains(&opacity) {
+        Ok(opacity)
+    } else {
+        Err(value.error("the opacity must be between 0 and 1"))
+    }
+}
+
+fn parse_theme(value: Word<'_>) -> Result<bool, DescriptorError> {
+    if value.text.eq_ignore_ascii_case("dark") {
+        Ok(true)
+    } else if value.text.eq_ignore_ascii_case("light") {
+        Ok(false)
+    } else {
+        Err(value.error(format!("expected dark or light, found \"{}\"", value.text)))
+    }
+}
+
+fn parse_name<T: FromStr>(value: Word<'_>, what: &str) -> Result<T, DescriptorError> {
+    value
+        .text
+        .parse()
+        .map_err(|_| value.error(format!("unknown {} \"{}\"", what, value.text)))
+}
+
+fn parse_color(value: Word<'_>) -> Result<Color, DescriptorError> {
+    parse_hex_color(value.text).ok_or_else(|| {
+        value.error(format!(
+            "expected a #rgb, #rgba, #rrggbb or #rrggbbaa color, found \"{}\"",
+            value.text
+        ))
+    })
+}
+
+/// `#rgb`, `#rgba`, `#rrggbb` or `#rrggbbaa`.
+pub(crate) fn parse_hex_color(text: &str) -> Option<Color> {
+    let hex = text.strip_prefix('#')?;
+    if !hex.bytes().all(|b| b.is_ascii_hexdigit()) {
+        return None;
+    }
+    let digits: Vec<u8> = hex
+        .bytes()
+        .map(|b| (b as char).to_digit(16).unwrap_or(0) as u8)
+        .collect();
+    let channels: Vec<u8> = match digits.len() {
+        3 | 4 => digits.iter().map(|d| d * 17).collect(),
+        6 | 8 => digits.chunks(2).map(|d| d[0] * 16 + d[1]).collect(),
+        _ => return None,
+    };
+    Some((
+        channels[0],
+        channels[1],
+        channels[2],
+        channels.get(3).copied().unwrap_or(255),
+    ))
+}
+
+impl fmt::Display for EffectDescriptor {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.steps.is_empty() {
+            return f.write_str("none");
+        }
+        for (i, step) in self.steps.iter().enumerate() {
+            if i > 0 {
+                f.write_str(" | ")?;
+            }
+            write!(f, "{}", step)?;
+        }
+        Ok(())
+    }
+}
+
+/// The canonical form of the step, see [`EffectDescriptor`].
+impl fmt::Display for EffectStep {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut arguments = Vec::new();
+        let name = match *self {
+            EffectStep::Effect(Effect::Blur(tint)) | EffectStep::Effect(Effect::Acrylic(tint)) => {
+                if let Some(tint) = tint {
+                    arguments.push(format!("tint={}", ColorHex(tint)));
+                }
+                if let EffectStep::Effect(Effect::Blur(_)) = self {
+                    "blur"
+                } else {
+                    "acrylic"
+                }
+            }
+            EffectStep::Effect(Effect::Mica { dark })
+            | EffectStep::Effect(Effect::Tabbed { dark }) => {
+                if let Some(dark) = dark {
+                    arguments.push(if dark { "dark" } else { "light" }.to_string());
+                }
+                if let EffectStep::Effect(Effect::Mica { .. }) = self {
+                    "mica"
+                } else {
+                    "tabbed"
+                }
+            }
+            EffectStep::Effect(Effect::Vibrancy {
+                material,
+                state,
+                radius,
+            }) => {
+                arguments.push(material.to_string());
+                if let Some(state) = state {
+                    arguments.push(state.to_string());
+                }
+                if let Some(radius) = radius {
+                    arguments.push(format!("radius={}", radius));
+                }
+                "vibrancy"
+            }
+            EffectStep::KdeBlur(contrast) => {
+                let defaults = BackgroundContrast::default();
+                for (key, value, default) in [
+                    ("contrast", contrast.contrast, defaults.contrast),
+                    ("intensity", contrast.intensity, defaults.intensity),
+                    ("saturation", contrast.saturation, defaults.saturation),
+                ] {
+                    if value != default {
+                        arguments.push(format!("{}={}", key, value));
+                    }
+                }
+                "kde-blur"
+            }
+            EffectStep::Opacity(opacity) => {
+                arguments.push(opacity.to_string());
+                "opacity"
+            }
+        };
+        f.write_str(name)?;
+        if !arguments.is_empty() {
+            write!(f, "({})", arguments.join(", "))?;
+        }
+        Ok(())
+    }
+}
+
+struct ColorHex(Color);
+
+impl fmt::Display for ColorHex {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (r, g, b, a) = self.0;
+        write!(f, "#{:02x}{:02x}{:02x}", r, g, b)?;
+        if a != 255 {
+            write!(f, "{:02x}", a)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{NSVisualEffectMaterial, NSVisualEffectState};
+
+    /// A xorshift generator, so failures can be reproduced.
+    struct Rng(u64);
+
+    impl Rng {
+        fn next(&mut self) -> u64 {
+            self.0 ^= self.0 << 13;
+            self.0 ^= self.0 >> 7;
+            self.0 ^= self.0 << 17;
+            self.0
+        }
+
+        fn below(&mut self, n: usize) -> usize {
+            (self.next() % n as u64) as usize
+        }
+
+        fn maybe<T>(&mut self, value: impl FnOnce(&mut Self) -> T) -> Option<T> {
+            if self.next() & 1 == 0 {
+                Some(value(self))
+            } else {
+                None
+            }
+        }
+
+        fn number(&mut self) -> f64 {
+            match self.below(3) {
+                0 => self.below(100) as f64,
+                1 => self.next() as f64 / u64::MAX as f64 * 1000.0 - 500.0,
+                _ => loop {
+                    let number = f64::from_bits(self.next());
+                    if number.is_finite() {
+                        break number;
+                    }
+                },
+            }
+        }
+
+        fn color(&mut self) -> Color {
+            let [r, g, b, a, ..] = self.next().to_le_bytes();
+            (r, g, b, a)
+        }
+
+        fn step(&mut self) -> EffectStep {
+            match self.below(7) {
+                0 => EffectStep::Effect(Effect::Blur(self.maybe(Rng::color))),
+                1 => EffectStep::Effect(Effect::Acrylic(self.maybe(Rng::color))),
+                2 => EffectStep::Effect(Effect::Mica {
+                    dark: self.maybe(|rng| rng.next() & 1 == 0),
+                }),
+                3 => EffectStep::Effect(Effect::Tabbed {
+                    dark: self.maybe(|rng| rng.next() & 1 == 0),
+                }),
+                4 => EffectStep::Effect(Effect::Vibrancy {
+                    material: NSVisualEffectMaterial::ALL
+                        [self.below(NSVisualEffectMaterial::ALL.len())],
+                    state: self.maybe(|rng| NSVisualEffectState::ALL[rng.below(3)]),
+                    radius: self.maybe(Rng::number),
+                }),
+                5 => {
+                    let mut f32s = || loop {
+                        let number = self.number() as f32;
+                        if number.is_finite() {
+                            break number;
+                        }
+                    };
+                    EffectStep::KdeBlur(BackgroundContrast {
+                        contrast: f32s(),
+                        intensity: f32s(),
+                        saturation: f32s(),
+                    })
+                }
+                _ => EffectStep::Opacity(self.next() as f64 / u64::MAX as f64),
+            }
+        }
+    }
+
+    #[test]
+    fn round_trips() {
+        let mut rng = Rng(0x5eed);
+        for _ in 0..2000 {
+            let descriptor = EffectDescriptor {
+                steps: (0..rng.below(4)).map(|_| rng.step()).collect(),
+            };
+            let printed = descriptor.to_string();
+            assert_eq!(printed.parse(), Ok(descriptor), "{}", printed);
+        }
+    }
+
+    #[test]
+    fn mutations_never_panic() {
+        const PIECES: &[&str] = &[
+            "blur",
+            "acrylic",
+            "mica",
+            "vibrancy",
+            "kde-blur",
+            "opacity",
+            "none",
+            "(",
+            ")",
+            ",",
+            "|",
+            "=",
+            " ",
+            "tint",
+            "#12",
+            "#121212cc",
+            "dark",
+            "hud-window",
+            "radius",
+            "1.5",
+            "-0",
+            "1e39",
+            "nan",
+            "é",
+            "\u{1f600}",
+        ];
+        let mut rng = Rng(42);
+        for _ in 0..5000 {
+            let input: String = (0..rng.below(12))
+                .map(|_| PIECES[rng.below(PIECES.len())])
+                .collect();
+            match input.parse::<EffectDescriptor>() {
+                Ok(descriptor) => {
+                    assert_eq!(descriptor.to_string().parse(), Ok(descriptor), "{}", input)
+                }
+                Err(error) => {
+                    let span = error.span();
+                    assert!(span.start <= span.end && span.end <= input.len());
+                    assert!(input.is_char_boundary(span.start) && input.is_char_boundary(span.end));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn errors() {
+        let error = |input: &str| {
+            let error = input.parse::<EffectDescriptor>().unwrap_err();
+            (error.span(), error.message().to_string())
+        };
+        assert_eq!(
+            error("opacity(2)"),
+            (8..9, "the opacity must be between 0 and 1".into())
+        );
+        assert_eq!(
+            error("blur |"),
+            (6..6, "expected an effect, found the end".into())
+        );
+        assert_eq!(
+            error("kde-blur(contrast=1e39)"),
+            (18..22, "the number \"1e39\" is too large".into())
+        );
+        assert_eq!(
+            error("mica(dark, dark)"),
+            (11..15, "unexpected argument \"dark\" for \"mica\"".into())
+        );
+        assert_eq!(error("none | blur").0, 0..4);
+    }
+
+    #[test]
+    fn prints_opacities_out_of_range_as_given() {
+        // documented: they print, but don't parse back
+        let descriptor = EffectDescriptor {
+            steps: vec![EffectStep::Opacity(2.0)],
+        };
+        assert_eq!(descriptor.to_string(), "opacity(2)");
+        assert!(descriptor.to_string().parse::<EffectDescriptor>().is_err());
+    }
+}
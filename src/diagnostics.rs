@@ -0,0 +1,199 @@
+// Copyright 2019-2022 Tauri Programme within The Commons Conservancy
+// SPDX-License-Identifier: Apache-2.0
+// SPDX-License-Identifier: MIT
+
+//! What the crate sees of the system, to attach to bug reports.
+//!
+//! ```no_run
+//! # let window: &dyn raw_window_handle::HasWindowHandle = unsafe { std::mem::zeroed() };
+//! let report = window_vibrancy::diagnostics::report(&window);
+//! println!("{}", report);
+//! // with the `json` feature
+//! # #[cfg(feature = "json")]
+//! std::fs::write("vibrancy.json", report.to_json()).unwrap();
+//! ```
+
+use std::fmt;
+
+use raw_window_handle::{HasWindowHandle, RawWindowHandle};
+
+/// How the window reaches the screen.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub enum DisplayServer {
+    Win32,
+    AppKit,
+    X11,
+    /// An X11 window of a Wayland session.
+    XWayland,
+    Wayland,
+}
+
+impl DisplayServer {
+    fn as_str(self) -> &'static str {
+        match self {
+            DisplayServer::Win32 => "Win32",
+            DisplayServer::AppKit => "AppKit",
+            DisplayServer::X11 => "X11",
+            DisplayServer::XWayland => "XWayland",
+            DisplayServer::Wayland => "Wayland",
+        }
+    }
+}
+
+/// The environment of a window, see [`report`].
+///
+/// `None` and empty fields are what could not be found out.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct Report {
+    /// The crate version.
+    pub version: &'static str,
+    /// The name and version of the operating system.
+    pub os: Option<String>,
+    /// The kernel version, on Linux.
+    pub kernel: Option<String>,
+    pub display_server: Option<DisplayServer>,
+    /// The compositor or window manager, with its version when it tells.
+    pub compositor: Option<String>,
+    /// The APIs, protocols or X11 atoms behind the effects that are available.
+    pub protocols: Vec<String>,
+    /// The bit depth of the window's visual, 32 is needed for translucency on X11.
+    pub window_depth: Option<u8>,
+    /// Whether the system prefers dark mode.
+    pub dark_mode: Option<bool>,
+    /// Whether a high contrast mode is on.
+    pub high_contrast: Option<bool>,
+    /// Whether the user asked for less transparency, which usually disables the effects.
+    pub reduce_transparency: Option<bool>,
+    /// The functions of this crate that should work on this window.
+    pub effects: Vec<&'static str>,
+    /// What went wrong while gathering the report.
+    pub errors: Vec<String>,
+}
+
+/// Gathers what the crate knows of `window` and its environment.
+///
+/// Never fails, whatever can't be found out is left empty and the reason is added to
+/// [`Report::errors`].
+pub fn report(window: impl HasWindowHandle) -> Report {
+    let mut report = Report {
+        version: env!("CARGO_PKG_VERSION"),
+        ..Default::default()
+    };
+    let handle = match window.window_handle() {
+        Ok(handle) => handle.as_raw(),
+        Err(e) => {
+            report.errors.push(e.to_string());
+            return report;
+        }
+    };
+
+    match handle {
+        #[cfg(target_os = "windows")]
+        RawWindowHandle::Win32(handle) => {
+            crate::windows::diagnose(handle.hwnd.get() as _, &mut report)
+        }
+        #[cfg(target_os = "macos")]
+        RawWindowHandle::AppKit(handle) => {
+            crate::macos::diagnose(handle.ns_view.as_ptr() as _, &mut report)
+        }
+        #[cfg(target_os = "linux")]
+        handle @ (RawWindowHandle::Xlib(_)
+        | RawWindowHandle::Xcb(_)
+        | RawWindowHandle::Wayland(_)) => crate::linux::diagnose(handle, &mut report),
+        _ => report
+            .errors
+            .push("the window handle is not supported on this platform".into()),
+    }
+    report
+}
+
+#[cfg(feature = "json")]
+impl Report {
+    /// The report as a JSON object.
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(self).expect("a report is always valid JSON")
+    }
+}
+
+/// The report as aligned `key: value` lines.
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fn line(f: &mut fmt::Formatter<'_>, key: &str, value: Option<String>) -> fmt::Result {
+            writeln!(f, "{:<20}{}", key, value.as_deref().unwrap_or("unknown"))
+        }
+        fn list(f: &mut fmt::Formatter<'_>, key: &str, values: &[&str]) -> fmt::Result {
+            let values = if values.is_empty() {
+                "none".to_string()
+            } else {
+                values.join(", ")
+            };
+            writeln!(f, "{:<20}{}", key, values)
+        }
+
+        line(f, "window-vibrancy", Some(self.version.to_string()))?;
+        line(f, "os", self.os.clone())?;
+        line(f, "kernel", self.kernel.clone())?;
+        line(
+            f,
+            "display server",
+            self.display_server.map(|d| d.as_str().to_string()),
+        )?;
+        line(f, "compositor", self.compositor.clone())?;
+        let protocols: Vec<&str> = self.protocols.iter().map(String::as_str).collect();
+        list(f, "protocols", &protocols)?;
+        line(f, "window depth", self.window_depth.map(|v| v.to_string()))?;
+        line(f, "dark mode", self.dark_mode.map(|v| v.to_string()))?;
+        line(
+            f,
+            "high contrast",
+            self.high_contrast.map(|v| v.to_string()),
+        )?;
+        line(
+            f,
+            "reduce transparency",
+            self.reduce_transparency.map(|v| v.to_string()),
+        )?;
+        list(f, "effects", &self.effects)?;
+        for error in &self.errors {
+            writeln!(f, "{:<20}{}", "error", error)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample() -> Report {
+        Report {
+            version: "0.5.0",
+            os: Some("Fedora Linux 40 (\"KDE\")".into()),
+            display_server: Some(DisplayServer::XWayland),
+            protocols: vec!["_KDE_NET_WM_BLUR_BEHIND_REGION".into()],
+            window_depth: Some(32),
+            dark_mode: Some(true),
+            effects: vec!["apply_blur", "clear_blur"],
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn text() {
+        let text = sample().to_string();
+        assert!(text.contains("display server      XWayland\n"));
+        assert!(text.contains("kernel              unknown\n"));
+        assert!(text.contains("effects             apply_blur, clear_blur\n"));
+    }
+
+    #[cfg(feature = "json")]
+    #[test]
+    fn json() {
+        assert_eq!(
+            sample().to_json(),
+            r#"{"version":"0.5.0","os":"Fedora Linux 40 (\"KDE\")","kernel":null,"display_server":"XWayland","compositor":null,"protocols":["_KDE_NET_WM_BLUR_BEHIND_REGION"],"window_depth":32,"dark_mode":true,"high_contrast":null,"reduce_transparency":null,"effects":["apply_blur","clear_blur"],"errors":[]}"#
+        );
+    }
+}
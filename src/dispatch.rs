@@ -0,0 +1,298 @@
+// Copyright 2019-2022 Tauri Programme within The Commons Conservancy
+// SPDX-License-Identifier: Apache-2.0
+// SPDX-License-Identifier: MIT
+
+#[cfg(any(feature = "winit", feature = "tao"))]
+use std::thread::{self, ThreadId};
+use std::{
+    fmt,
+    future::Future,
+    pin::Pin,
+    sync::{Arc, Condvar, Mutex},
+    task::{Context, Poll, Waker},
+};
+
+use crate::Error;
+
+/// Runs closures on the thread that owns the windows, the main thread on macOS.
+///
+/// Use it with [`run_on_main_thread`] to call this crate from worker threads without blocking
+/// them. On macOS the effects also move to the main thread by themselves, but the calling
+/// thread waits for it.
+/// With the `winit` or `tao` feature, `EventLoopDispatcher` implements it for their event loops.
+pub trait MainThreadDispatcher: Send + Sync {
+    /// Whether the current thread is the main thread, tasks are then run right away.
+    fn is_main_thread(&self) -> bool;
+
+    /// Queues `task` to be run on the main thread.
+    ///
+    /// Dropping the task instead, e.g. because the event loop exited, fails its [`Dispatched`].
+    fn dispatch(&self, task: MainThreadTask);
+}
+
+/// A closure to be run on the main thread, see [`MainThreadDispatcher`].
+pub struct MainThreadTask(Box<dyn FnOnce() + Send>);
+
+impl MainThreadTask {
+    pub fn new(task: impl FnOnce() + Send + 'static) -> Self {
+        Self(Box::new(task))
+    }
+
+    pub fn run(self) {
+        (self.0)()
+    }
+}
+
+impl fmt::Debug for MainThreadTask {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("MainThreadTask")
+    }
+}
+
+/// Runs `f` on the main thread through `dispatcher`, right away if already on it.
+///
+/// ```no_run
+/// use window_vibrancy::{apply_blur, run_on_main_thread, MainThreadDispatcher};
+///
+/// fn blur_from_worker(
+///     window: std::sync::Arc<winit::window::Window>,
+///     dispatcher: &dyn MainThreadDispatcher,
+/// ) {
+///     let result = run_on_main_thread(dispatcher, move || apply_blur(&window, None));
+///     // block this worker thread until the main thread ran it, or `.await` it
+///     result.wait().unwrap();
+/// }
+/// ```
+pub fn run_on_main_thread<D, F, T>(dispatcher: &D, f: F) -> Dispatched<T>
+where
+    D: MainThreadDispatcher + ?Sized,
+    F: FnOnce() -> Result<T, Error> + Send + 'static,
+    T: Send + 'static,
+{
+    let shared = Arc::new(Shared {
+        state: Mutex::new(State {
+            result: None,
+            waker: None,
+        }),
+        ready: Condvar::new(),
+    });
+    let completer = Completer(Some(shared.clone()));
+
+    if dispatcher.is_main_thread() {
+        completer.complete(f());
+    } else {
+        // if the task is dropped unrun, `Completer::drop` reports it
+        dispatcher.dispatch(MainThreadTask::new(move || completer.complete(f())));
+    }
+
+    Dispatched { shared }
+}
+
+/// The result of a call made with [`run_on_main_thread`].
+///
+/// Wait for it with [`Dispatched::wait`] or `.await` it. It resolves to [`Error::Cancelled`] if
+/// the task was dropped without running, e.g. because the event loop exited.
+#[must_use]
+pub struct Dispatched<T> {
+    shared: Arc<Shared<T>>,
+}
+
+impl<T> Dispatched<T> {
+    /// Blocks until the task ran.
+    ///
+    /// Never call it on the main thread for a task that was queued, it would wait forever.
+    pub fn wait(self) -> Result<T, Error> {
+        let mut state = self.shared.state.lock().unwrap();
+        loop {
+            if let Some(result) = state.result.take() {
+                return result;
+            }
+            state = self.shared.ready.wait(state).unwrap();
+        }
+    }
+
+    /// Takes the result if the task already ran.
+    pub fn try_take(&mut self) -> Option<Result<T, Error>> {
+        self.shared.state.lock().unwrap().result.take()
+    }
+}
+
+impl<T> Future for Dispatched<T> {
+    type Output = Result<T, Error>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let mut state = self.shared.state.lock().unwrap();
+        match state.result.take() {
+            Some(result) => Poll::Ready(result),
+            None => {
+                state.waker = Some(cx.waker().clone());
+                Poll::Pending
+            }
+        }
+    }
+}
+
+impl<T> fmt::Debug for Dispatched<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Dispatched").finish_non_exhaustive()
+    }
+}
+
+struct Shared<T> {
+    state: Mutex<State<T>>,
+    ready: Condvar,
+}
+
+struct State<T> {
+    result: Option<Result<T, Error>>,
+    waker: Option<Waker>,
+}
+
+struct Completer<T>(Option<Arc<Shared<T>>>);
+
+impl<T> Completer<T> {
+    fn complete(mut self, result: Result<T, Error>) {
+        if let Some(shared) = self.0.take() {
+            Self::send(&shared, result);
+        }
+    }
+
+    fn send(shared: &Shared<T>, result: Result<T, Error>) {
+        let waker = {
+            let mut state = shared.state.lock().unwrap();
+            state.result = Some(result);
+            state.waker.take()
+        };
+        shared.ready.notify_all();
+        if let Some(waker) = waker {
+            waker.wake();
+        }
+    }
+}
+
+impl<T> Drop for Completer<T> {
+    fn drop(&mut self) {
+        if let Some(shared) = self.0.take() {
+            Self::send(&shared, Err(Error::Cancelled));
+        }
+    }
+}
+
+/// A [`MainThreadDispatcher`] sending tasks through an event loop proxy.
+///
+/// Create it on the main thread. The event loop's user event must implement
+/// `From<MainThreadTask>`, and the app must [`run`](MainThreadTask::run) the task when it
+/// receives it:
+///
+/// ```ignore
+/// enum UserEvent {
+///     Vibrancy(MainThreadTask),
+/// }
+///
+/// impl From<MainThreadTask> for UserEvent {
+///     fn from(task: MainThreadTask) -> Self {
+///         UserEvent::Vibrancy(task)
+///     }
+/// }
+///
+/// let event_loop = EventLoopBuilder::<UserEvent>::with_user_event().build()?;
+/// let dispatcher = EventLoopDispatcher::new(event_loop.create_proxy());
+/// // in the event handler:
+/// // Event::UserEvent(UserEvent::Vibrancy(task)) => task.run(),
+/// ```
+#[cfg(any(feature = "winit", feature = "tao"))]
+pub struct EventLoopDispatcher<P> {
+    // proxies are `Send` but not always `Sync`
+    proxy: Mutex<P>,
+    main_thread: ThreadId,
+}
+
+#[cfg(any(feature = "winit", feature = "tao"))]
+impl<P> EventLoopDispatcher<P> {
+    /// Wraps `proxy`, the current thread is taken as the main thread.
+    pub fn new(proxy: P) -> Self {
+        Self {
+            proxy: Mutex::new(proxy),
+            main_thread: thread::current().id(),
+        }
+    }
+
+    fn on_main_thread(&self) -> bool {
+        thread::current().id() == self.main_thread
+    }
+}
+
+#[cfg(any(feature = "winit", feature = "tao"))]
+impl<P> fmt::Debug for EventLoopDispatcher<P> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("EventLoopDispatcher")
+            .field("main_thread", &self.main_thread)
+            .finish_non_exhaustive()
+    }
+}
+
+#[cfg(feature = "winit")]
+impl<T> MainThreadDispatcher for EventLoopDispatcher<winit::event_loop::EventLoopProxy<T>>
+where
+    T: From<MainThreadTask> + Send + 'static,
+{
+    fn is_main_thread(&self) -> bool {
+        self.on_main_thread()
+    }
+
+    fn dispatch(&self, task: MainThreadTask) {
+        // a closed event loop hands the event back, dropping it fails the task
+        let _ = self.proxy.lock().unwrap().send_event(T::from(task));
+    }
+}
+
+#[cfg(feature = "tao")]
+impl<T> MainThreadDispatcher for EventLoopDispatcher<tao::event_loop::EventLoopProxy<T>>
+where
+    T: From<MainThreadTask> + Send + 'static,
+{
+    fn is_main_thread(&self) -> bool {
+        self.on_main_thread()
+    }
+
+    fn dispatch(&self, task: MainThreadTask) {
+        // a closed event loop hands the event back, dropping it fails the task
+        let _ = self.proxy.lock().unwrap().send_event(T::from(task));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::thread;
+
+    use super::*;
+
+    /// Runs tasks on a new thread, or drops them.
+    struct Spawner {
+        run: bool,
+    }
+
+    impl MainThreadDispatcher for Spawner {
+        fn is_main_thread(&self) -> bool {
+            false
+        }
+
+        fn dispatch(&self, task: MainThreadTask) {
+            if self.run {
+                thread::spawn(move || task.run());
+            }
+        }
+    }
+
+    #[test]
+    fn runs_the_task() {
+        let result = run_on_main_thread(&Spawner { run: true }, || Ok(42));
+        assert_eq!(result.wait().unwrap(), 42);
+    }
+
+    #[test]
+    fn dropped_task_is_cancelled() {
+        let mut result = run_on_main_thread(&Spawner { run: false }, || Ok(42));
+        assert!(matches!(result.try_take(), Some(Err(Error::Cancelled))));
+    }
+}
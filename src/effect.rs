@@ -0,0 +1,110 @@
+// Copyright 2019-2022 Tauri Programme within The Commons Conservancy
+// SPDX-License-Identifier: Apache-2.0
+// SPDX-License-Identifier: MIT
+
+use raw_window_handle::HasWindowHandle;
+
+use crate::{
+    apply_acrylic, apply_blur, apply_mica, apply_tabbed, apply_vibrancy, clear_acrylic, clear_blur,
+    clear_mica, clear_tabbed, run_on_main_thread, Color, Error, MainThreadDispatcher,
+    NSVisualEffectMaterial, NSVisualEffectState,
+};
+
+/// An effect and its parameters, to pick the effect at runtime.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Effect {
+    /// See [`apply_blur`].
+    Blur(Option<Color>),
+    /// See [`apply_acrylic`].
+    Acrylic(Option<Color>),
+    /// See [`apply_mica`].
+    Mica { dark: Option<bool> },
+    /// See [`apply_tabbed`].
+    Tabbed { dark: Option<bool> },
+    /// See [`apply_vibrancy`].
+    Vibrancy {
+        material: NSVisualEffectMaterial,
+        state: Option<NSVisualEffectState>,
+        radius: Option<f64>,
+    },
+}
+
+/// Applies `effect` to the window, see the matching `apply_*` function.
+pub fn apply_effect(window: impl HasWindowHandle, effect: &Effect) -> Result<(), Error> {
+    match *effect {
+        Effect::Blur(color) => apply_blur(window, color),
+        Effect::Acrylic(color) => apply_acrylic(window, color),
+        Effect::Mica { dark } => apply_mica(window, dark),
+        Effect::Tabbed { dark } => apply_tabbed(window, dark),
+        Effect::Vibrancy {
+            material,
+            state,
+            radius,
+        } => apply_vibrancy(window, material, state, radius).map(|_| ()),
+    }
+}
+
+/// Clears `effect` from the window, see the matching `clear_*` function.
+///
+/// ## Platform-specific
+///
+/// - **macOS**: Vibrancy can't be cleared.
+pub fn clear_effect(window: impl HasWindowHandle, effect: &Effect) -> Result<(), Error> {
+    match *effect {
+        Effect::Blur(_) => clear_blur(window),
+        Effect::Acrylic(_) => clear_acrylic(window),
+        Effect::Mica { .. } => clear_mica(window),
+        Effect::Tabbed { .. } => clear_tabbed(window),
+        // vibrancy is blur on Linux, where it can be cleared
+        #[cfg(target_os = "linux")]
+        Effect::Vibrancy { .. } => clear_blur(window),
+        #[cfg(not(target_os = "linux"))]
+        Effect::Vibrancy { .. } => {
+            let _ = window;
+            Err(Error::UnsupportedPlatform(
+                "clearing vibrancy is only supported on Linux.",
+            ))
+        }
+    }
+}
+
+/// Applies `effect` on the main thread through `dispatcher`, from any thread or async task.
+///
+/// Resolves once the platform call returned. On Linux that is once the X server applied the
+/// window properties, the Wayland compositor processed the surface commit, or sway replied to
+/// the IPC command. It doesn't depend on any async runtime.
+///
+/// ```no_run
+/// use std::sync::Arc;
+/// use window_vibrancy::{apply_effect_async, Effect, MainThreadDispatcher};
+///
+/// async fn on_theme_changed(window: Arc<winit::window::Window>, dispatcher: Arc<dyn MainThreadDispatcher>) {
+///     apply_effect_async(&*dispatcher, window, Effect::Mica { dark: Some(true) })
+///         .await
+///         .unwrap();
+/// }
+/// ```
+pub async fn apply_effect_async<D, W>(
+    dispatcher: &D,
+    window: W,
+    effect: Effect,
+) -> Result<(), Error>
+where
+    D: MainThreadDispatcher + ?Sized,
+    W: HasWindowHandle + Send + 'static,
+{
+    run_on_main_thread(dispatcher, move || apply_effect(&window, &effect)).await
+}
+
+/// Clears `effect` on the main thread through `dispatcher`, see [`apply_effect_async`].
+pub async fn clear_effect_async<D, W>(
+    dispatcher: &D,
+    window: W,
+    effect: Effect,
+) -> Result<(), Error>
+where
+    D: MainThreadDispatcher + ?Sized,
+    W: HasWindowHandle + Send + 'static,
+{
+    run_on_main_thread(dispatcher, move || clear_effect(&window, &effect)).await
+}
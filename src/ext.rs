@@ -0,0 +1,97 @@
+// Copyright 2019-2022 Tauri Programme within The Commons Conservancy
+// SPDX-License-Identifier: Apache-2.0
+// SPDX-License-Identifier: MIT
+
+#![cfg(any(feature = "winit", feature = "tao"))]
+
+use raw_window_handle::HasWindowHandle;
+
+use crate::{Effect, Error, Preset, VibrancyController, WindowState};
+
+/// The effects as methods of winit and tao windows, with the `winit` or `tao` feature.
+///
+/// [`vibrancy_controller`](Self::vibrancy_controller) gives a controller that follows the
+/// window once its events are forwarded:
+///
+/// ```no_run
+/// use window_vibrancy::{Effect, FocusPolicy, InactiveEffect, WindowVibrancyExt};
+/// use winit::{event::Event, event_loop::EventLoop, window::WindowBuilder};
+///
+/// let event_loop = EventLoop::new().unwrap();
+/// let window = WindowBuilder::new()
+///     .with_transparent(true)
+///     .build(&event_loop)
+///     .unwrap();
+///
+/// let mut controller = window.vibrancy_controller();
+/// controller
+///     .set_focus_policy(FocusPolicy {
+///         inactive_effect: InactiveEffect::Same,
+///         inactive_tint: Some((32, 32, 32, 230)),
+///     })
+///     .unwrap();
+/// controller
+///     .set_effect(Some(Effect::Mica { dark: None }))
+///     .unwrap();
+///
+/// event_loop
+///     .run(move |event, _| {
+///         if let Event::WindowEvent { event, .. } = event {
+///             controller.on_winit_event(&event).unwrap();
+///         }
+///     })
+///     .unwrap();
+/// ```
+pub trait WindowVibrancyExt: HasWindowHandle {
+    /// See [`apply_effect`](crate::apply_effect).
+    fn apply_effect(&self, effect: &Effect) -> Result<(), Error> {
+        crate::apply_effect(self, effect)
+    }
+
+    /// See [`clear_effect`](crate::clear_effect).
+    fn clear_effect(&self, effect: &Effect) -> Result<(), Error> {
+        crate::clear_effect(self, effect)
+    }
+
+    /// See [`apply_preset`](crate::apply_preset).
+    fn apply_preset(&self, preset: Preset) -> Result<(), Error> {
+        crate::apply_preset(self, preset)
+    }
+
+    /// A controller for the window, which already knows its size, scale factor, focus and
+    /// whether it is maximized or fullscreen. Forward the window's events to
+    /// `on_winit_event` or `on_tao_event` to keep it up to date.
+    fn vibrancy_controller(&self) -> VibrancyController<&Self>;
+}
+
+#[cfg(feature = "winit")]
+impl WindowVibrancyExt for winit::window::Window {
+    fn vibrancy_controller(&self) -> VibrancyController<&Self> {
+        let size = self.inner_size();
+        VibrancyController::with_window_state(
+            self,
+            (size.width, size.height, self.scale_factor()),
+            self.has_focus(),
+            WindowState {
+                maximized: self.is_maximized(),
+                fullscreen: self.fullscreen().is_some(),
+            },
+        )
+    }
+}
+
+#[cfg(feature = "tao")]
+impl WindowVibrancyExt for tao::window::Window {
+    fn vibrancy_controller(&self) -> VibrancyController<&Self> {
+        let size = self.inner_size();
+        VibrancyController::with_window_state(
+            self,
+            (size.width, size.height, self.scale_factor()),
+            self.is_focused(),
+            WindowState {
+                maximized: self.is_maximized(),
+                fullscreen: self.fullscreen().is_some(),
+            },
+        )
+    }
+}
@@ -6,7 +6,9 @@
 //!
 //! ## Platform-specific
 //!
-//! - **Linux**: Unsupported, Blur and any vibrancy effects are controlled by the compositor installed on the end-user system.
+//! - **Linux**: Only [SwayFX](https://github.com/WillPower3309/swayfx), through the sway IPC socket, and KDE Plasma are supported.
+//!   On SwayFX, effects applied to a native Wayland window reach every Wayland window of the process.
+//!   Otherwise, blur and any vibrancy effects are controlled by the compositor installed on the end-user system.
 //!
 //! # Example
 //!
@@ -23,10 +25,51 @@
 
 #![allow(clippy::deprecated_semver)]
 
+mod background_contrast;
+mod contrast;
+mod controller;
+mod descriptor;
+pub mod diagnostics;
+mod dispatch;
+mod effect;
+mod ext;
+mod linux;
 mod macos;
+mod move_size;
+mod policy;
+mod preset;
+mod region;
+pub mod software;
+mod trace;
+mod user_config;
 mod windows;
 
-pub use macos::{NSVisualEffectMaterial, NSVisualEffectState};
+pub use background_contrast::BackgroundContrast;
+pub use contrast::{
+    contrast_ratio, recommend_tint, relative_luminance, worst_case_contrast, WCAG_AA, WCAG_AAA,
+};
+pub use controller::VibrancyController;
+pub use descriptor::{DescriptorError, EffectDescriptor, EffectStep};
+#[cfg(any(feature = "winit", feature = "tao"))]
+pub use dispatch::EventLoopDispatcher;
+pub use dispatch::{run_on_main_thread, Dispatched, MainThreadDispatcher, MainThreadTask};
+pub use effect::{apply_effect, apply_effect_async, clear_effect, clear_effect_async, Effect};
+#[cfg(any(feature = "winit", feature = "tao"))]
+pub use ext::WindowVibrancyExt;
+#[cfg(target_os = "linux")]
+pub use linux::{current_wallpaper, ForeignWindow, WallpaperLocator, WindowMatch};
+#[cfg(all(target_os = "linux", feature = "rules"))]
+pub use linux::{Rule, RuleEffects, RulesEngine, WindowProperties};
+pub use macos::{resolve_material, Fallback, NSVisualEffectMaterial, NSVisualEffectState};
+pub use move_size::{MoveSizeCommand, MoveSizeMachine, MoveSizePolicy};
+pub use policy::{
+    EffectCommand, EffectMachine, FocusPolicy, InactiveEffect, StateEffect, WindowState,
+    WindowStatePolicy,
+};
+pub use preset::{apply_preset, clear_preset, LinuxPreset, Preset, PresetEffects, PresetMapping};
+pub use region::{opaque_region, AnchoredRect, Length, Rect, RegionLayout};
+#[cfg(feature = "user-config")]
+pub use user_config::{EffectLevel, UserConfig};
 
 /// a tuple of RGBA colors. Each value has minimum of 0 and maximum of 255.
 pub type Color = (u8, u8, u8, u8);
@@ -38,42 +81,105 @@ pub type Color = (u8, u8, u8, u8);
 /// This method has poor performance on Windows 11 build 22621,
 /// the window will lag when resizing or dragging.
 /// It is an issue in the undocumented api used for this method
-/// and microsoft needs to fix it (they probably won't).
+/// and microsoft needs to fix it (they probably won't). See
+/// [`VibrancyController::set_move_size_policy`] to swap it for a tint meanwhile.
 ///
 /// ## Platform-specific
 ///
 /// - **Windows**: *`color`* is ignored on Windows 7 and has no effect.
-/// - **Linux / macOS**: Unsupported.
+/// - **Linux**: Only supported on SwayFX and KDE Plasma. *`color`* is ignored.
+/// - **macOS**: Unsupported.
 pub fn apply_blur(
     window: impl raw_window_handle::HasWindowHandle,
     #[allow(unused)] color: Option<Color>,
 ) -> Result<(), Error> {
-    match window.window_handle()?.as_raw() {
+    if !user_config::permits("apply_blur", user_config::EffectLevel::Blur) {
+        return Ok(());
+    }
+    #[allow(unused)]
+    let color = user_config::tint(color);
+    let handle = window.window_handle()?.as_raw();
+    trace::instrument("apply_blur", handle, || match handle {
         #[cfg(target_os = "windows")]
         raw_window_handle::RawWindowHandle::Win32(handle) => {
             windows::apply_blur(handle.hwnd.get() as _, color)
         }
+        #[cfg(target_os = "linux")]
+        handle @ (raw_window_handle::RawWindowHandle::Xlib(_)
+        | raw_window_handle::RawWindowHandle::Xcb(_)
+        | raw_window_handle::RawWindowHandle::Wayland(_)) => linux::apply_blur(handle, None),
         _ => Err(Error::UnsupportedPlatform(
-            "\"apply_blur()\" is only supported on Windows.",
+            "\"apply_blur()\" is only supported on Windows and Linux.",
         )),
+    })
+}
+
+/// Applies blur effect to parts of the window only.
+///
+/// *`rects`* are in physical pixels, relative to the top-left corner of the window's client area.
+/// Calling this again replaces the previous region, an empty *`rects`* removes the blur.
+///
+/// ## Platform-specific
+///
+/// - **Windows**: Works only on Windows 7. *`tint`* is ignored.
+/// - **Linux**: Works only on KDE Plasma. *`tint`* is ignored and rounded corners are approximated.
+///   The rest of the window is declared opaque through `_NET_WM_OPAQUE_REGION` or
+///   `wl_surface.set_opaque_region`, so draw it opaque. Clearing the blur gives an X11 window back
+///   the opaque region it had before, and removes the one of a Wayland surface.
+/// - **macOS**: Each rect is covered by its own `NSVisualEffectView`, tinted with *`tint`*.
+pub fn apply_blur_region(
+    window: impl raw_window_handle::HasWindowHandle,
+    #[allow(unused)] rects: &[Rect],
+    #[allow(unused)] tint: Option<Color>,
+) -> Result<(), Error> {
+    if !user_config::permits("apply_blur_region", user_config::EffectLevel::Blur) {
+        return Ok(());
     }
+    #[allow(unused)]
+    let tint = user_config::tint(tint);
+    let handle = window.window_handle()?.as_raw();
+    trace::instrument("apply_blur_region", handle, || match handle {
+        #[cfg(target_os = "windows")]
+        raw_window_handle::RawWindowHandle::Win32(handle) => {
+            windows::apply_blur_region(handle.hwnd.get() as _, rects)
+        }
+        #[cfg(target_os = "macos")]
+        raw_window_handle::RawWindowHandle::AppKit(handle) => {
+            macos::apply_blur_region(handle.ns_view.as_ptr() as _, rects, tint)
+        }
+        #[cfg(target_os = "linux")]
+        handle @ (raw_window_handle::RawWindowHandle::Xlib(_)
+        | raw_window_handle::RawWindowHandle::Xcb(_)
+        | raw_window_handle::RawWindowHandle::Wayland(_)) => {
+            linux::apply_blur_region(handle, rects)
+        }
+        _ => Err(Error::UnsupportedPlatform(
+            "\"apply_blur_region()\" is only supported on Windows, macOS and Linux.",
+        )),
+    })
 }
 
 /// Clears blur effect applied to window. Works only on Windows 7, Windows 10 v1809 or newer.
 ///
 /// ## Platform-specific
 ///
-/// - **Linux / macOS**: Unsupported.
+/// - **Linux**: Only supported on SwayFX and KDE Plasma.
+/// - **macOS**: Unsupported.
 pub fn clear_blur(window: impl raw_window_handle::HasWindowHandle) -> Result<(), Error> {
-    match window.window_handle()?.as_raw() {
+    let handle = window.window_handle()?.as_raw();
+    trace::instrument("clear_blur", handle, || match handle {
         #[cfg(target_os = "windows")]
         raw_window_handle::RawWindowHandle::Win32(handle) => {
             windows::clear_blur(handle.hwnd.get() as _)
         }
+        #[cfg(target_os = "linux")]
+        handle @ (raw_window_handle::RawWindowHandle::Xlib(_)
+        | raw_window_handle::RawWindowHandle::Xcb(_)
+        | raw_window_handle::RawWindowHandle::Wayland(_)) => linux::clear_blur(handle),
         _ => Err(Error::UnsupportedPlatform(
-            "\"clear_blur()\" is only supported on Windows.",
+            "\"clear_blur()\" is only supported on Windows and Linux.",
         )),
-    }
+    })
 }
 
 /// Applies acrylic effect to window. Works only on Windows 10 v1809 or newer.
@@ -83,7 +189,8 @@ pub fn clear_blur(window: impl raw_window_handle::HasWindowHandle) -> Result<(),
 /// This method has poor performance on Windows 10 v1903+ and Windows 11 build 22000,
 /// the window will lag when resizing or dragging.
 /// It is an issue in the undocumented api used for this method
-/// and microsoft needs to fix it (they probably won't).
+/// and microsoft needs to fix it (they probably won't). See
+/// [`VibrancyController::set_move_size_policy`] to swap it for a tint meanwhile.
 ///
 /// ## Platform-specific
 ///
@@ -93,7 +200,13 @@ pub fn apply_acrylic(
     window: impl raw_window_handle::HasWindowHandle,
     #[allow(unused)] color: Option<Color>,
 ) -> Result<(), Error> {
-    match window.window_handle()?.as_raw() {
+    if !user_config::permits("apply_acrylic", user_config::EffectLevel::All) {
+        return Ok(());
+    }
+    #[allow(unused)]
+    let color = user_config::tint(color);
+    let handle = window.window_handle()?.as_raw();
+    trace::instrument("apply_acrylic", handle, || match handle {
         #[cfg(target_os = "windows")]
         raw_window_handle::RawWindowHandle::Win32(handle) => {
             windows::apply_acrylic(handle.hwnd.get() as _, color)
@@ -101,7 +214,7 @@ pub fn apply_acrylic(
         _ => Err(Error::UnsupportedPlatform(
             "\"apply_acrylic()\" is only supported on Windows.",
         )),
-    }
+    })
 }
 
 /// Clears acrylic effect applied to window. Works only on Windows 10 v1809 or newer.
@@ -110,7 +223,8 @@ pub fn apply_acrylic(
 ///
 /// - **Linux / macOS**: Unsupported.
 pub fn clear_acrylic(window: impl raw_window_handle::HasWindowHandle) -> Result<(), Error> {
-    match window.window_handle()?.as_raw() {
+    let handle = window.window_handle()?.as_raw();
+    trace::instrument("clear_acrylic", handle, || match handle {
         #[cfg(target_os = "windows")]
         raw_window_handle::RawWindowHandle::Win32(handle) => {
             windows::clear_acrylic(handle.hwnd.get() as _)
@@ -118,7 +232,7 @@ pub fn clear_acrylic(window: impl raw_window_handle::HasWindowHandle) -> Result<
         _ => Err(Error::UnsupportedPlatform(
             "\"clear_acrylic()\" is only supported on Windows.",
         )),
-    }
+    })
 }
 
 /// Applies mica effect to window. Works only on Windows 11.
@@ -134,9 +248,13 @@ pub fn apply_mica(
     window: impl raw_window_handle::HasWindowHandle,
     dark: Option<bool>,
 ) -> Result<(), Error> {
+    if !user_config::permits("apply_mica", user_config::EffectLevel::All) {
+        return Ok(());
+    }
     #[cfg(not(target_os = "windows"))]
     let _ = dark;
-    match window.window_handle()?.as_raw() {
+    let handle = window.window_handle()?.as_raw();
+    trace::instrument("apply_mica", handle, || match handle {
         #[cfg(target_os = "windows")]
         raw_window_handle::RawWindowHandle::Win32(handle) => {
             windows::apply_mica(handle.hwnd.get() as _, dark)
@@ -144,7 +262,7 @@ pub fn apply_mica(
         _ => Err(Error::UnsupportedPlatform(
             "\"apply_mica()\" is only supported on Windows.",
         )),
-    }
+    })
 }
 
 /// Clears mica effect applied to window. Works only on Windows 11.
@@ -153,7 +271,8 @@ pub fn apply_mica(
 ///
 /// - **Linux / macOS**: Unsupported.
 pub fn clear_mica(window: impl raw_window_handle::HasWindowHandle) -> Result<(), Error> {
-    match window.window_handle()?.as_raw() {
+    let handle = window.window_handle()?.as_raw();
+    trace::instrument("clear_mica", handle, || match handle {
         #[cfg(target_os = "windows")]
         raw_window_handle::RawWindowHandle::Win32(handle) => {
             windows::clear_mica(handle.hwnd.get() as _)
@@ -161,7 +280,7 @@ pub fn clear_mica(window: impl raw_window_handle::HasWindowHandle) -> Result<(),
         _ => Err(Error::UnsupportedPlatform(
             "\"clear_mica()\" is only supported on Windows.",
         )),
-    }
+    })
 }
 
 /// Applies mica tabbed effect to window. Works only on Windows 11.
@@ -177,9 +296,13 @@ pub fn apply_tabbed(
     window: impl raw_window_handle::HasWindowHandle,
     dark: Option<bool>,
 ) -> Result<(), Error> {
+    if !user_config::permits("apply_tabbed", user_config::EffectLevel::All) {
+        return Ok(());
+    }
     #[cfg(not(target_os = "windows"))]
     let _ = dark;
-    match window.window_handle()?.as_raw() {
+    let handle = window.window_handle()?.as_raw();
+    trace::instrument("apply_tabbed", handle, || match handle {
         #[cfg(target_os = "windows")]
         raw_window_handle::RawWindowHandle::Win32(handle) => {
             windows::apply_tabbed(handle.hwnd.get() as _, dark)
@@ -187,7 +310,7 @@ pub fn apply_tabbed(
         _ => Err(Error::UnsupportedPlatform(
             "\"apply_tabbed()\" is only supported on Windows.",
         )),
-    }
+    })
 }
 
 /// Clears mica tabbed effect applied to window. Works only on Windows 11.
@@ -196,7 +319,8 @@ pub fn apply_tabbed(
 ///
 /// - **Linux / macOS**: Unsupported.
 pub fn clear_tabbed(window: impl raw_window_handle::HasWindowHandle) -> Result<(), Error> {
-    match window.window_handle()?.as_raw() {
+    let handle = window.window_handle()?.as_raw();
+    trace::instrument("clear_tabbed", handle, || match handle {
         #[cfg(target_os = "windows")]
         raw_window_handle::RawWindowHandle::Win32(handle) => {
             windows::clear_tabbed(handle.hwnd.get() as _)
@@ -204,29 +328,152 @@ pub fn clear_tabbed(window: impl raw_window_handle::HasWindowHandle) -> Result<(
         _ => Err(Error::UnsupportedPlatform(
             "\"clear_tabbed()\" is only supported on Windows.",
         )),
-    }
+    })
 }
 
 /// Applies macos vibrancy effect to window. Works only on macOS 10.10 or newer.
 ///
+/// Materials newer than the running macOS are replaced, see [`resolve_material`].
+/// The returned [`Fallback`] tells whether that happened.
+///
 /// ## Platform-specific
 ///
-/// - **Linux / Windows**: Unsupported.
+/// - **Linux**: Only supported on SwayFX and KDE Plasma, where it enables blur. On SwayFX, *`radius`*
+///   is used as the container's corner radius. *`effect`* and *`state`* are ignored, and [`Fallback::None`]
+///   is returned.
+/// - **Windows**: Unsupported.
 pub fn apply_vibrancy(
     window: impl raw_window_handle::HasWindowHandle,
     #[allow(unused)] effect: NSVisualEffectMaterial,
     #[allow(unused)] state: Option<NSVisualEffectState>,
     #[allow(unused)] radius: Option<f64>,
-) -> Result<(), Error> {
-    match window.window_handle()?.as_raw() {
+) -> Result<Fallback, Error> {
+    if !user_config::permits("apply_vibrancy", user_config::EffectLevel::Blur) {
+        return Ok(Fallback::None);
+    }
+    let handle = window.window_handle()?.as_raw();
+    trace::instrument("apply_vibrancy", handle, || match handle {
         #[cfg(target_os = "macos")]
         raw_window_handle::RawWindowHandle::AppKit(handle) => {
             macos::apply_vibrancy(handle.ns_view.as_ptr() as _, effect, state, radius)
         }
+        #[cfg(target_os = "linux")]
+        handle @ (raw_window_handle::RawWindowHandle::Xlib(_)
+        | raw_window_handle::RawWindowHandle::Xcb(_)
+        | raw_window_handle::RawWindowHandle::Wayland(_)) => {
+            linux::apply_vibrancy(handle, radius).map(|()| Fallback::None)
+        }
         _ => Err(Error::UnsupportedPlatform(
-            "\"apply_vibrancy()\" is only supported on macOS.",
+            "\"apply_vibrancy()\" is only supported on macOS and Linux.",
         )),
+    })
+}
+
+/// Tells the window manager whether to draw the window decorations dark or light,
+/// independently of any effect applied to the window.
+///
+/// ## Arguments
+///
+/// - `dark`: If `None` is provide, it will match the system preference
+///
+/// ## Platform-specific
+///
+/// - **Windows**: Works only on Windows 10 v1809 or newer.
+/// - **Linux**: Works only on X11, through `_GTK_THEME_VARIANT` and KDE's `_KDE_NET_WM_COLOR_SCHEME`.
+/// - **macOS**: Works only on macOS 10.14 or newer, sets the appearance of the whole window.
+pub fn set_dark_mode(
+    window: impl raw_window_handle::HasWindowHandle,
+    dark: Option<bool>,
+) -> Result<(), Error> {
+    #[cfg(not(any(target_os = "windows", target_os = "linux", target_os = "macos")))]
+    let _ = dark;
+    let handle = window.window_handle()?.as_raw();
+    trace::instrument("set_dark_mode", handle, || match handle {
+        #[cfg(target_os = "windows")]
+        raw_window_handle::RawWindowHandle::Win32(handle) => {
+            windows::set_dark_mode(handle.hwnd.get() as _, dark)
+        }
+        #[cfg(target_os = "macos")]
+        raw_window_handle::RawWindowHandle::AppKit(handle) => {
+            macos::set_dark_mode(handle.ns_view.as_ptr() as _, dark)
+        }
+        #[cfg(target_os = "linux")]
+        handle @ (raw_window_handle::RawWindowHandle::Xlib(_)
+        | raw_window_handle::RawWindowHandle::Xcb(_)
+        | raw_window_handle::RawWindowHandle::Wayland(_)) => linux::set_dark_mode(handle, dark),
+        _ => Err(Error::UnsupportedPlatform(
+            "\"set_dark_mode()\" is only supported on Windows, macOS and Linux.",
+        )),
+    })
+}
+
+/// Sets the opacity of the whole window, its content included, between `0.0` and `1.0`.
+/// `None` makes it opaque again.
+///
+/// ## Platform-specific
+///
+/// - **Windows**: Makes the window layered, `None` gives it back the layered style and
+///   attributes it had before.
+/// - **Linux**: Works only on X11 with a compositing window manager, through `_NET_WM_WINDOW_OPACITY`.
+/// - **macOS**: Sets the `alphaValue` of the window.
+pub(crate) fn set_opacity(
+    window: impl raw_window_handle::HasWindowHandle,
+    opacity: Option<f64>,
+) -> Result<(), Error> {
+    if opacity.is_some() && !user_config::permits("set_opacity", user_config::EffectLevel::Opacity)
+    {
+        return Ok(());
     }
+    #[cfg(not(any(target_os = "windows", target_os = "linux", target_os = "macos")))]
+    let _ = opacity;
+    let handle = window.window_handle()?.as_raw();
+    trace::instrument("set_opacity", handle, || match handle {
+        #[cfg(target_os = "windows")]
+        raw_window_handle::RawWindowHandle::Win32(handle) => {
+            windows::set_opacity(handle.hwnd.get() as _, opacity)
+        }
+        #[cfg(target_os = "macos")]
+        raw_window_handle::RawWindowHandle::AppKit(handle) => {
+            macos::set_opacity(handle.ns_view.as_ptr() as _, opacity)
+        }
+        #[cfg(target_os = "linux")]
+        handle @ (raw_window_handle::RawWindowHandle::Xlib(_)
+        | raw_window_handle::RawWindowHandle::Xcb(_)
+        | raw_window_handle::RawWindowHandle::Wayland(_)) => linux::set_opacity(handle, opacity),
+        _ => Err(Error::UnsupportedPlatform(
+            "\"set_opacity()\" is only supported on Windows, macOS and Linux.",
+        )),
+    })
+}
+
+/// Whether the window is maximized or fullscreen.
+///
+/// ## Platform-specific
+///
+/// - **Windows**: A window is fullscreen when it covers its whole monitor.
+/// - **Linux**: Only supported on X11, through the `_NET_WM_STATE_MAXIMIZED_VERT`,
+///   `_NET_WM_STATE_MAXIMIZED_HORZ` and `_NET_WM_STATE_FULLSCREEN` atoms of `_NET_WM_STATE`.
+///   A window maximized in one direction only is not maximized.
+/// - **macOS**: `maximized` is `isZoomed`, `fullscreen` the full screen style mask.
+pub fn window_state(window: impl raw_window_handle::HasWindowHandle) -> Result<WindowState, Error> {
+    let handle = window.window_handle()?.as_raw();
+    trace::instrument("window_state", handle, || match handle {
+        #[cfg(target_os = "windows")]
+        raw_window_handle::RawWindowHandle::Win32(handle) => {
+            windows::window_state(handle.hwnd.get() as _)
+        }
+        #[cfg(target_os = "macos")]
+        raw_window_handle::RawWindowHandle::AppKit(handle) => {
+            macos::window_state(handle.ns_view.as_ptr() as _)
+        }
+        #[cfg(target_os = "linux")]
+        handle @ (raw_window_handle::RawWindowHandle::Xlib(_)
+        | raw_window_handle::RawWindowHandle::Xcb(_)
+        | raw_window_handle::RawWindowHandle::Wayland(_)) => linux::window_state(handle),
+        _ => Err(Error::UnsupportedPlatform(
+            "\"window_state()\" is only supported on Windows, macOS and Linux.",
+        )),
+    })
 }
 
 #[derive(Debug)]
@@ -235,6 +482,15 @@ pub enum Error {
     UnsupportedPlatformVersion(&'static str),
     NotMainThread(&'static str),
     NoWindowHandle(raw_window_handle::HandleError),
+    /// The compositor refused or failed to apply the effect.
+    Compositor(String),
+    /// Communicating with the compositor failed.
+    Io(std::io::Error),
+    /// A configuration file or string is invalid.
+    Config(String),
+    /// A task queued on the main thread was dropped before it could run, e.g. because the
+    /// event loop exited.
+    Cancelled,
 }
 
 impl std::fmt::Display for Error {
@@ -248,6 +504,18 @@ impl std::fmt::Display for Error {
             Error::NoWindowHandle(e) => {
                 write!(f, "{}", e)
             }
+            Error::Compositor(e) | Error::Config(e) => {
+                write!(f, "{}", e)
+            }
+            Error::Io(e) => {
+                write!(f, "{}", e)
+            }
+            Error::Cancelled => {
+                write!(
+                    f,
+                    "the task was dropped before it could run on the main thread"
+                )
+            }
         }
     }
 }
@@ -259,3 +527,9 @@ impl From<raw_window_handle::HandleError> for Error {
         Error::NoWindowHandle(err)
     }
 }
+
+impl From<std::io::Error> for Error {
+    fn from(err: std::io::Error) -> Self {
+        Error::Io(err)
+    }
+}
@@ -0,0 +1,212 @@
+// Copyright 2019-2022 Tauri Programme within The Commons Conservancy
+// SPDX-License-Identifier: Apache-2.0
+// SPDX-License-Identifier: MIT
+
+// Windows of other X11 clients, for desktop shells and similar tools.
+// Window properties are writable by any client, so the same atoms work on them.
+
+use super::{kde, sway, x11::X11, Target};
+use crate::{BackgroundContrast, Error};
+
+const CLIENT_LIST: &str = "_NET_CLIENT_LIST";
+const WINDOW_OPACITY: &str = "_NET_WM_WINDOW_OPACITY";
+
+/// How to find the windows of other X11 clients, see [`ForeignWindow::find`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum WindowMatch {
+    /// The window with this XID, managed or not.
+    Id(u32),
+    /// Managed windows whose `WM_CLASS` instance or class name is this one.
+    Class(String),
+    /// Managed windows whose `_NET_WM_PID` is this one.
+    Pid(u32),
+    /// Managed windows whose title contains this text.
+    Title(String),
+}
+
+/// A window of another X11 client.
+///
+/// Each call opens its own connection to the X server, like the rest of the crate.
+/// [`RulesEngine`](crate::RulesEngine) reuses its own.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct ForeignWindow(u32);
+
+impl ForeignWindow {
+    pub fn from_id(xid: u32) -> Self {
+        Self(xid)
+    }
+
+    /// Looks up the managed windows listed in the window manager's `_NET_CLIENT_LIST`.
+    pub fn find(query: &WindowMatch) -> Result<Vec<Self>, Error> {
+        if let WindowMatch::Id(xid) = query {
+            return Ok(vec![Self(*xid)]);
+        }
+
+        let x11 = X11::connect()?;
+        let mut windows = Vec::new();
+        for window in x11.get_cardinals(x11.root(), CLIENT_LIST)? {
+            // windows can be destroyed while we look at them
+            if Self::matches(&x11, window, query).unwrap_or(false) {
+                windows.push(Self(window));
+            }
+        }
+        Ok(windows)
+    }
+
+    pub fn id(&self) -> u32 {
+        self.0
+    }
+
+    /// The window's `_NET_WM_NAME`, or `WM_NAME`.
+    pub fn title(&self) -> Result<Option<String>, Error> {
+        Self::title_of(&X11::connect()?, self.0)
+    }
+
+    /// Blurs the whole window, on SwayFX (XWayland windows) and KDE Plasma.
+    pub fn apply_blur(&self) -> Result<(), Error> {
+        self.set_blur(&X11::connect()?, true)
+    }
+
+    pub fn clear_blur(&self) -> Result<(), Error> {
+        self.set_blur(&X11::connect()?, false)
+    }
+
+    /// Sets the opacity the compositor draws the window with, between `0.0` and `1.0`,
+    /// through `_NET_WM_WINDOW_OPACITY`. `None` restores the default.
+    pub fn set_opacity(&self, opacity: Option<f64>) -> Result<(), Error> {
+        self.set_opacity_with(&X11::connect()?, opacity)
+    }
+
+    /// Adjusts the colors of what is seen through the window, on KDE Plasma.
+    /// `None` removes the adjustment.
+    pub fn set_background_contrast(
+        &self,
+        contrast: Option<BackgroundContrast>,
+    ) -> Result<(), Error> {
+        self.set_background_contrast_with(&X11::connect()?, contrast)
+    }
+
+    /// [`apply_blur`](Self::apply_blur) or [`clear_blur`](Self::clear_blur) through an open
+    /// connection, for the rules engine.
+    pub(super) fn set_blur(&self, x11: &X11, enabled: bool) -> Result<(), Error> {
+        if sway::is_available() {
+            let target = Target::X11(self.0);
+            return if enabled {
+                sway::apply_blur(&target, None)
+            } else {
+                sway::clear_blur(&target)
+            };
+        }
+        if kde::is_blur_supported(x11)? {
+            return kde::set_blur(x11, self.0, enabled);
+        }
+        Err(Error::UnsupportedPlatform(
+            "Blurring other windows is only supported on SwayFX and KDE Plasma (X11).",
+        ))
+    }
+
+    pub(super) fn set_opacity_with(&self, x11: &X11, opacity: Option<f64>) -> Result<(), Error> {
+        match opacity {
+            Some(opacity) => {
+                let value = (opacity.clamp(0.0, 1.0) * u32::MAX as f64).round() as u32;
+                x11.set_cardinals(self.0, WINDOW_OPACITY, &[value])
+            }
+            None => x11.delete_property(self.0, WINDOW_OPACITY),
+        }
+    }
+
+    pub(super) fn set_background_contrast_with(
+        &self,
+        x11: &X11,
+        contrast: Option<BackgroundContrast>,
+    ) -> Result<(), Error> {
+        kde::set_background_contrast(x11, self.0, contrast.map(|c| c.color_matrix()))
+    }
+
+    fn matches(x11: &X11, window: u32, query: &WindowMatch) -> Result<bool, Error> {
+        Ok(match query {
+            WindowMatch::Id(xid) => window == *xid,
+            // `WM_CLASS` holds the instance and class names, each followed by a NUL
+            WindowMatch::Class(class) => x11
+                .get_string(window, "WM_CLASS")?
+                .map_or(false, |names| names.split('\0').any(|name| name == class)),
+            WindowMatch::Pid(pid) => x11.get_cardinals(window, "_NET_WM_PID")?.first() == Some(pid),
+            WindowMatch::Title(title) => {
+                Self::title_of(x11, window)?.map_or(false, |name| name.contains(title.as_str()))
+            }
+        })
+    }
+
+    fn title_of(x11: &X11, window: u32) -> Result<Option<String>, Error> {
+        match x11.get_string(window, "_NET_WM_NAME")? {
+            Some(title) => Ok(Some(title)),
+            None => x11.get_string(window, "WM_NAME"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A name no other test window has.
+    fn unique(name: &str) -> String {
+        format!("window-vibrancy-{}-{}", name, std::process::id())
+    }
+
+    #[test]
+    #[ignore = "needs an X server, see `X11::create_test_window`"]
+    fn finds_windows() {
+        let x11 = X11::connect().unwrap();
+        let class = unique("find");
+        let window = x11.create_test_window(&class, "Find me, please").unwrap();
+        let other = x11.create_test_window(&unique("other"), "Not me").unwrap();
+        let found = |query| ForeignWindow::find(&query).unwrap();
+
+        assert_eq!(found(WindowMatch::Class(class)), [ForeignWindow(window)]);
+        assert_eq!(
+            found(WindowMatch::Title("Find me".into())),
+            [ForeignWindow(window)]
+        );
+        let by_pid = found(WindowMatch::Pid(std::process::id()));
+        assert!(by_pid.contains(&ForeignWindow(window)) && by_pid.contains(&ForeignWindow(other)));
+        assert_eq!(found(WindowMatch::Id(other)), [ForeignWindow(other)]);
+        assert_eq!(
+            ForeignWindow(window).title().unwrap().as_deref(),
+            Some("Find me, please")
+        );
+    }
+
+    #[test]
+    #[ignore = "needs an X server, see `X11::create_test_window`"]
+    fn sets_properties() {
+        let x11 = X11::connect().unwrap();
+        let window = x11.create_test_window(&unique("properties"), "").unwrap();
+        let target = ForeignWindow(window);
+        let property = |name| x11.get_property32(window, name).unwrap();
+
+        target.apply_blur().unwrap();
+        assert_eq!(property("_KDE_NET_WM_BLUR_BEHIND_REGION"), Some(vec![]));
+        // the application knows best what it draws
+        assert_eq!(property("_NET_WM_OPAQUE_REGION"), None);
+        target.clear_blur().unwrap();
+        assert_eq!(property("_KDE_NET_WM_BLUR_BEHIND_REGION"), None);
+
+        target.set_opacity(Some(0.5)).unwrap();
+        assert_eq!(property(WINDOW_OPACITY), Some(vec![0x8000_0000]));
+        target.set_opacity(Some(2.0)).unwrap();
+        assert_eq!(property(WINDOW_OPACITY), Some(vec![u32::MAX]));
+        target.set_opacity(None).unwrap();
+        assert_eq!(property(WINDOW_OPACITY), None);
+
+        target
+            .set_background_contrast(Some(BackgroundContrast::default()))
+            .unwrap();
+        assert_eq!(
+            property("_KDE_NET_WM_BACKGROUND_CONTRAST_REGION").map(|v| v.len()),
+            Some(16)
+        );
+        target.set_background_contrast(None).unwrap();
+        assert_eq!(property("_KDE_NET_WM_BACKGROUND_CONTRAST_REGION"), None);
+    }
+}
@@ -0,0 +1,71 @@
+// Copyright 2019-2022 Tauri Programme within The Commons Conservancy
+// SPDX-License-Identifier: Apache-2.0
+// SPDX-License-Identifier: MIT
+
+// KWin's blur effect on X11, the same properties `KWindowEffects` sets.
+// https://invent.kde.org/plasma/kwin/-/blob/master/src/plugins/blur/blur.cpp
+
+use super::x11::X11;
+use crate::{
+    region::{self, Rect},
+    Error,
+};
+
+const BLUR_REGION: &str = "_KDE_NET_WM_BLUR_BEHIND_REGION";
+const CONTRAST_REGION: &str = "_KDE_NET_WM_BACKGROUND_CONTRAST_REGION";
+
+pub fn is_blur_supported(x11: &X11) -> Result<bool, Error> {
+    x11.root_has_property(BLUR_REGION)
+}
+
+/// Blurs `rects` of the window, or all of it when `rects` is `None`.
+///
+/// The rest of the window is declared opaque.
+pub fn apply_blur(x11: &X11, window: u32, rects: Option<&[Rect]>) -> Result<(), Error> {
+    match rects {
+        // KWin treats an empty region as "the whole window"
+        None => x11.set_cardinals(window, BLUR_REGION, &[])?,
+        Some(rects) => {
+            let (width, height) = x11.window_size(window)?;
+            let cardinals = region::to_x11_cardinals(rects, width, height);
+            if cardinals.is_empty() {
+                return clear_blur(x11, window);
+            }
+            x11.set_cardinals(window, BLUR_REGION, &cardinals)?;
+        }
+    }
+    x11.set_opaque_region(window, rects, None)
+}
+
+pub fn clear_blur(x11: &X11, window: u32) -> Result<(), Error> {
+    x11.delete_property(window, BLUR_REGION)?;
+    x11.set_opaque_region(window, None, None)
+}
+
+/// Blurs or unblurs the whole window, without touching its opaque region,
+/// for windows of other clients which know best what they draw.
+pub fn set_blur(x11: &X11, window: u32, enabled: bool) -> Result<(), Error> {
+    if enabled {
+        x11.set_cardinals(window, BLUR_REGION, &[])
+    } else {
+        x11.delete_property(window, BLUR_REGION)
+    }
+}
+
+/// Applies a background contrast color matrix to the whole window, or removes it.
+///
+/// The property holds the region followed by the 16 floats of the row-major matrix,
+/// an empty region meaning the whole window.
+pub fn set_background_contrast(
+    x11: &X11,
+    window: u32,
+    matrix: Option<[f32; 16]>,
+) -> Result<(), Error> {
+    match matrix {
+        Some(matrix) => {
+            let cardinals: Vec<u32> = matrix.iter().map(|value| value.to_bits()).collect();
+            x11.set_cardinals(window, CONTRAST_REGION, &cardinals)
+        }
+        None => x11.delete_property(window, CONTRAST_REGION),
+    }
+}
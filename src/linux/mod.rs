@@ -0,0 +1,381 @@
+// Copyright 2019-2022 Tauri Programme within The Commons Conservancy
+// SPDX-License-Identifier: Apache-2.0
+// SPDX-License-Identifier: MIT
+
+#![cfg(target_os = "linux")]
+
+mod foreign;
+mod kde;
+mod move_size;
+mod rules;
+mod sway;
+mod theme;
+mod wallpaper;
+mod wayland;
+mod x11;
+
+pub use foreign::{ForeignWindow, WindowMatch};
+pub use move_size::{MoveSizeEvent, MoveSizeTracker};
+#[cfg(feature = "rules")]
+pub use rules::{Rule, RuleEffects, RulesEngine, WindowProperties};
+pub use wallpaper::{current_wallpaper, WallpaperLocator};
+
+use std::{ffi::c_void, ptr::NonNull};
+
+use raw_window_handle::RawWindowHandle;
+
+use crate::{
+    diagnostics::{DisplayServer, Report},
+    preset::LinuxPreset,
+    region::Rect,
+    trace::{trace_debug, trace_warn},
+    Error, WindowState,
+};
+
+/// The window an effect is applied to, as seen by the compositor.
+pub enum Target {
+    /// An X11 window, native or running under XWayland.
+    X11(u32),
+    /// A Wayland surface. Compositors don't expose surface pointers over IPC,
+    /// so for SwayFX the window is identified by the process that owns it, and
+    /// effects reach every Wayland window of the process.
+    Wayland { pid: u32, surface: NonNull<c_void> },
+}
+
+impl Target {
+    pub fn from_raw(handle: RawWindowHandle) -> Result<Self, Error> {
+        match handle {
+            RawWindowHandle::Xlib(handle) => Ok(Target::X11(handle.window as _)),
+            RawWindowHandle::Xcb(handle) => Ok(Target::X11(handle.window.get())),
+            RawWindowHandle::Wayland(handle) => Ok(Target::Wayland {
+                pid: std::process::id(),
+                surface: handle.surface,
+            }),
+            _ => Err(Error::UnsupportedPlatform(
+                "Only X11 and Wayland windows are supported on Linux.",
+            )),
+        }
+    }
+}
+
+/// The compositor-specific mechanism used to reach a window.
+enum Backend {
+    Sway(Target),
+    Kde(Box<x11::X11>, u32),
+    KdeWayland(Box<wayland::Wayland>),
+}
+
+impl Backend {
+    fn detect(handle: RawWindowHandle) -> Result<Option<Self>, Error> {
+        Self::detect_target(Target::from_raw(handle)?)
+    }
+
+    fn detect_target(target: Target) -> Result<Option<Self>, Error> {
+        if sway::is_available() {
+            trace_debug!(
+                "using the sway IPC of {}",
+                sway::Connection::connect()
+                    .and_then(|mut sway| sway.get_version())
+                    .unwrap_or_else(|e| e.to_string())
+            );
+            return Ok(Some(Backend::Sway(target)));
+        }
+        if let Target::X11(window) = target {
+            let x11 = x11::X11::connect()?;
+            if kde::is_blur_supported(&x11)? {
+                trace_debug!(
+                    "using _KDE_NET_WM_BLUR_BEHIND_REGION of {}",
+                    x11.window_manager_name()
+                        .ok()
+                        .flatten()
+                        .unwrap_or_else(|| "an unknown window manager".into())
+                );
+                return Ok(Some(Backend::Kde(Box::new(x11), window)));
+            }
+        }
+        if let Target::Wayland { surface, .. } = target {
+            // the surface comes from the window handle, which the caller keeps alive
+            let wayland = unsafe { wayland::Wayland::from_surface(surface)? };
+            if wayland.is_blur_supported() {
+                trace_debug!("using org_kde_kwin_blur_manager");
+                return Ok(Some(Backend::KdeWayland(Box::new(wayland))));
+            }
+        }
+        trace_warn!(
+            "no supported compositor found for a {} window: $SWAYSOCK is not set and neither \
+             _KDE_NET_WM_BLUR_BEHIND_REGION nor org_kde_kwin_blur_manager is announced",
+            match target {
+                Target::X11(_) => "X11",
+                Target::Wayland { .. } => "Wayland",
+            }
+        );
+        Ok(None)
+    }
+}
+
+pub fn apply_blur(handle: RawWindowHandle, radius: Option<f64>) -> Result<(), Error> {
+    match Backend::detect(handle)? {
+        Some(Backend::Sway(target)) => sway::apply_blur(&target, radius),
+        Some(Backend::Kde(x11, window)) => kde::apply_blur(&x11, window, None),
+        Some(Backend::KdeWayland(mut wayland)) => wayland.apply_blur(None),
+        None => Err(Error::UnsupportedPlatform(
+            "\"apply_blur()\" is only supported on SwayFX and KDE Plasma on Linux.",
+        )),
+    }
+}
+
+pub fn apply_vibrancy(handle: RawWindowHandle, radius: Option<f64>) -> Result<(), Error> {
+    match Backend::detect(handle)? {
+        Some(Backend::Sway(target)) => sway::apply_blur(&target, radius),
+        Some(Backend::Kde(x11, window)) => kde::apply_blur(&x11, window, None),
+        Some(Backend::KdeWayland(mut wayland)) => wayland.apply_blur(None),
+        None => Err(Error::UnsupportedPlatform(
+            "\"apply_vibrancy()\" is only supported on SwayFX and KDE Plasma on Linux.",
+        )),
+    }
+}
+
+pub fn apply_blur_region(handle: RawWindowHandle, rects: &[Rect]) -> Result<(), Error> {
+    match Backend::detect(handle)? {
+        Some(Backend::Kde(x11, window)) => kde::apply_blur(&x11, window, Some(rects)),
+        Some(Backend::KdeWayland(mut wayland)) => wayland.apply_blur(Some(rects)),
+        Some(Backend::Sway(_)) => Err(Error::UnsupportedPlatform(
+            "\"apply_blur_region()\" is not supported on SwayFX, which can only blur whole containers.",
+        )),
+        None => Err(Error::UnsupportedPlatform(
+            "\"apply_blur_region()\" is only supported on KDE Plasma on Linux.",
+        )),
+    }
+}
+
+pub fn clear_blur(handle: RawWindowHandle) -> Result<(), Error> {
+    match Backend::detect(handle)? {
+        Some(Backend::Sway(target)) => sway::clear_blur(&target),
+        Some(Backend::Kde(x11, window)) => kde::clear_blur(&x11, window),
+        Some(Backend::KdeWayland(mut wayland)) => wayland.clear_blur(),
+        None => Err(Error::UnsupportedPlatform(
+            "\"clear_blur()\" is only supported on SwayFX and KDE Plasma on Linux.",
+        )),
+    }
+}
+
+/// Applies the Linux side of a preset: blur with the corner radius on SwayFX,
+/// blur and background contrast on KDE Plasma.
+pub fn apply_preset(handle: RawWindowHandle, preset: &LinuxPreset) -> Result<(), Error> {
+    match Backend::detect(handle)? {
+        Some(Backend::Sway(target)) if preset.blur => sway::apply_blur(&target, preset.radius),
+        Some(Backend::Sway(target)) => sway::clear_blur(&target),
+        Some(Backend::Kde(x11, window)) => {
+            if preset.blur {
+                kde::apply_blur(&x11, window, None)?;
+            } else {
+                kde::clear_blur(&x11, window)?;
+            }
+            kde::set_background_contrast(&x11, window, preset.contrast.map(|c| c.color_matrix()))
+        }
+        Some(Backend::KdeWayland(mut wayland)) => {
+            if preset.blur {
+                wayland.apply_blur(None)?;
+            } else {
+                wayland.clear_blur()?;
+            }
+            wayland.set_background_contrast(preset.contrast)
+        }
+        None => Err(Error::UnsupportedPlatform(
+            "\"apply_preset()\" is only supported on SwayFX and KDE Plasma on Linux.",
+        )),
+    }
+}
+
+pub fn clear_preset(handle: RawWindowHandle) -> Result<(), Error> {
+    match Backend::detect(handle)? {
+        Some(Backend::Sway(target)) => sway::clear_blur(&target),
+        Some(Backend::Kde(x11, window)) => {
+            kde::clear_blur(&x11, window)?;
+            kde::set_background_contrast(&x11, window, None)
+        }
+        Some(Backend::KdeWayland(mut wayland)) => {
+            wayland.clear_blur()?;
+            wayland.set_background_contrast(None)
+        }
+        None => Err(Error::UnsupportedPlatform(
+            "\"clear_preset()\" is only supported on SwayFX and KDE Plasma on Linux.",
+        )),
+    }
+}
+
+pub fn set_dark_mode(handle: RawWindowHandle, dark: Option<bool>) -> Result<(), Error> {
+    match Target::from_raw(handle)? {
+        Target::X11(window) => theme::set_dark_mode(&x11::X11::connect()?, window, dark),
+        Target::Wayland { .. } => Err(Error::UnsupportedPlatform(
+            "\"set_dark_mode()\" is only supported on X11 on Linux.",
+        )),
+    }
+}
+
+pub fn set_opacity(handle: RawWindowHandle, opacity: Option<f64>) -> Result<(), Error> {
+    match Target::from_raw(handle)? {
+        Target::X11(window) => ForeignWindow::from_id(window).set_opacity(opacity),
+        Target::Wayland { .. } => Err(Error::UnsupportedPlatform(
+            "\"set_opacity()\" is only supported on X11 on Linux.",
+        )),
+    }
+}
+
+/// Reads the state the window manager gives an X11 window in `_NET_WM_STATE`.
+pub fn window_state(handle: RawWindowHandle) -> Result<WindowState, Error> {
+    let window = match Target::from_raw(handle)? {
+        Target::X11(window) => window,
+        Target::Wayland { .. } => {
+            return Err(Error::UnsupportedPlatform(
+                "\"window_state()\" is only supported on X11 on Linux.",
+            ))
+        }
+    };
+    let x11 = x11::X11::connect()?;
+    let state = x11.get_cardinals(window, "_NET_WM_STATE")?;
+    let has = |name| x11.atom(name).map(|atom| state.contains(&atom));
+    Ok(WindowState {
+        maximized: has("_NET_WM_STATE_MAXIMIZED_VERT")? && has("_NET_WM_STATE_MAXIMIZED_HORZ")?,
+        fullscreen: has("_NET_WM_STATE_FULLSCREEN")?,
+    })
+}
+
+/// Watches an X11 window for interactive moves and resizes, see [`MoveSizeTracker`].
+pub fn move_size_tracker(handle: RawWindowHandle) -> Result<MoveSizeTracker, Error> {
+    match Target::from_raw(handle)? {
+        Target::X11(window) => MoveSizeTracker::new(window),
+        Target::Wayland { .. } => Err(Error::UnsupportedPlatform(
+            "moves and resizes can only be tracked on X11 on Linux.",
+        )),
+    }
+}
+
+/// Recomputes the opaque region of an X11 window after a resize that kept its blur region.
+pub fn update_opaque_region(
+    handle: RawWindowHandle,
+    width: u32,
+    height: u32,
+    translucent: &[Rect],
+) -> Result<(), Error> {
+    match Target::from_raw(handle)? {
+        Target::X11(window) if !translucent.is_empty() => {
+            x11::X11::connect()?.set_opaque_region(window, Some(translucent), Some((width, height)))
+        }
+        _ => Ok(()),
+    }
+}
+
+/// The X11 properties whose support is worth reporting.
+const REPORTED_ATOMS: &[&str] = &[
+    "_KDE_NET_WM_BLUR_BEHIND_REGION",
+    "_KDE_NET_WM_BACKGROUND_CONTRAST_REGION",
+    "_NET_WM_OPAQUE_REGION",
+    "_NET_WM_WINDOW_OPACITY",
+];
+
+/// The Wayland globals behind effects, ours or other toolkits'.
+const REPORTED_GLOBALS: &[&str] = &[
+    "org_kde_kwin_blur_manager",
+    "org_kde_kwin_contrast_manager",
+    "ext_background_effect_manager_v1",
+    "wp_alpha_modifier_v1",
+];
+
+pub fn diagnose(handle: RawWindowHandle, report: &mut Report) {
+    report.os = os_name();
+    report.kernel = std::fs::read_to_string("/proc/sys/kernel/osrelease")
+        .ok()
+        .map(|release| release.trim().to_string());
+    report.dark_mode = theme::is_system_dark_mode();
+    report.high_contrast = theme::is_high_contrast();
+
+    let target = match Target::from_raw(handle) {
+        Ok(target) => target,
+        Err(e) => return report.errors.push(e.to_string()),
+    };
+    report.display_server = Some(match target {
+        Target::Wayland { .. } => DisplayServer::Wayland,
+        Target::X11(_) if std::env::var_os("WAYLAND_DISPLAY").is_some() => DisplayServer::XWayland,
+        Target::X11(_) => DisplayServer::X11,
+    });
+
+    if sway::is_available() {
+        match sway::Connection::connect().and_then(|mut sway| sway.get_version()) {
+            Ok(version) => report.compositor = Some(version),
+            Err(e) => report.errors.push(format!("sway IPC: {}", e)),
+        }
+        report.protocols.push("sway IPC".into());
+        report
+            .effects
+            .extend(["apply_blur", "clear_blur", "apply_vibrancy"]);
+    }
+
+    let result = match target {
+        Target::X11(window) => diagnose_x11(window, report),
+        // the surface comes from the window handle, which the caller keeps alive
+        Target::Wayland { surface, .. } => unsafe { diagnose_wayland(surface, report) },
+    };
+    if let Err(e) = result {
+        report.errors.push(e.to_string());
+    }
+
+    if report.compositor.is_none() {
+        report.compositor = std::env::var("XDG_CURRENT_DESKTOP").ok();
+    }
+    report.effects.sort_unstable();
+    report.effects.dedup();
+}
+
+fn diagnose_x11(window: u32, report: &mut Report) -> Result<(), Error> {
+    let x11 = x11::X11::connect()?;
+    if let Some(name) = x11.window_manager_name()? {
+        report.compositor.get_or_insert(name);
+    }
+    report.window_depth = Some(x11.window_depth(window)?);
+    for atom in REPORTED_ATOMS {
+        if x11.supports(atom)? {
+            report.protocols.push(atom.to_string());
+        }
+    }
+    if kde::is_blur_supported(&x11)? {
+        report.effects.extend([
+            "apply_blur",
+            "clear_blur",
+            "apply_blur_region",
+            "apply_vibrancy",
+        ]);
+    }
+    report.effects.push("set_dark_mode");
+    Ok(())
+}
+
+/// `surface` must point to a live `wl_surface`.
+unsafe fn diagnose_wayland(surface: NonNull<c_void>, report: &mut Report) -> Result<(), Error> {
+    let wayland = wayland::Wayland::from_surface(surface)?;
+    for global in REPORTED_GLOBALS {
+        if wayland.has_global(global) {
+            report.protocols.push(global.to_string());
+        }
+    }
+    if wayland.is_blur_supported() {
+        report.effects.extend([
+            "apply_blur",
+            "clear_blur",
+            "apply_blur_region",
+            "apply_vibrancy",
+        ]);
+    }
+    Ok(())
+}
+
+/// `PRETTY_NAME` from os-release.
+fn os_name() -> Option<String> {
+    let release = std::fs::read_to_string("/etc/os-release")
+        .or_else(|_| std::fs::read_to_string("/usr/lib/os-release"))
+        .ok()?;
+    release.lines().find_map(|line| {
+        let value = line.strip_prefix("PRETTY_NAME=")?;
+        Some(value.trim_matches('"').to_string())
+    })
+}
@@ -0,0 +1,72 @@
+// Copyright 2019-2022 Tauri Programme within The Commons Conservancy
+// SPDX-License-Identifier: Apache-2.0
+// SPDX-License-Identifier: MIT
+
+use x11rb::protocol::{xproto::EventMask, Event};
+
+use super::x11::X11;
+use crate::Error;
+
+/// `_NET_WM_MOVERESIZE_CANCEL`, the other directions start a move or resize.
+const MOVERESIZE_CANCEL: u32 = 11;
+
+/// What happened to the window, see [`MoveSizeTracker::poll_events`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MoveSizeEvent {
+    /// A `_NET_WM_MOVERESIZE` started an interactive move or resize.
+    Started,
+    /// A `_NET_WM_MOVERESIZE_CANCEL` ended it.
+    Cancelled,
+    /// The window moved or was resized.
+    Configured,
+}
+
+/// Watches an X11 window for interactive moves and resizes.
+///
+/// Clients ask the window manager to move or resize them with a `_NET_WM_MOVERESIZE`
+/// message to the root window, which every client watching the root's substructure
+/// receives too. Nothing tells when the move or resize ends, which is left to the
+/// timeout of the [`MoveSizeMachine`](crate::MoveSizeMachine) once configure events stop.
+pub struct MoveSizeTracker {
+    x11: X11,
+    window: u32,
+    moveresize: u32,
+}
+
+impl MoveSizeTracker {
+    pub fn new(window: u32) -> Result<Self, Error> {
+        let x11 = X11::connect()?;
+        let moveresize = x11.atom("_NET_WM_MOVERESIZE")?;
+        x11.select_events(x11.root(), EventMask::SUBSTRUCTURE_NOTIFY)?;
+        x11.select_events(window, EventMask::STRUCTURE_NOTIFY)?;
+        Ok(Self {
+            x11,
+            window,
+            moveresize,
+        })
+    }
+
+    /// The events received since the last call, without waiting for more.
+    pub fn poll_events(&self) -> Result<Vec<MoveSizeEvent>, Error> {
+        let mut events = Vec::new();
+        while let Some(event) = self.x11.poll_for_event()? {
+            match event {
+                Event::ClientMessage(message)
+                    if message.window == self.window && message.type_ == self.moveresize =>
+                {
+                    let direction = message.data.as_data32()[2];
+                    events.push(if direction == MOVERESIZE_CANCEL {
+                        MoveSizeEvent::Cancelled
+                    } else {
+                        MoveSizeEvent::Started
+                    });
+                }
+                Event::ConfigureNotify(event) if event.window == self.window => {
+                    events.push(MoveSizeEvent::Configured)
+                }
+                _ => {}
+            }
+        }
+        Ok(events)
+    }
+}
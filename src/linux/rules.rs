@@ -0,0 +1,349 @@
+// Copyright 2019-2022 Tauri Programme within The Commons Conservancy
+// SPDX-License-Identifier: Apache-2.0
+// SPDX-License-Identifier: MIT
+
+#![cfg(feature = "rules")]
+
+use std::{collections::HashMap, fs, path::Path};
+
+use regex::Regex;
+use serde::Deserialize;
+use x11rb::protocol::{xproto::EventMask, Event};
+
+use super::{foreign::ForeignWindow, x11::X11};
+use crate::{
+    trace::{trace_debug, trace_warn},
+    BackgroundContrast, Error,
+};
+
+const CLIENT_LIST: &str = "_NET_CLIENT_LIST";
+/// The properties a rule can match on, a change re-evaluates the window.
+const MATCHED_PROPERTIES: &[&str] = &["WM_CLASS", "WM_NAME", "_NET_WM_NAME", "_NET_WM_WINDOW_TYPE"];
+
+/// A rule of a [`RulesEngine`], a `[[rule]]` table of its TOML file.
+///
+/// A window matches when it matches every field that is set. When several rules match,
+/// the effects of later rules override the ones of earlier rules.
+#[derive(Clone, Debug, Default, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Rule {
+    /// The `WM_CLASS` instance or class name.
+    pub class: Option<String>,
+    /// The `_NET_WM_WINDOW_TYPE`, like `normal`, `dialog` or `utility`.
+    pub window_type: Option<String>,
+    /// A regular expression the title must match.
+    pub title: Option<String>,
+    /// Blur the window, `false` removes a blur set by an earlier rule.
+    pub blur: Option<bool>,
+    /// The opacity of the window, between `0.0` and `1.0`.
+    pub opacity: Option<f64>,
+    /// The background contrast of the window, on KDE Plasma.
+    pub contrast: Option<BackgroundContrast>,
+}
+
+/// The effects rules resolved to for a window, see [`RulesEngine::evaluate`].
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct RuleEffects {
+    pub blur: bool,
+    pub opacity: Option<f64>,
+    pub contrast: Option<BackgroundContrast>,
+}
+
+/// What rules match on, see [`RulesEngine::evaluate`].
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct WindowProperties {
+    /// The `WM_CLASS` instance and class names.
+    pub class: Vec<String>,
+    /// The `_NET_WM_WINDOW_TYPE` names, without the `_NET_WM_WINDOW_TYPE_` prefix, lowercased.
+    pub window_types: Vec<String>,
+    pub title: String,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RulesFile {
+    #[serde(default)]
+    rule: Vec<Rule>,
+}
+
+/// Applies effects to the X11 windows of every client, as they appear, following rules.
+///
+/// ```toml
+/// [[rule]]
+/// class = "kitty"
+/// blur = true
+/// opacity = 0.9
+///
+/// [[rule]]
+/// window_type = "dialog"
+/// title = "^Open File"
+/// blur = true
+/// contrast = { contrast = 0.8, intensity = 1.1, saturation = 1.5 }
+/// ```
+pub struct RulesEngine {
+    rules: Vec<(Rule, Option<Regex>)>,
+}
+
+impl RulesEngine {
+    /// Compiles the rules, failing on invalid title regexes.
+    pub fn new(rules: Vec<Rule>) -> Result<Self, Error> {
+        let rules = rules
+            .into_iter()
+            .map(|rule| {
+                let title = match &rule.title {
+                    Some(title) => Some(Regex::new(title).map_err(|e| {
+                        Error::Config(format!("invalid title regex \"{}\": {}", title, e))
+                    })?),
+                    None => None,
+                };
+                Ok((rule, title))
+            })
+            .collect::<Result<_, Error>>()?;
+        Ok(Self { rules })
+    }
+
+    /// Parses rules from the content of a TOML file.
+    pub fn from_toml(toml: &str) -> Result<Self, Error> {
+        let file: RulesFile =
+            toml::from_str(toml).map_err(|e| Error::Config(format!("invalid rules: {}", e)))?;
+        Self::new(file.rule)
+    }
+
+    /// Reads rules from a TOML file.
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, Error> {
+        Self::from_toml(&fs::read_to_string(path)?)
+    }
+
+    pub fn rules(&self) -> impl Iterator<Item = &Rule> {
+        self.rules.iter().map(|(rule, _)| rule)
+    }
+
+    /// The effects the rules give a window with these properties.
+    pub fn evaluate(&self, window: &WindowProperties) -> RuleEffects {
+        let mut effects = RuleEffects::default();
+        for (rule, title) in &self.rules {
+            let matches = rule
+                .class
+                .as_ref()
+                .map_or(true, |class| window.class.contains(class))
+                && rule.window_type.as_ref().map_or(true, |window_type| {
+                    window
+                        .window_types
+                        .iter()
+                        .any(|t| t.eq_ignore_ascii_case(window_type))
+                })
+                && title
+                    .as_ref()
+                    .map_or(true, |title| title.is_match(&window.title));
+            if !matches {
+                continue;
+            }
+            if let Some(blur) = rule.blur {
+                effects.blur = blur;
+            }
+            if rule.opacity.is_some() {
+                effects.opacity = rule.opacity;
+            }
+            if rule.contrast.is_some() {
+                effects.contrast = rule.contrast;
+            }
+        }
+        effects
+    }
+
+    /// Applies the rules to the current windows, then to new windows and windows whose
+    /// class, type or title changes, until the connection to the X server fails.
+    pub fn run(&self) -> Result<(), Error> {
+        let x11 = X11::connect()?;
+        let root = x11.root();
+        let client_list = x11.atom(CLIENT_LIST)?;
+        let mut matched = Vec::with_capacity(MATCHED_PROPERTIES.len());
+        for name in MATCHED_PROPERTIES {
+            matched.push(x11.atom(name)?);
+        }
+        x11.select_events(
+            root,
+            EventMask::SUBSTRUCTURE_NOTIFY | EventMask::PROPERTY_CHANGE,
+        )?;
+
+        let mut applied = HashMap::new();
+        self.sync_clients(&x11, &mut applied)?;
+        loop {
+            match x11.wait_for_event()? {
+                // the window manager lists a new client once it manages it
+                Event::PropertyNotify(event)
+                    if event.window == root && event.atom == client_list =>
+                {
+                    self.sync_clients(&x11, &mut applied)?
+                }
+                Event::MapNotify(_) => self.sync_clients(&x11, &mut applied)?,
+                Event::PropertyNotify(event) if matched.contains(&event.atom) => {
+                    if let Some(previous) = applied.get(&event.window).copied() {
+                        let effects = self.update(&x11, event.window, previous);
+                        applied.insert(event.window, effects);
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+
+    /// Starts watching new clients, and forgets the ones that are gone.
+    fn sync_clients(
+        &self,
+        x11: &X11,
+        applied: &mut HashMap<u32, RuleEffects>,
+    ) -> Result<(), Error> {
+        let clients = x11.get_cardinals(x11.root(), CLIENT_LIST)?;
+        applied.retain(|window, _| clients.contains(window));
+        for window in clients {
+            if applied.contains_key(&window) {
+                continue;
+            }
+            // the window can be destroyed at any time, it is then removed from the client list
+            if let Err(e) = x11.select_events(window, EventMask::PROPERTY_CHANGE) {
+                trace_debug!("can't watch window {:#x}: {}", window, e);
+                continue;
+            }
+            let effects = self.update(x11, window, RuleEffects::default());
+            applied.insert(window, effects);
+        }
+        Ok(())
+    }
+
+    /// Applies what changed since `previous`, returns what is now applied.
+    fn update(&self, x11: &X11, window: u32, previous: RuleEffects) -> RuleEffects {
+        let properties = match read_properties(x11, window) {
+            Ok(properties) => properties,
+            Err(_) => return previous,
+        };
+        let effects = self.evaluate(&properties);
+        let target = ForeignWindow::from_id(window);
+        let mut results = Vec::new();
+        if effects.blur != previous.blur {
+            results.push(target.set_blur(x11, effects.blur));
+        }
+        if effects.opacity != previous.opacity {
+            results.push(target.set_opacity_with(x11, effects.opacity));
+        }
+        if effects.contrast != previous.contrast {
+            results.push(target.set_background_contrast_with(x11, effects.contrast));
+        }
+        for result in results {
+            if let Err(e) = result {
+                trace_warn!("can't apply the rules to window {:#x}: {}", window, e);
+            }
+        }
+        effects
+    }
+}
+
+fn read_properties(x11: &X11, window: u32) -> Result<WindowProperties, Error> {
+    let class = x11
+        .get_string(window, "WM_CLASS")?
+        .unwrap_or_default()
+        .split('\0')
+        .filter(|name| !name.is_empty())
+        .map(str::to_string)
+        .collect();
+    let mut window_types = Vec::new();
+    for atom in x11.get_cardinals(window, "_NET_WM_WINDOW_TYPE")? {
+        let name = x11.atom_name(atom)?;
+        let name = name.strip_prefix("_NET_WM_WINDOW_TYPE_").unwrap_or(&name);
+        window_types.push(name.to_ascii_lowercase());
+    }
+    let title = match x11.get_string(window, "_NET_WM_NAME")? {
+        Some(title) => title,
+        None => x11.get_string(window, "WM_NAME")?.unwrap_or_default(),
+    };
+    Ok(WindowProperties {
+        class,
+        window_types,
+        title,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        thread,
+        time::{Duration, Instant},
+    };
+
+    use super::*;
+
+    const RULES: &str = r#"
+        [[rule]]
+        class = "window-vibrancy-rules"
+        blur = true
+        opacity = 0.5
+
+        [[rule]]
+        title = "^Private"
+        blur = false
+    "#;
+
+    #[test]
+    fn later_rules_override_earlier_ones() {
+        let engine = RulesEngine::from_toml(RULES).unwrap();
+        let mut window = WindowProperties {
+            class: vec!["window-vibrancy-rules".into()],
+            window_types: vec!["normal".into()],
+            title: "Notes".into(),
+        };
+        let blurred = RuleEffects {
+            blur: true,
+            opacity: Some(0.5),
+            contrast: None,
+        };
+        assert_eq!(engine.evaluate(&window), blurred);
+        window.title = "Private notes".into();
+        assert_eq!(
+            engine.evaluate(&window),
+            RuleEffects {
+                blur: false,
+                ..blurred
+            }
+        );
+        assert!(RulesEngine::from_toml("[[rule]]\ntitle = \"(\"").is_err());
+    }
+
+    /// Waits for `check` to hold, the engine runs on its own connection.
+    fn eventually(check: impl Fn() -> bool) -> bool {
+        let start = Instant::now();
+        while start.elapsed() < Duration::from_secs(5) {
+            if check() {
+                return true;
+            }
+            thread::sleep(Duration::from_millis(20));
+        }
+        false
+    }
+
+    #[test]
+    #[ignore = "needs an X server, see `X11::create_test_window`"]
+    fn applies_rules_to_mapped_windows() {
+        thread::spawn(|| RulesEngine::from_toml(RULES).unwrap().run());
+
+        let x11 = X11::connect().unwrap();
+        let window = x11
+            .create_test_window("window-vibrancy-rules", "Notes")
+            .unwrap();
+        let property = |name| x11.get_property32(window, name).unwrap();
+
+        assert!(eventually(
+            || property("_KDE_NET_WM_BLUR_BEHIND_REGION").is_some()
+        ));
+        assert!(eventually(
+            || property("_NET_WM_WINDOW_OPACITY") == Some(vec![0x8000_0000])
+        ));
+
+        // a new title is matched again
+        x11.set_string(window, "_NET_WM_NAME", "UTF8_STRING", "Private notes")
+            .unwrap();
+        assert!(eventually(
+            || property("_KDE_NET_WM_BLUR_BEHIND_REGION").is_none()
+        ));
+        assert_eq!(property("_NET_WM_WINDOW_OPACITY"), Some(vec![0x8000_0000]));
+    }
+}
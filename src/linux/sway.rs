@@ -0,0 +1,300 @@
+// Copyright 2019-2022 Tauri Programme within The Commons Conservancy
+// SPDX-License-Identifier: Apache-2.0
+// SPDX-License-Identifier: MIT
+
+// SwayFX exposes blur, corner radius and shadows as regular sway commands,
+// so we only need to speak the i3 IPC protocol on `$SWAYSOCK`.
+// https://i3wm.org/docs/ipc.html
+// https://github.com/WillPower3309/swayfx
+
+use std::{
+    env,
+    io::{self, Read, Write},
+    os::unix::net::UnixStream,
+    path::Path,
+};
+
+use serde_json::Value;
+
+use super::Target;
+use crate::Error;
+
+const MAGIC: &[u8; 6] = b"i3-ipc";
+const HEADER_LEN: usize = MAGIC.len() + 8;
+
+const RUN_COMMAND: u32 = 0;
+const GET_TREE: u32 = 4;
+const GET_VERSION: u32 = 7;
+
+pub fn is_available() -> bool {
+    env::var_os("SWAYSOCK").is_some()
+}
+
+pub fn apply_blur(target: &Target, radius: Option<f64>) -> Result<(), Error> {
+    run_for_target(&mut Connection::connect()?, target, &blur_commands(radius))
+}
+
+fn blur_commands(radius: Option<f64>) -> Vec<String> {
+    let mut commands = vec!["blur enable".to_string()];
+    if let Some(radius) = radius {
+        commands.push(format!("corner_radius {}", radius.max(0.0).round() as u32));
+    }
+    commands
+}
+
+pub fn clear_blur(target: &Target) -> Result<(), Error> {
+    run_for_target(
+        &mut Connection::connect()?,
+        target,
+        &["blur disable".to_string()],
+    )
+}
+
+/// Looks up every container belonging to `target` and runs `commands` on them.
+///
+/// For a Wayland target these are all the native Wayland containers of the process:
+/// sway doesn't tell which container shows a given `wl_surface`, so an application with
+/// several Wayland windows gets the effect on all of them.
+fn run_for_target(
+    conn: &mut Connection,
+    target: &Target,
+    commands: &[String],
+) -> Result<(), Error> {
+    let tree = conn.get_tree()?;
+    let mut ids = Vec::new();
+    find_containers(&tree, target, &mut ids);
+    if ids.is_empty() {
+        return Err(Error::Compositor(
+            "sway has no container for this window".into(),
+        ));
+    }
+
+    // `;` would reset the criteria, so the per-container commands are chained with `,`.
+    let command = ids
+        .iter()
+        .map(|id| format!("[con_id={}] {}", id, commands.join(", ")))
+        .collect::<Vec<_>>()
+        .join("; ");
+    conn.run_command(&command)
+}
+
+fn find_containers(node: &Value, target: &Target, ids: &mut Vec<u64>) {
+    let matches = match target {
+        Target::X11(xid) => node["window"].as_u64() == Some(*xid as u64),
+        Target::Wayland { pid, .. } => {
+            node["pid"].as_u64() == Some(*pid as u64) && node["app_id"].is_string()
+        }
+    };
+    if matches {
+        if let Some(id) = node["id"].as_u64() {
+            ids.push(id);
+        }
+    }
+
+    for key in ["nodes", "floating_nodes"] {
+        if let Some(children) = node[key].as_array() {
+            for child in children {
+                find_containers(child, target, ids);
+            }
+        }
+    }
+}
+
+/// A connection to the sway IPC socket.
+pub struct Connection {
+    stream: UnixStream,
+}
+
+impl Connection {
+    /// Connects to the socket advertised in `$SWAYSOCK`.
+    pub fn connect() -> Result<Self, Error> {
+        let path = env::var_os("SWAYSOCK").ok_or(Error::UnsupportedPlatform(
+            "\"$SWAYSOCK\" is not set, sway does not seem to be running.",
+        ))?;
+        Ok(Self::connect_to(path)?)
+    }
+
+    pub fn connect_to(path: impl AsRef<Path>) -> io::Result<Self> {
+        Ok(Self {
+            stream: UnixStream::connect(path)?,
+        })
+    }
+
+    pub fn get_tree(&mut self) -> Result<Value, Error> {
+        let reply = self.request(GET_TREE, b"")?;
+        serde_json::from_slice(&reply)
+            .map_err(|e| Error::Compositor(format!("invalid sway tree: {}", e)))
+    }
+
+    /// The human readable version of the compositor, e.g. `swayfx version 0.4`.
+    pub fn get_version(&mut self) -> Result<String, Error> {
+        let reply = self.request(GET_VERSION, b"")?;
+        let version: Value = serde_json::from_slice(&reply)
+            .map_err(|e| Error::Compositor(format!("invalid sway version: {}", e)))?;
+        Ok(version["human_readable"]
+            .as_str()
+            .unwrap_or_default()
+            .to_string())
+    }
+
+    pub fn run_command(&mut self, command: &str) -> Result<(), Error> {
+        let reply = self.request(RUN_COMMAND, command.as_bytes())?;
+        let outcomes: Value = serde_json::from_slice(&reply)
+            .map_err(|e| Error::Compositor(format!("invalid sway reply: {}", e)))?;
+
+        for outcome in outcomes.as_array().into_iter().flatten() {
+            if outcome["success"].as_bool() != Some(true) {
+                let error = outcome["error"].as_str().unwrap_or("unknown error");
+                return Err(Error::Compositor(format!(
+                    "sway rejected \"{}\": {}",
+                    command, error
+                )));
+            }
+        }
+        Ok(())
+    }
+
+    fn request(&mut self, kind: u32, payload: &[u8]) -> io::Result<Vec<u8>> {
+        self.stream.write_all(&encode_message(kind, payload))?;
+        let (reply_kind, reply) = read_message(&mut self.stream)?;
+        if reply_kind != kind {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("expected a reply of type {}, got {}", kind, reply_kind),
+            ));
+        }
+        Ok(reply)
+    }
+}
+
+/// Frames `payload` as `"i3-ipc" <length: u32> <type: u32> <payload>`, integers in native byte order.
+pub fn encode_message(kind: u32, payload: &[u8]) -> Vec<u8> {
+    let mut message = Vec::with_capacity(HEADER_LEN + payload.len());
+    message.extend_from_slice(MAGIC);
+    message.extend_from_slice(&(payload.len() as u32).to_ne_bytes());
+    message.extend_from_slice(&kind.to_ne_bytes());
+    message.extend_from_slice(payload);
+    message
+}
+
+/// Reads one framed message, returning its type and payload.
+pub fn read_message(reader: &mut impl Read) -> io::Result<(u32, Vec<u8>)> {
+    let mut header = [0; HEADER_LEN];
+    reader.read_exact(&mut header)?;
+    if &header[..MAGIC.len()] != MAGIC {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "missing \"i3-ipc\" magic string",
+        ));
+    }
+
+    let mut len = [0; 4];
+    len.copy_from_slice(&header[6..10]);
+    let mut kind = [0; 4];
+    kind.copy_from_slice(&header[10..14]);
+
+    let mut payload = vec![0; u32::from_ne_bytes(len) as usize];
+    reader.read_exact(&mut payload)?;
+    Ok((u32::from_ne_bytes(kind), payload))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        os::unix::net::UnixListener,
+        path::PathBuf,
+        thread::{self, JoinHandle},
+    };
+
+    use super::*;
+
+    /// A stand-in for sway answering `GET_TREE` with `tree`, then recording the next command.
+    fn fake_sway(name: &str, tree: &'static str) -> (PathBuf, JoinHandle<Vec<u8>>) {
+        let path = env::temp_dir().join(format!(
+            "window-vibrancy-{}-{}.sock",
+            name,
+            std::process::id()
+        ));
+        let _ = std::fs::remove_file(&path);
+        let listener = UnixListener::bind(&path).unwrap();
+        let server = thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let (kind, payload) = read_message(&mut stream).unwrap();
+            assert_eq!((kind, payload.as_slice()), (GET_TREE, &b""[..]));
+            stream
+                .write_all(&encode_message(GET_TREE, tree.as_bytes()))
+                .unwrap();
+
+            let (kind, command) = read_message(&mut stream).unwrap();
+            assert_eq!(kind, RUN_COMMAND);
+            stream
+                .write_all(&encode_message(RUN_COMMAND, br#"[{"success":true}]"#))
+                .unwrap();
+            command
+        });
+        (path, server)
+    }
+
+    const TREE: &str = r#"{
+        "id": 1,
+        "nodes": [{
+            "id": 2,
+            "nodes": [
+                { "id": 10, "window": 4194305, "pid": 100, "app_id": null },
+                { "id": 11, "window": null, "pid": 200, "app_id": "foo" }
+            ],
+            "floating_nodes": [
+                { "id": 12, "window": null, "pid": 200, "app_id": "foo" },
+                { "id": 13, "window": null, "pid": 300, "app_id": "bar" }
+            ]
+        }]
+    }"#;
+
+    #[test]
+    fn framing() {
+        let message = encode_message(RUN_COMMAND, b"blur enable");
+        assert_eq!(&message[..6], b"i3-ipc");
+        assert_eq!(&message[6..10], &11u32.to_ne_bytes());
+        assert_eq!(&message[10..14], &RUN_COMMAND.to_ne_bytes());
+        assert_eq!(&message[14..], b"blur enable");
+
+        assert_eq!(
+            read_message(&mut message.as_slice()).unwrap(),
+            (RUN_COMMAND, b"blur enable".to_vec())
+        );
+        assert!(read_message(&mut &b"i3-xyz\0\0\0\0\0\0\0\0"[..]).is_err());
+    }
+
+    #[test]
+    fn x11_window_gets_one_container() {
+        let (path, server) = fake_sway("x11", TREE);
+        let mut conn = Connection::connect_to(&path).unwrap();
+        run_for_target(&mut conn, &Target::X11(4194305), &blur_commands(Some(11.6))).unwrap();
+        assert_eq!(
+            server.join().unwrap(),
+            b"[con_id=10] blur enable, corner_radius 12"
+        );
+        let _ = std::fs::remove_file(path);
+    }
+
+    #[test]
+    fn wayland_process_gets_all_its_containers() {
+        let (path, server) = fake_sway("wayland", TREE);
+        let mut conn = Connection::connect_to(&path).unwrap();
+        let commands = ["blur disable".to_string()];
+        run_for_target(
+            &mut conn,
+            &Target::Wayland {
+                pid: 200,
+                surface: std::ptr::NonNull::dangling(),
+            },
+            &commands,
+        )
+        .unwrap();
+        assert_eq!(
+            server.join().unwrap(),
+            b"[con_id=11] blur disable; [con_id=12] blur disable"
+        );
+        let _ = std::fs::remove_file(path);
+    }
+}
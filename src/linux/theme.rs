@@ -0,0 +1,82 @@
+// Copyright 2019-2022 Tauri Programme within The Commons Conservancy
+// SPDX-License-Identifier: Apache-2.0
+// SPDX-License-Identifier: MIT
+
+// Hints window managers use to pick the theme of server-side decorations.
+
+use std::{env, path::PathBuf, process::Command};
+
+use super::x11::X11;
+use crate::Error;
+
+/// Read by mutter, xfwm4 and other GTK-based window managers.
+const GTK_THEME_VARIANT: &str = "_GTK_THEME_VARIANT";
+/// Read by KWin, holds the path of a `.colors` file.
+const KDE_COLOR_SCHEME: &str = "_KDE_NET_WM_COLOR_SCHEME";
+
+pub fn set_dark_mode(x11: &X11, window: u32, dark: Option<bool>) -> Result<(), Error> {
+    let dark = match dark {
+        Some(dark) => dark,
+        None => {
+            x11.delete_property(window, GTK_THEME_VARIANT)?;
+            return x11.delete_property(window, KDE_COLOR_SCHEME);
+        }
+    };
+
+    let variant = if dark { "dark" } else { "light" };
+    x11.set_string(window, GTK_THEME_VARIANT, "UTF8_STRING", variant)?;
+
+    let scheme = if dark { "BreezeDark" } else { "BreezeLight" };
+    match find_color_scheme(scheme) {
+        Some(path) => x11.set_string(window, KDE_COLOR_SCHEME, "STRING", &path.to_string_lossy()),
+        None => x11.delete_property(window, KDE_COLOR_SCHEME),
+    }
+}
+
+/// Looks for `color-schemes/<name>.colors` in the XDG data directories.
+fn find_color_scheme(name: &str) -> Option<PathBuf> {
+    let data_home = env::var_os("XDG_DATA_HOME")
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".local/share")));
+    let data_dirs = env::var("XDG_DATA_DIRS")
+        .ok()
+        .filter(|dirs| !dirs.is_empty())
+        .unwrap_or_else(|| "/usr/local/share:/usr/share".into());
+
+    data_home
+        .into_iter()
+        .chain(data_dirs.split(':').map(PathBuf::from))
+        .map(|dir| dir.join("color-schemes").join(format!("{}.colors", name)))
+        .find(|path| path.is_file())
+}
+
+/// Whether the desktop prefers dark mode, from GNOME's `color-scheme` or else the GTK theme name.
+pub fn is_system_dark_mode() -> Option<bool> {
+    match gsettings("org.gnome.desktop.interface", "color-scheme").as_deref() {
+        Some("prefer-dark") => return Some(true),
+        Some("prefer-light") => return Some(false),
+        _ => {}
+    }
+    let theme = env::var("GTK_THEME")
+        .ok()
+        .or_else(|| gsettings("org.gnome.desktop.interface", "gtk-theme"))?;
+    Some(theme.to_ascii_lowercase().contains("dark"))
+}
+
+/// Whether GNOME's high contrast mode is on.
+pub fn is_high_contrast() -> Option<bool> {
+    gsettings("org.gnome.desktop.a11y.interface", "high-contrast").map(|value| value == "true")
+}
+
+/// Reads a setting, without the quotes of string values.
+fn gsettings(schema: &str, key: &str) -> Option<String> {
+    let output = Command::new("gsettings")
+        .args(["get", schema, key])
+        .output()
+        .ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    let value = String::from_utf8(output.stdout).ok()?;
+    Some(value.trim().trim_matches('\'').to_string())
+}
@@ -0,0 +1,277 @@
+// Copyright 2019-2022 Tauri Programme within The Commons Conservancy
+// SPDX-License-Identifier: Apache-2.0
+// SPDX-License-Identifier: MIT
+
+use std::{
+    env, fs,
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+/// Finds the wallpaper of the current GNOME or KDE Plasma session, to feed into
+/// [`software::mica_background`](crate::software::mica_background).
+///
+/// Every location it reads can be overridden, e.g. to point it at fixtures.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct WallpaperLocator {
+    /// `$XDG_CONFIG_HOME`, where `plasma-org.kde.plasma.desktop-appletsrc` lives.
+    pub config_home: PathBuf,
+    /// The `gsettings` program used to read GNOME's `org.gnome.desktop.background` settings.
+    pub gsettings: PathBuf,
+    /// `$XDG_CURRENT_DESKTOP`, used to ask the running desktop first.
+    pub current_desktop: String,
+}
+
+impl WallpaperLocator {
+    /// Uses the locations of the current session.
+    pub fn from_env() -> Self {
+        let config_home = env::var_os("XDG_CONFIG_HOME")
+            .map(PathBuf::from)
+            .filter(|path| path.is_absolute())
+            .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))
+            .unwrap_or_default();
+        Self {
+            config_home,
+            gsettings: PathBuf::from("gsettings"),
+            current_desktop: env::var("XDG_CURRENT_DESKTOP").unwrap_or_default(),
+        }
+    }
+
+    /// Returns the path of the wallpaper, the dark variant if `dark` and the desktop has one.
+    pub fn locate(&self, dark: bool) -> Option<PathBuf> {
+        let kde_first = self
+            .current_desktop
+            .split(':')
+            .any(|desktop| desktop.eq_ignore_ascii_case("KDE"));
+        if kde_first {
+            self.kde().or_else(|| self.gnome(dark))
+        } else {
+            self.gnome(dark).or_else(|| self.kde())
+        }
+    }
+
+    fn gnome(&self, dark: bool) -> Option<PathBuf> {
+        let gsettings = |key: &str| -> Option<PathBuf> {
+            let output = Command::new(&self.gsettings)
+                .args(["get", "org.gnome.desktop.background", key])
+                .output()
+                .ok()?;
+            if !output.status.success() {
+                return None;
+            }
+            // values are printed as GVariant strings: 'file:///usr/share/backgrounds/a.jpg'
+            let value = String::from_utf8(output.stdout).ok()?;
+            let value = value.trim().trim_matches('\'');
+            uri_to_path(value)
+        };
+
+        if dark {
+            if let Some(path) = gsettings("picture-uri-dark") {
+                return Some(path);
+            }
+        }
+        gsettings("picture-uri")
+    }
+
+    fn kde(&self) -> Option<PathBuf> {
+        let config = fs::read_to_string(
+            self.config_home
+                .join("plasma-org.kde.plasma.desktop-appletsrc"),
+        )
+        .ok()?;
+        parse_kde_appletsrc(&config)
+    }
+}
+
+/// Returns the path of the wallpaper of the current session, see [`WallpaperLocator`].
+pub fn current_wallpaper(dark: bool) -> Option<PathBuf> {
+    WallpaperLocator::from_env().locate(dark)
+}
+
+/// Takes the first `Image=` of an `org.kde.image` wallpaper section, i.e. the first screen's.
+fn parse_kde_appletsrc(config: &str) -> Option<PathBuf> {
+    let mut in_image_section = false;
+    for line in config.lines().map(str::trim) {
+        if line.starts_with('[') {
+            in_image_section = line.ends_with("[Wallpaper][org.kde.image][General]");
+        } else if in_image_section {
+            if let Some(value) = line.strip_prefix("Image=") {
+                if let Some(path) = uri_to_path(value.trim()) {
+                    return Some(path);
+                }
+            }
+        }
+    }
+    None
+}
+
+/// Accepts `file://` URIs and plain absolute paths.
+fn uri_to_path(value: &str) -> Option<PathBuf> {
+    let path = match value.strip_prefix("file://") {
+        Some(path) => percent_decode(path)?,
+        None => value.to_string(),
+    };
+    let path = PathBuf::from(path);
+    if path.is_absolute() && path != Path::new("/") {
+        Some(path)
+    } else {
+        None
+    }
+}
+
+fn percent_decode(value: &str) -> Option<String> {
+    let bytes = value.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'%' {
+            let hex = value.get(i + 1..i + 3)?;
+            out.push(u8::from_str_radix(hex, 16).ok()?);
+            i += 3;
+        } else {
+            out.push(bytes[i]);
+            i += 1;
+        }
+    }
+    String::from_utf8(out).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::os::unix::fs::PermissionsExt;
+
+    use super::*;
+
+    /// An empty directory to hold the fixtures of the test `name`.
+    fn fixture_dir(name: &str) -> PathBuf {
+        let dir = env::temp_dir().join(format!(
+            "window-vibrancy-wallpaper-{}-{}",
+            name,
+            std::process::id()
+        ));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    /// A stand-in for `gsettings` printing `picture-uri` and, if given, `picture-uri-dark`.
+    fn fake_gsettings(dir: &Path, light: &str, dark: Option<&str>) -> PathBuf {
+        let dark = match dark {
+            Some(uri) => format!("  picture-uri-dark) echo \"'{}'\" ;;\n", uri),
+            None => String::new(),
+        };
+        let script = format!(
+            "#!/bin/sh\ncase \"$3\" in\n  picture-uri) echo \"'{}'\" ;;\n{}  *) exit 1 ;;\nesac\n",
+            light, dark
+        );
+        let path = dir.join("gsettings");
+        fs::write(&path, script).unwrap();
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o755)).unwrap();
+        path
+    }
+
+    const APPLETSRC: &str = "\
+[Containments][1][Wallpaper][org.kde.image][General]
+Image=file:///home/user/Pictures/My%20Lake.png
+
+[Containments][2][Wallpaper][org.kde.image][General]
+Image=file:///home/user/Pictures/second-screen.png
+";
+
+    fn locator_at(dir: &Path, gsettings: PathBuf, current_desktop: &str) -> WallpaperLocator {
+        WallpaperLocator {
+            config_home: dir.to_path_buf(),
+            gsettings,
+            current_desktop: current_desktop.to_string(),
+        }
+    }
+
+    #[test]
+    fn gnome_picture_uri() {
+        let dir = fixture_dir("gnome");
+        let gsettings = fake_gsettings(
+            &dir,
+            "file:///usr/share/backgrounds/day.jpg",
+            Some("file:///usr/share/backgrounds/night.jpg"),
+        );
+        let locator = locator_at(&dir, gsettings, "GNOME");
+        assert_eq!(
+            locator.locate(false),
+            Some(PathBuf::from("/usr/share/backgrounds/day.jpg"))
+        );
+        assert_eq!(
+            locator.locate(true),
+            Some(PathBuf::from("/usr/share/backgrounds/night.jpg"))
+        );
+
+        // without a dark variant the light one is used
+        let gsettings = fake_gsettings(&dir, "file:///usr/share/backgrounds/day.jpg", None);
+        assert_eq!(
+            locator_at(&dir, gsettings, "GNOME").locate(true),
+            Some(PathBuf::from("/usr/share/backgrounds/day.jpg"))
+        );
+    }
+
+    #[test]
+    fn kde_appletsrc() {
+        let dir = fixture_dir("kde");
+        fs::write(
+            dir.join("plasma-org.kde.plasma.desktop-appletsrc"),
+            APPLETSRC,
+        )
+        .unwrap();
+        let locator = locator_at(&dir, dir.join("missing-gsettings"), "KDE");
+        assert_eq!(
+            locator.locate(false),
+            Some(PathBuf::from("/home/user/Pictures/My Lake.png"))
+        );
+    }
+
+    #[test]
+    fn current_desktop_goes_first() {
+        let dir = fixture_dir("order");
+        fs::write(
+            dir.join("plasma-org.kde.plasma.desktop-appletsrc"),
+            APPLETSRC,
+        )
+        .unwrap();
+        let gsettings = fake_gsettings(&dir, "file:///usr/share/backgrounds/day.jpg", None);
+
+        let gnome = locator_at(&dir, gsettings.clone(), "ubuntu:GNOME");
+        assert_eq!(
+            gnome.locate(false),
+            Some(PathBuf::from("/usr/share/backgrounds/day.jpg"))
+        );
+        let kde = locator_at(&dir, gsettings, "KDE");
+        assert_eq!(
+            kde.locate(false),
+            Some(PathBuf::from("/home/user/Pictures/My Lake.png"))
+        );
+    }
+
+    #[test]
+    fn nothing_found() {
+        let dir = fixture_dir("none");
+        let locator = locator_at(&dir, dir.join("missing-gsettings"), "");
+        assert_eq!(locator.locate(false), None);
+
+        // the default background in some distributions is not a file
+        let gsettings = fake_gsettings(&dir, "", None);
+        assert_eq!(locator_at(&dir, gsettings, "").locate(false), None);
+    }
+
+    #[test]
+    fn uris() {
+        assert_eq!(
+            uri_to_path("file:///a%20b/%C3%A9.jpg"),
+            Some(PathBuf::from("/a b/é.jpg"))
+        );
+        assert_eq!(
+            uri_to_path("/plain/path.png"),
+            Some(PathBuf::from("/plain/path.png"))
+        );
+        assert_eq!(uri_to_path("relative.png"), None);
+        assert_eq!(uri_to_path("file:///bad%2"), None);
+        assert_eq!(uri_to_path("file:///"), None);
+    }
+}
@@ -0,0 +1,354 @@
+// Copyright 2019-2022 Tauri Programme within The Commons Conservancy
+// SPDX-License-Identifier: Apache-2.0
+// SPDX-License-Identifier: MIT
+
+// KWin's blur and background contrast on Wayland, the protocols `KWindowEffects` uses.
+// The surface is reached through the toolkit's own `wl_display`, on an event queue of ours
+// so the toolkit's dispatching is left alone.
+// https://invent.kde.org/libraries/plasma-wayland-protocols/-/blob/master/src/protocols/blur.xml
+// https://invent.kde.org/libraries/plasma-wayland-protocols/-/blob/master/src/protocols/contrast.xml
+
+use std::{cell::RefCell, collections::HashMap, ffi::c_void, ptr::NonNull, rc::Rc};
+
+use wayland_client::{
+    backend::{Backend, ObjectId},
+    delegate_noop,
+    globals::{registry_queue_init, GlobalList, GlobalListContents},
+    protocol::{
+        wl_compositor::WlCompositor, wl_region::WlRegion, wl_registry, wl_surface::WlSurface,
+    },
+    Connection, Dispatch, EventQueue, Proxy, QueueHandle,
+};
+use wayland_protocols_plasma::{
+    blur::client::{
+        org_kde_kwin_blur::OrgKdeKwinBlur, org_kde_kwin_blur_manager::OrgKdeKwinBlurManager,
+    },
+    contrast::client::{
+        org_kde_kwin_contrast::OrgKdeKwinContrast,
+        org_kde_kwin_contrast_manager::OrgKdeKwinContrastManager,
+    },
+};
+
+use crate::{
+    region::{self, Rect},
+    BackgroundContrast, Error,
+};
+
+const BLUR_MANAGER: &str = "org_kde_kwin_blur_manager";
+
+/// The registry of a toolkit's connection and the globals bound on it.
+///
+/// Neither `wl_registry`, `wl_compositor` nor KWin's managers have a destructor, they
+/// live until the toolkit disconnects. They are bound once per connection and kept for
+/// the next calls, so re-applying effects on every resize doesn't pile them up.
+struct Globals {
+    conn: Connection,
+    queue: EventQueue<State>,
+    list: GlobalList,
+    compositor: Option<WlCompositor>,
+    blur_manager: Option<OrgKdeKwinBlurManager>,
+    contrast_manager: Option<OrgKdeKwinContrastManager>,
+}
+
+thread_local! {
+    /// The globals of each connection, keyed by its `wl_display`. Toolkits keep their
+    /// display until the application exits, an entry is dropped when its connection fails.
+    static CONNECTIONS: RefCell<HashMap<usize, Rc<RefCell<Globals>>>> =
+        RefCell::new(HashMap::new());
+}
+
+/// A native Wayland surface and the globals of its compositor.
+pub struct Wayland {
+    display: usize,
+    globals: Rc<RefCell<Globals>>,
+    surface: WlSurface,
+}
+
+impl Wayland {
+    /// Reaches `surface` through the connection of the toolkit that created it, listing
+    /// its globals the first time the connection is used.
+    ///
+    /// # Safety
+    ///
+    /// `surface` must point to a live `wl_surface` proxy.
+    pub unsafe fn from_surface(surface: NonNull<c_void>) -> Result<Self, Error> {
+        let display = proxy_display(surface)? as usize;
+        let cached = CONNECTIONS.with(|connections| connections.borrow().get(&display).cloned());
+        let globals = match cached {
+            Some(globals) => globals,
+            None => {
+                let conn =
+                    Connection::from_backend(Backend::from_foreign_display(display as *mut _));
+                let (list, queue) = registry_queue_init::<State>(&conn).map_err(|e| {
+                    Error::Compositor(format!("cannot list the Wayland globals: {}", e))
+                })?;
+                let globals = Rc::new(RefCell::new(Globals {
+                    conn,
+                    queue,
+                    list,
+                    compositor: None,
+                    blur_manager: None,
+                    contrast_manager: None,
+                }));
+                CONNECTIONS
+                    .with(|connections| connections.borrow_mut().insert(display, globals.clone()));
+                globals
+            }
+        };
+        let surface = ObjectId::from_ptr(WlSurface::interface(), surface.as_ptr() as *mut _)
+            .and_then(|id| WlSurface::from_id(&globals.borrow().conn, id))
+            .map_err(|e| Error::Compositor(format!("invalid wl_surface: {}", e)))?;
+        Ok(Self {
+            display,
+            globals,
+            surface,
+        })
+    }
+
+    pub fn is_blur_supported(&self) -> bool {
+        self.has_global(BLUR_MANAGER)
+    }
+
+    /// Whether the compositor announces `interface`.
+    pub fn has_global(&self, interface: &str) -> bool {
+        self.globals
+            .borrow()
+            .list
+            .contents()
+            .with_list(|globals| globals.iter().any(|global| global.interface == interface))
+    }
+
+    /// Blurs `rects` of the surface, or all of it when `rects` is `None`.
+    ///
+    /// The rest of the surface is declared opaque.
+    pub fn apply_blur(&mut self, rects: Option<&[Rect]>) -> Result<(), Error> {
+        let manager = self.blur_manager()?;
+        let region = match rects {
+            None => None,
+            Some(rects) => match self.region(rects)? {
+                Some(region) => Some(region),
+                None => return self.clear_blur(),
+            },
+        };
+
+        let blur = manager.create(&self.surface, &self.queue_handle(), ());
+        // no region means the whole surface
+        blur.set_region(region.as_ref());
+        blur.commit();
+        blur.release();
+        if let Some(region) = region {
+            region.destroy();
+        }
+        self.set_opaque_region(rects)?;
+        self.commit()
+    }
+
+    pub fn clear_blur(&mut self) -> Result<(), Error> {
+        self.blur_manager()?.unset(&self.surface);
+        self.set_opaque_region(None)?;
+        self.commit()
+    }
+
+    /// Marks everything outside of `translucent` as opaque, like [`X11::set_opaque_region`].
+    ///
+    /// The compositor clips the region to the surface, so it starts as big as possible and
+    /// the size of the surface isn't needed. `None` removes the opaque region: unlike X11
+    /// properties it can't be read back, toolkits that set one set it again on their next
+    /// frame.
+    ///
+    /// [`X11::set_opaque_region`]: super::x11::X11::set_opaque_region
+    fn set_opaque_region(&mut self, translucent: Option<&[Rect]>) -> Result<(), Error> {
+        let translucent = match translucent {
+            Some(translucent) => translucent,
+            None => {
+                self.surface.set_opaque_region(None);
+                return Ok(());
+            }
+        };
+        let region = self.compositor()?.create_region(&self.queue_handle(), ());
+        region.add(0, 0, i32::MAX, i32::MAX);
+        // rounded corners count as their whole bounding box, like `opaque_region`
+        for rect in translucent.iter().filter(|r| !r.is_empty()) {
+            let square = Rect::new(rect.x, rect.y, rect.width, rect.height);
+            for [x, y, width, height] in region::to_wayland_rects(&[square]) {
+                region.subtract(x, y, width, height);
+            }
+        }
+        self.surface.set_opaque_region(Some(&region));
+        region.destroy();
+        Ok(())
+    }
+
+    /// Adjusts the colors of what is seen through the whole surface, or removes the
+    /// adjustment. Does nothing on compositors without the protocol.
+    pub fn set_background_contrast(
+        &mut self,
+        contrast: Option<BackgroundContrast>,
+    ) -> Result<(), Error> {
+        if !self.has_global("org_kde_kwin_contrast_manager") {
+            return Ok(());
+        }
+        let manager = self.contrast_manager()?;
+        match contrast {
+            Some(values) => {
+                let contrast = manager.create(&self.surface, &self.queue_handle(), ());
+                contrast.set_region(None);
+                contrast.set_contrast(values.contrast.into());
+                contrast.set_intensity(values.intensity.into());
+                contrast.set_saturation(values.saturation.into());
+                contrast.commit();
+                contrast.release();
+            }
+            None => manager.unset(&self.surface),
+        }
+        self.commit()
+    }
+
+    /// A `wl_region` made of `rects`, or `None` if they are all empty.
+    fn region(&self, rects: &[Rect]) -> Result<Option<WlRegion>, Error> {
+        let rects = region::to_wayland_rects(rects);
+        if rects.is_empty() {
+            return Ok(None);
+        }
+        let region = self.compositor()?.create_region(&self.queue_handle(), ());
+        for [x, y, width, height] in rects {
+            region.add(x, y, width, height);
+        }
+        Ok(Some(region))
+    }
+
+    fn queue_handle(&self) -> QueueHandle<State> {
+        self.globals.borrow().queue.handle()
+    }
+
+    fn compositor(&self) -> Result<WlCompositor, Error> {
+        self.bind(|globals| &mut globals.compositor)
+    }
+
+    fn blur_manager(&self) -> Result<OrgKdeKwinBlurManager, Error> {
+        self.bind(|globals| &mut globals.blur_manager)
+    }
+
+    fn contrast_manager(&self) -> Result<OrgKdeKwinContrastManager, Error> {
+        self.bind(|globals| &mut globals.contrast_manager)
+    }
+
+    /// The global kept in `slot`, bound at version 1 the first time.
+    fn bind<I>(&self, slot: fn(&mut Globals) -> &mut Option<I>) -> Result<I, Error>
+    where
+        I: Proxy + Clone + 'static,
+        State: Dispatch<I, ()>,
+    {
+        let mut globals = self.globals.borrow_mut();
+        if let Some(bound) = slot(&mut globals) {
+            return Ok(bound.clone());
+        }
+        let bound: I = globals
+            .list
+            .bind(&globals.queue.handle(), 1..=1, ())
+            .map_err(|e| Error::Compositor(format!("{}: {}", I::interface().name, e)))?;
+        *slot(&mut globals) = Some(bound.clone());
+        Ok(bound)
+    }
+
+    /// Commits the surface, so the double-buffered state set on it takes effect, and waits
+    /// for the compositor to process it with a `wl_display.sync` round trip.
+    fn commit(&mut self) -> Result<(), Error> {
+        self.surface.commit();
+        let result = self.globals.borrow_mut().queue.roundtrip(&mut State);
+        result.map(|_| ()).map_err(|e| {
+            // the globals are gone with the connection
+            CONNECTIONS.with(|connections| connections.borrow_mut().remove(&self.display));
+            Error::Compositor(format!("Wayland connection: {}", e))
+        })
+    }
+}
+
+/// The `wl_display` a proxy belongs to.
+///
+/// `wl_proxy_get_display` only exists since libwayland-client 1.23, so it is looked up in
+/// the library the toolkit already loaded. Older versions get the pointer that follows the
+/// `wl_object` header of `struct wl_proxy`, a layout that hasn't changed since 1.0, and
+/// check that it points back to the display, whose object id is always 1.
+unsafe fn proxy_display(proxy: NonNull<c_void>) -> Result<*mut c_void, Error> {
+    #[repr(C)]
+    struct WlProxy {
+        interface: *const c_void,
+        implementation: *const c_void,
+        id: u32,
+        display: *mut WlProxy,
+    }
+
+    let lib = libc::dlopen(
+        b"libwayland-client.so.0\0".as_ptr().cast(),
+        libc::RTLD_NOW | libc::RTLD_NOLOAD,
+    );
+    if lib.is_null() {
+        return Err(Error::UnsupportedPlatform(
+            "libwayland-client is not loaded, the window doesn't seem to be a Wayland one.",
+        ));
+    }
+    let get_display = libc::dlsym(lib, b"wl_proxy_get_display\0".as_ptr().cast());
+    let display_interface = libc::dlsym(lib, b"wl_display_interface\0".as_ptr().cast());
+    libc::dlclose(lib);
+
+    if !get_display.is_null() {
+        let get_display: unsafe extern "C" fn(*mut c_void) -> *mut c_void =
+            std::mem::transmute(get_display);
+        return Ok(get_display(proxy.as_ptr()));
+    }
+    let display = (*proxy.as_ptr().cast::<WlProxy>()).display;
+    if display.is_null()
+        || !std::ptr::eq((*display).interface, display_interface)
+        || (*display).id != 1
+    {
+        return Err(Error::UnsupportedPlatformVersion(
+            "Cannot find the wl_display of the window, libwayland-client 1.23 or newer is required.",
+        ));
+    }
+    Ok(display.cast())
+}
+
+struct State;
+
+impl Dispatch<wl_registry::WlRegistry, GlobalListContents> for State {
+    fn event(
+        _: &mut Self,
+        _: &wl_registry::WlRegistry,
+        _: wl_registry::Event,
+        _: &GlobalListContents,
+        _: &Connection,
+        _: &QueueHandle<Self>,
+    ) {
+    }
+}
+
+delegate_noop!(State: WlCompositor);
+delegate_noop!(State: WlRegion);
+delegate_noop!(State: OrgKdeKwinBlurManager);
+delegate_noop!(State: OrgKdeKwinBlur);
+delegate_noop!(State: OrgKdeKwinContrastManager);
+delegate_noop!(State: OrgKdeKwinContrast);
+
+#[cfg(test)]
+mod tests {
+    use std::os::unix::net::UnixStream;
+
+    use wayland_client::protocol::wl_callback::WlCallback;
+
+    use super::*;
+
+    delegate_noop!(State: ignore WlCallback);
+
+    #[test]
+    fn finds_the_display_of_a_proxy() {
+        // libwayland only needs a socket to create proxies, requests are buffered
+        let (client, _server) = UnixStream::pair().unwrap();
+        let conn = Connection::from_socket(client).unwrap();
+        let queue = conn.new_event_queue::<State>();
+        let callback = conn.display().sync(&queue.handle(), ());
+        let proxy = NonNull::new(callback.id().as_ptr().cast()).unwrap();
+        let display = unsafe { proxy_display(proxy) }.unwrap();
+        assert_eq!(display, conn.backend().display_ptr().cast());
+    }
+}
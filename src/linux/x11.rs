@@ -0,0 +1,317 @@
+// Copyright 2019-2022 Tauri Programme within The Commons Conservancy
+// SPDX-License-Identifier: Apache-2.0
+// SPDX-License-Identifier: MIT
+
+use x11rb::{
+    connection::Connection,
+    protocol::xproto::{AtomEnum, ConnectionExt as _, GetGeometryReply, PropMode, Window},
+    rust_connection::RustConnection,
+    wrapper::ConnectionExt as _,
+};
+
+use crate::{
+    region::{self, Rect},
+    Error,
+};
+
+const OPAQUE_REGION: &str = "_NET_WM_OPAQUE_REGION";
+/// The opaque region the window had before we set one, as a presence flag followed by the
+/// region, so it can be restored.
+const SAVED_OPAQUE_REGION: &str = "_WINDOW_VIBRANCY_SAVED_OPAQUE_REGION";
+
+/// A connection to the X server the window lives on.
+///
+/// Window ids are global to the server, so our own connection can
+/// change properties of windows created through the toolkit's one.
+pub struct X11 {
+    conn: RustConnection,
+    root: Window,
+}
+
+impl X11 {
+    pub fn connect() -> Result<Self, Error> {
+        let (conn, screen) = x11rb::connect(None).map_err(x11_error)?;
+        let root = conn.setup().roots[screen].root;
+        Ok(Self { conn, root })
+    }
+
+    pub fn root(&self) -> Window {
+        self.root
+    }
+
+    pub fn atom(&self, name: &str) -> Result<u32, Error> {
+        Ok(self
+            .conn
+            .intern_atom(false, name.as_bytes())
+            .map_err(x11_error)?
+            .reply()
+            .map_err(x11_error)?
+            .atom)
+    }
+
+    /// Whether the root window carries the property `name`, which is how
+    /// compositors usually announce that they support a protocol.
+    pub fn root_has_property(&self, name: &str) -> Result<bool, Error> {
+        let atom = self
+            .conn
+            .intern_atom(true, name.as_bytes())
+            .map_err(x11_error)?
+            .reply()
+            .map_err(x11_error)?
+            .atom;
+        if atom == u32::from(AtomEnum::NONE) {
+            return Ok(false);
+        }
+        let properties = self
+            .conn
+            .list_properties(self.root)
+            .map_err(x11_error)?
+            .reply()
+            .map_err(x11_error)?;
+        Ok(properties.atoms.contains(&atom))
+    }
+
+    pub fn set_cardinals(&self, window: Window, name: &str, values: &[u32]) -> Result<(), Error> {
+        let atom = self.atom(name)?;
+        self.conn
+            .change_property32(PropMode::REPLACE, window, atom, AtomEnum::CARDINAL, values)
+            .map_err(x11_error)?;
+        self.sync()
+    }
+
+    /// Sets a text property, `kind` being `"UTF8_STRING"` or `"STRING"`.
+    pub fn set_string(
+        &self,
+        window: Window,
+        name: &str,
+        kind: &str,
+        value: &str,
+    ) -> Result<(), Error> {
+        let atom = self.atom(name)?;
+        let kind = self.atom(kind)?;
+        self.conn
+            .change_property8(PropMode::REPLACE, window, atom, kind, value.as_bytes())
+            .map_err(x11_error)?;
+        self.sync()
+    }
+
+    /// Reads a 32-bit property of any type, empty if it is not set.
+    pub fn get_cardinals(&self, window: Window, name: &str) -> Result<Vec<u32>, Error> {
+        Ok(self.get_property32(window, name)?.unwrap_or_default())
+    }
+
+    /// Reads a 32-bit property of any type, `None` if it is not set.
+    pub fn get_property32(&self, window: Window, name: &str) -> Result<Option<Vec<u32>>, Error> {
+        let atom = self.atom(name)?;
+        let reply = self
+            .conn
+            .get_property(false, window, atom, AtomEnum::ANY, 0, u32::MAX / 4)
+            .map_err(x11_error)?
+            .reply()
+            .map_err(x11_error)?;
+        Ok(reply.value32().map(Iterator::collect))
+    }
+
+    /// Reads a text property of any type, `None` if it is not set.
+    pub fn get_string(&self, window: Window, name: &str) -> Result<Option<String>, Error> {
+        let atom = self.atom(name)?;
+        let reply = self
+            .conn
+            .get_property(false, window, atom, AtomEnum::ANY, 0, u32::MAX / 4)
+            .map_err(x11_error)?
+            .reply()
+            .map_err(x11_error)?;
+        if reply.format != 8 {
+            return Ok(None);
+        }
+        Ok(Some(String::from_utf8_lossy(&reply.value).into_owned()))
+    }
+
+    /// Whether the window manager lists `name` in `_NET_SUPPORTED`, or sets it on the root window.
+    pub fn supports(&self, name: &str) -> Result<bool, Error> {
+        let atom = self.atom(name)?;
+        Ok(self
+            .get_cardinals(self.root, "_NET_SUPPORTED")?
+            .contains(&atom)
+            || self.root_has_property(name)?)
+    }
+
+    /// The name of the running window manager, as announced through `_NET_SUPPORTING_WM_CHECK`.
+    pub fn window_manager_name(&self) -> Result<Option<String>, Error> {
+        match self
+            .get_cardinals(self.root, "_NET_SUPPORTING_WM_CHECK")?
+            .first()
+        {
+            Some(&check) => self.get_string(check, "_NET_WM_NAME"),
+            None => Ok(None),
+        }
+    }
+
+    pub fn delete_property(&self, window: Window, name: &str) -> Result<(), Error> {
+        let atom = self.atom(name)?;
+        self.conn.delete_property(window, atom).map_err(x11_error)?;
+        self.sync()
+    }
+
+    pub fn window_size(&self, window: Window) -> Result<(u32, u32), Error> {
+        let geometry = self.geometry(window)?;
+        Ok((geometry.width as u32, geometry.height as u32))
+    }
+
+    /// The bit depth of the window's visual.
+    pub fn window_depth(&self, window: Window) -> Result<u8, Error> {
+        Ok(self.geometry(window)?.depth)
+    }
+
+    fn geometry(&self, window: Window) -> Result<GetGeometryReply, Error> {
+        self.conn
+            .get_geometry(window)
+            .map_err(x11_error)?
+            .reply()
+            .map_err(x11_error)
+    }
+
+    /// Marks everything outside of `translucent` as opaque, so the compositor
+    /// can skip drawing what is below those parts of an ARGB window.
+    ///
+    /// `None` gives the window back the opaque region it had before, usually none, which means
+    /// the whole window is translucent. `size` is queried from the server if not given.
+    pub fn set_opaque_region(
+        &self,
+        window: Window,
+        translucent: Option<&[Rect]>,
+        size: Option<(u32, u32)>,
+    ) -> Result<(), Error> {
+        let translucent = match translucent {
+            Some(translucent) => translucent,
+            None => return self.restore_opaque_region(window),
+        };
+        if self.get_property32(window, SAVED_OPAQUE_REGION)?.is_none() {
+            let saved = match self.get_property32(window, OPAQUE_REGION)? {
+                Some(region) => std::iter::once(1).chain(region).collect(),
+                None => vec![0],
+            };
+            self.set_cardinals(window, SAVED_OPAQUE_REGION, &saved)?;
+        }
+        let (width, height) = match size {
+            Some(size) => size,
+            None => self.window_size(window)?,
+        };
+        let opaque = region::opaque_region(width, height, translucent);
+        self.set_cardinals(
+            window,
+            OPAQUE_REGION,
+            &region::to_x11_cardinals(&opaque, width, height),
+        )
+    }
+
+    fn restore_opaque_region(&self, window: Window) -> Result<(), Error> {
+        let saved = match self.get_property32(window, SAVED_OPAQUE_REGION)? {
+            Some(saved) => saved,
+            None => return Ok(()),
+        };
+        match saved.split_first() {
+            Some((1, region)) => self.set_cardinals(window, OPAQUE_REGION, region)?,
+            _ => self.delete_property(window, OPAQUE_REGION)?,
+        }
+        self.delete_property(window, SAVED_OPAQUE_REGION)
+    }
+
+    #[cfg(feature = "rules")]
+    pub fn atom_name(&self, atom: u32) -> Result<String, Error> {
+        let reply = self
+            .conn
+            .get_atom_name(atom)
+            .map_err(x11_error)?
+            .reply()
+            .map_err(x11_error)?;
+        Ok(String::from_utf8_lossy(&reply.name).into_owned())
+    }
+
+    /// Asks for the `mask` events of `window`, see [`X11::wait_for_event`].
+    pub fn select_events(
+        &self,
+        window: Window,
+        mask: x11rb::protocol::xproto::EventMask,
+    ) -> Result<(), Error> {
+        let attributes = x11rb::protocol::xproto::ChangeWindowAttributesAux::new().event_mask(mask);
+        self.conn
+            .change_window_attributes(window, &attributes)
+            .map_err(x11_error)?
+            .check()
+            .map_err(x11_error)
+    }
+
+    #[cfg(feature = "rules")]
+    pub fn wait_for_event(&self) -> Result<x11rb::protocol::Event, Error> {
+        self.conn.wait_for_event().map_err(x11_error)
+    }
+
+    /// The next event already received, without waiting for one.
+    pub fn poll_for_event(&self) -> Result<Option<x11rb::protocol::Event>, Error> {
+        self.conn.poll_for_event().map_err(x11_error)
+    }
+
+    /// Waits for the server to process our requests, with a `GetInputFocus` round trip, so
+    /// changes are applied when the calls return.
+    fn sync(&self) -> Result<(), Error> {
+        self.conn.sync().map_err(x11_error)
+    }
+}
+
+/// Windows made as other clients would, for the tests that need an X server, like `Xvfb`.
+///
+/// Run them with `DISPLAY=:99 cargo test --features rules -- --ignored` once `Xvfb :99` started.
+#[cfg(test)]
+impl X11 {
+    /// Creates and maps a window, and adds it to `_NET_CLIENT_LIST` as a window manager would.
+    ///
+    /// The root window also gets `_KDE_NET_WM_BLUR_BEHIND_REGION`, so the server passes for
+    /// KWin. The window lives as long as this connection.
+    pub fn create_test_window(&self, class: &str, title: &str) -> Result<Window, Error> {
+        use x11rb::protocol::xproto::{CreateWindowAux, WindowClass};
+
+        let window = self.conn.generate_id().map_err(x11_error)?;
+        self.conn
+            .create_window(
+                x11rb::COPY_DEPTH_FROM_PARENT,
+                window,
+                self.root,
+                0,
+                0,
+                64,
+                48,
+                0,
+                WindowClass::INPUT_OUTPUT,
+                x11rb::COPY_FROM_PARENT,
+                &CreateWindowAux::new(),
+            )
+            .map_err(x11_error)?;
+        self.set_string(window, "WM_CLASS", "STRING", &format!("{0}\0{0}\0", class))?;
+        self.set_string(window, "_NET_WM_NAME", "UTF8_STRING", title)?;
+        self.set_cardinals(window, "_NET_WM_PID", &[std::process::id()])?;
+        self.conn.map_window(window).map_err(x11_error)?;
+
+        // appending keeps the windows of tests running in parallel
+        let client_list = self.atom("_NET_CLIENT_LIST")?;
+        self.conn
+            .change_property32(
+                PropMode::APPEND,
+                self.root,
+                client_list,
+                AtomEnum::WINDOW,
+                &[window],
+            )
+            .map_err(x11_error)?;
+        let blur = self.atom("_KDE_NET_WM_BLUR_BEHIND_REGION")?;
+        self.conn
+            .change_property32(PropMode::APPEND, self.root, blur, AtomEnum::CARDINAL, &[])
+            .map_err(x11_error)?;
+        self.sync()?;
+        Ok(window)
+    }
+}
+
+fn x11_error(e: impl std::fmt::Display) -> Error {
+    Error::Compositor(format!("X11: {}", e))
+}
@@ -5,10 +5,13 @@
 // The use of NSVisualEffectView comes from https://github.com/joboet/winit/tree/macos_blurred_background
 // with a bit of rewrite by @youngsing to make it more like cocoa::appkit style.
 
-/// <https://developer.apple.com/documentation/appkit/nsvisualeffectview/material>
+use std::{fmt, str::FromStr};
+
+use crate::Error;
 
+/// <https://developer.apple.com/documentation/appkit/nsvisualeffectview/material>
 #[repr(u64)]
-#[derive(Clone, Copy, Debug, PartialEq)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
 pub enum NSVisualEffectMaterial {
     #[deprecated(
         since = "macOS 10.14",
@@ -59,7 +62,7 @@ pub enum NSVisualEffectMaterial {
 /// <https://developer.apple.com/documentation/appkit/nsvisualeffectview/state>
 #[allow(dead_code)]
 #[repr(u64)]
-#[derive(Clone, Copy, Debug, PartialEq)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
 pub enum NSVisualEffectState {
     /// Make window vibrancy state follow the window's active state
     FollowsWindowActiveState = 0,
@@ -69,25 +72,433 @@ pub enum NSVisualEffectState {
     Inactive = 2,
 }
 
+/// The `NSAppKitVersionNumber` of the first release of each macOS version materials need.
+const APPKIT_VERSIONS: [((u32, u32), f64); 3] =
+    [((10, 10), 1343.0), ((10, 11), 1404.0), ((10, 14), 1671.0)];
+
+#[allow(deprecated)]
+impl NSVisualEffectMaterial {
+    /// The first macOS version with this material, as `(major, minor)`.
+    ///
+    /// | Materials                                                          | macOS |
+    /// |--------------------------------------------------------------------|-------|
+    /// | `AppearanceBased`, `Light`, `Dark`, `Titlebar`, `Selection`        | 10.10 |
+    /// | `MediumLight`, `UltraDark`, `Menu`, `Popover`, `Sidebar`           | 10.11 |
+    /// | `HeaderView`, `Sheet`, `WindowBackground`, `HudWindow`, `FullScreenUI`, `Tooltip`, `ContentBackground`, `UnderWindowBackground`, `UnderPageBackground` | 10.14 |
+    pub fn min_macos_version(self) -> (u32, u32) {
+        use NSVisualEffectMaterial::*;
+        match self {
+            AppearanceBased | Light | Dark | Titlebar | Selection => (10, 10),
+            MediumLight | UltraDark | Menu | Popover | Sidebar => (10, 11),
+            HeaderView
+            | Sheet
+            | WindowBackground
+            | HudWindow
+            | FullScreenUI
+            | Tooltip
+            | ContentBackground
+            | UnderWindowBackground
+            | UnderPageBackground => (10, 14),
+        }
+    }
+
+    /// Whether macOS 10.14 deprecated this material in favor of the semantic ones.
+    pub fn is_deprecated(self) -> bool {
+        use NSVisualEffectMaterial::*;
+        matches!(
+            self,
+            AppearanceBased | Light | Dark | MediumLight | UltraDark
+        )
+    }
+
+    /// The closest material of an older macOS, used when this one is not available.
+    fn fallback(self) -> Option<Self> {
+        use NSVisualEffectMaterial::*;
+        match self {
+            AppearanceBased | Light | Dark | Titlebar | Selection => None,
+            MediumLight => Some(Light),
+            UltraDark => Some(Dark),
+            HeaderView => Some(Titlebar),
+            HudWindow | FullScreenUI => Some(UltraDark),
+            Tooltip => Some(Popover),
+            Menu
+            | Popover
+            | Sidebar
+            | Sheet
+            | WindowBackground
+            | ContentBackground
+            | UnderWindowBackground
+            | UnderPageBackground => Some(AppearanceBased),
+        }
+    }
+}
+
+/// Whether [`resolve_material`] had to replace the requested material.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Fallback {
+    /// The requested material is used.
+    None,
+    /// The requested material is newer than the running macOS, another one is used.
+    Substituted {
+        requested: NSVisualEffectMaterial,
+        /// See [`NSVisualEffectMaterial::min_macos_version`].
+        min_macos_version: (u32, u32),
+    },
+}
+
+/// The material [`apply_vibrancy`](crate::apply_vibrancy) uses for `requested` on the AppKit
+/// version `appkit_version`, the value of `NSAppKitVersionNumber`.
+///
+/// Materials newer than the running macOS are replaced by the closest older one, like
+/// `HeaderView` by `Titlebar`, or `AppearanceBased` when there is no closer one.
+///
+/// ```
+/// # #![allow(deprecated)]
+/// use window_vibrancy::{resolve_material, Fallback, NSVisualEffectMaterial};
+///
+/// // macOS 10.13
+/// assert_eq!(
+///     resolve_material(NSVisualEffectMaterial::HeaderView, 1561.0),
+///     (
+///         NSVisualEffectMaterial::Titlebar,
+///         Fallback::Substituted {
+///             requested: NSVisualEffectMaterial::HeaderView,
+///             min_macos_version: (10, 14),
+///         }
+///     )
+/// );
+/// // macOS 10.10, where the fallback of `Tooltip`, `Popover`, is not available either
+/// assert_eq!(
+///     resolve_material(NSVisualEffectMaterial::Tooltip, 1343.0).0,
+///     NSVisualEffectMaterial::AppearanceBased
+/// );
+/// // macOS 14
+/// assert_eq!(
+///     resolve_material(NSVisualEffectMaterial::HudWindow, 2487.0),
+///     (NSVisualEffectMaterial::HudWindow, Fallback::None)
+/// );
+/// ```
+pub fn resolve_material(
+    requested: NSVisualEffectMaterial,
+    appkit_version: f64,
+) -> (NSVisualEffectMaterial, Fallback) {
+    let is_available = |material: NSVisualEffectMaterial| {
+        let min = material.min_macos_version();
+        APPKIT_VERSIONS
+            .iter()
+            .find(|(version, _)| *version == min)
+            .map_or(false, |(_, appkit)| appkit_version >= *appkit)
+    };
+
+    let mut material = requested;
+    while !is_available(material) {
+        match material.fallback() {
+            Some(fallback) => material = fallback,
+            // older than macOS 10.10, where nothing is available
+            None => break,
+        }
+    }
+    if material == requested {
+        (material, Fallback::None)
+    } else {
+        (
+            material,
+            Fallback::Substituted {
+                requested,
+                min_macos_version: requested.min_macos_version(),
+            },
+        )
+    }
+}
+
+#[allow(deprecated)]
+impl NSVisualEffectMaterial {
+    /// Every material, in the order of their raw values.
+    pub const ALL: [NSVisualEffectMaterial; 19] = [
+        NSVisualEffectMaterial::AppearanceBased,
+        NSVisualEffectMaterial::Light,
+        NSVisualEffectMaterial::Dark,
+        NSVisualEffectMaterial::Titlebar,
+        NSVisualEffectMaterial::Selection,
+        NSVisualEffectMaterial::Menu,
+        NSVisualEffectMaterial::Popover,
+        NSVisualEffectMaterial::Sidebar,
+        NSVisualEffectMaterial::MediumLight,
+        NSVisualEffectMaterial::UltraDark,
+        NSVisualEffectMaterial::HeaderView,
+        NSVisualEffectMaterial::Sheet,
+        NSVisualEffectMaterial::WindowBackground,
+        NSVisualEffectMaterial::HudWindow,
+        NSVisualEffectMaterial::FullScreenUI,
+        NSVisualEffectMaterial::Tooltip,
+        NSVisualEffectMaterial::ContentBackground,
+        NSVisualEffectMaterial::UnderWindowBackground,
+        NSVisualEffectMaterial::UnderPageBackground,
+    ];
+
+    /// The kebab-case name, like `hud-window`, which [`Display`](fmt::Display) prints.
+    pub fn name(self) -> &'static str {
+        use NSVisualEffectMaterial::*;
+        match self {
+            AppearanceBased => "appearance-based",
+            Light => "light",
+            Dark => "dark",
+            Titlebar => "titlebar",
+            Selection => "selection",
+            Menu => "menu",
+            Popover => "popover",
+            Sidebar => "sidebar",
+            MediumLight => "medium-light",
+            UltraDark => "ultra-dark",
+            HeaderView => "header-view",
+            Sheet => "sheet",
+            WindowBackground => "window-background",
+            HudWindow => "hud-window",
+            FullScreenUI => "full-screen-ui",
+            Tooltip => "tooltip",
+            ContentBackground => "content-background",
+            UnderWindowBackground => "under-window-background",
+            UnderPageBackground => "under-page-background",
+        }
+    }
+
+    /// The name in AppKit, like `HUDWindow` for `NSVisualEffectMaterialHUDWindow`.
+    pub fn apple_name(self) -> &'static str {
+        use NSVisualEffectMaterial::*;
+        match self {
+            AppearanceBased => "AppearanceBased",
+            Light => "Light",
+            Dark => "Dark",
+            Titlebar => "Titlebar",
+            Selection => "Selection",
+            Menu => "Menu",
+            Popover => "Popover",
+            Sidebar => "Sidebar",
+            MediumLight => "MediumLight",
+            UltraDark => "UltraDark",
+            HeaderView => "HeaderView",
+            Sheet => "Sheet",
+            WindowBackground => "WindowBackground",
+            HudWindow => "HUDWindow",
+            FullScreenUI => "FullScreenUI",
+            Tooltip => "ToolTip",
+            ContentBackground => "ContentBackground",
+            UnderWindowBackground => "UnderWindowBackground",
+            UnderPageBackground => "UnderPageBackground",
+        }
+    }
+}
+
+impl fmt::Display for NSVisualEffectMaterial {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// Parses the kebab-case or AppKit name, ignoring case, optionally prefixed by
+/// `NSVisualEffectMaterial`.
+///
+/// ```
+/// use window_vibrancy::NSVisualEffectMaterial;
+///
+/// assert_eq!("hud-window".parse::<NSVisualEffectMaterial>().unwrap(), NSVisualEffectMaterial::HudWindow);
+/// assert_eq!("HUDWindow".parse::<NSVisualEffectMaterial>().unwrap(), NSVisualEffectMaterial::HudWindow);
+/// assert_eq!(NSVisualEffectMaterial::HudWindow.to_string(), "hud-window");
+/// ```
+impl FromStr for NSVisualEffectMaterial {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Error> {
+        let name = s.strip_prefix("NSVisualEffectMaterial").unwrap_or(s);
+        Self::ALL
+            .iter()
+            .copied()
+            .find(|material| same_name(name, material.name()))
+            .ok_or_else(|| Error::Config(format!("unknown material \"{}\"", s)))
+    }
+}
+
+impl TryFrom<u64> for NSVisualEffectMaterial {
+    type Error = Error;
+
+    fn try_from(value: u64) -> Result<Self, Error> {
+        Self::ALL
+            .iter()
+            .copied()
+            .find(|material| *material as u64 == value)
+            .ok_or_else(|| Error::Config(format!("unknown material value {}", value)))
+    }
+}
+
+impl NSVisualEffectState {
+    /// Every state, in the order of their raw values.
+    pub const ALL: [NSVisualEffectState; 3] = [
+        NSVisualEffectState::FollowsWindowActiveState,
+        NSVisualEffectState::Active,
+        NSVisualEffectState::Inactive,
+    ];
+
+    /// The kebab-case name, like `follows-window-active-state`, which [`Display`](fmt::Display) prints.
+    pub fn name(self) -> &'static str {
+        match self {
+            NSVisualEffectState::FollowsWindowActiveState => "follows-window-active-state",
+            NSVisualEffectState::Active => "active",
+            NSVisualEffectState::Inactive => "inactive",
+        }
+    }
+
+    /// The name in AppKit, like `FollowsWindowActiveState` for
+    /// `NSVisualEffectStateFollowsWindowActiveState`.
+    pub fn apple_name(self) -> &'static str {
+        match self {
+            NSVisualEffectState::FollowsWindowActiveState => "FollowsWindowActiveState",
+            NSVisualEffectState::Active => "Active",
+            NSVisualEffectState::Inactive => "Inactive",
+        }
+    }
+}
+
+impl fmt::Display for NSVisualEffectState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// Parses the kebab-case or AppKit name, ignoring case, optionally prefixed by
+/// `NSVisualEffectState`.
+impl FromStr for NSVisualEffectState {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Error> {
+        let name = s.strip_prefix("NSVisualEffectState").unwrap_or(s);
+        Self::ALL
+            .iter()
+            .copied()
+            .find(|state| same_name(name, state.name()))
+            .ok_or_else(|| Error::Config(format!("unknown state \"{}\"", s)))
+    }
+}
+
+impl TryFrom<u64> for NSVisualEffectState {
+    type Error = Error;
+
+    fn try_from(value: u64) -> Result<Self, Error> {
+        Self::ALL
+            .iter()
+            .copied()
+            .find(|state| *state as u64 == value)
+            .ok_or_else(|| Error::Config(format!("unknown state value {}", value)))
+    }
+}
+
+/// Compares `name` to a kebab-case name, ignoring case and dashes, so `HUDWindow` is `hud-window`.
+fn same_name(name: &str, kebab: &str) -> bool {
+    let mut name = name.chars().filter(|c| !matches!(c, '-' | '_'));
+    let mut kebab = kebab.chars().filter(|c| *c != '-');
+    loop {
+        match (name.next(), kebab.next()) {
+            (Some(a), Some(b)) if a.eq_ignore_ascii_case(&b) => {}
+            (None, None) => return true,
+            _ => return false,
+        }
+    }
+}
+
 #[cfg(target_os = "macos")]
-pub use internal::apply_vibrancy;
+pub use internal::{
+    apply_blur_region, apply_vibrancy, diagnose, set_dark_mode, set_opacity, window_state,
+};
 
 #[cfg(target_os = "macos")]
 mod internal {
-    use super::{NSVisualEffectMaterial, NSVisualEffectState};
+    use std::{
+        ffi::c_void,
+        panic::{self, AssertUnwindSafe},
+        thread,
+    };
+
+    use super::{Fallback, NSVisualEffectMaterial, NSVisualEffectState};
 
     use cocoa::{
         appkit::{
-            NSAppKitVersionNumber, NSAppKitVersionNumber10_10, NSAppKitVersionNumber10_11,
-            NSAutoresizingMaskOptions, NSView, NSViewHeightSizable, NSViewWidthSizable,
-            NSWindowOrderingMode,
+            NSAppKitVersionNumber, NSAppKitVersionNumber10_10, NSAutoresizingMaskOptions, NSView,
+            NSViewHeightSizable, NSViewWidthSizable, NSWindowOrderingMode,
         },
-        base::{id, nil, BOOL},
-        foundation::{NSAutoreleasePool, NSPoint, NSRect, NSSize},
+        base::{id, nil, BOOL, YES},
+        foundation::{NSArray, NSAutoreleasePool, NSPoint, NSRect, NSSize, NSString},
     };
     use objc::{class, msg_send, sel, sel_impl};
 
-    use crate::Error;
+    use crate::{
+        diagnostics::{DisplayServer, Report},
+        region::Rect,
+        trace::{trace_debug, trace_warn},
+        Color, Error, WindowState,
+    };
+
+    /// Identifies the views added by `apply_blur_region` so they can be replaced.
+    const REGION_VIEW_IDENTIFIER: &str = "window-vibrancy-region";
+
+    #[repr(C)]
+    struct DispatchQueue {
+        _private: [u8; 0],
+    }
+
+    #[link(name = "System", kind = "dylib")]
+    extern "C" {
+        /// The queue `dispatch_get_main_queue()` returns, it is a macro.
+        static _dispatch_main_q: DispatchQueue;
+        fn dispatch_sync_f(
+            queue: *const DispatchQueue,
+            context: *mut c_void,
+            work: extern "C" fn(*mut c_void),
+        );
+    }
+
+    /// Runs `f` on the main thread, which AppKit requires, and waits for it.
+    ///
+    /// From other threads `f` is queued on the main dispatch queue, so the main thread must
+    /// be running its event loop, and not be waiting for the calling thread.
+    fn on_main_thread<F, T>(f: F) -> T
+    where
+        F: FnOnce() -> T,
+    {
+        let is_main_thread: BOOL = unsafe { msg_send![class!(NSThread), isMainThread] };
+        if is_main_thread == YES {
+            return f();
+        }
+
+        struct Context<F, T> {
+            f: Option<F>,
+            result: Option<thread::Result<T>>,
+        }
+
+        extern "C" fn work<F: FnOnce() -> T, T>(context: *mut c_void) {
+            let context = unsafe { &mut *context.cast::<Context<F, T>>() };
+            if let Some(f) = context.f.take() {
+                // unwinding through libdispatch is undefined, the panic is resumed below
+                context.result = Some(panic::catch_unwind(AssertUnwindSafe(f)));
+            }
+        }
+
+        let mut context = Context {
+            f: Some(f),
+            result: None,
+        };
+        // the calling thread is blocked until `work` returned, so `f` may borrow from it
+        unsafe {
+            dispatch_sync_f(
+                &_dispatch_main_q,
+                (&mut context as *mut Context<F, T>).cast(),
+                work::<F, T>,
+            );
+        }
+        match context.result {
+            Some(Ok(result)) => result,
+            Some(Err(payload)) => panic::resume_unwind(payload),
+            None => unreachable!("dispatch_sync_f returned before running the task"),
+        }
+    }
 
     #[allow(deprecated)]
     pub fn apply_vibrancy(
@@ -95,27 +506,31 @@ mod internal {
         appearance: NSVisualEffectMaterial,
         state: Option<NSVisualEffectState>,
         radius: Option<f64>,
-    ) -> Result<(), Error> {
-        unsafe {
+    ) -> Result<Fallback, Error> {
+        on_main_thread(|| unsafe {
             if NSAppKitVersionNumber < NSAppKitVersionNumber10_10 {
-                eprintln!("\"NSVisualEffectView\" is only available on macOS 10.10 or newer");
+                trace_warn!("\"NSVisualEffectView\" is only available on macOS 10.10 or newer");
                 return Err(Error::UnsupportedPlatformVersion(
                     "\"apply_vibrancy()\" is only available on macOS 10.0 or newer.",
                 ));
             }
 
-            if !msg_send![class!(NSThread), isMainThread] {
-                return Err(Error::NotMainThread(
-                    "\"apply_vibrancy()\" can only be used on the main thread.",
-                ));
-            }
-
-            let mut m = appearance;
-            if (appearance as u32 > 9 && NSAppKitVersionNumber < NSAppKitVersionNumber10_14)
-                || (appearance as u32 > 4 && NSAppKitVersionNumber < NSAppKitVersionNumber10_11)
-            {
-                m = NSVisualEffectMaterial::AppearanceBased;
+            let (m, fallback) = super::resolve_material(appearance, NSAppKitVersionNumber);
+            if fallback != Fallback::None {
+                trace_warn!(
+                    "{:?} is not available on AppKit {}, falling back to {:?}",
+                    appearance,
+                    NSAppKitVersionNumber,
+                    m
+                );
+            } else if m.is_deprecated() && NSAppKitVersionNumber >= NSAppKitVersionNumber10_14 {
+                trace_warn!("{:?} is deprecated since macOS 10.14", m);
             }
+            trace_debug!(
+                "adding an NSVisualEffectView with {:?} on AppKit {}",
+                m,
+                NSAppKitVersionNumber
+            );
 
             let bounds = NSView::bounds(ns_view);
             let blurred_view =
@@ -132,8 +547,232 @@ mod internal {
             );
 
             let _: () = msg_send![ns_view, addSubview: blurred_view positioned: NSWindowOrderingMode::NSWindowBelow relativeTo: 0];
+            Ok(fallback)
+        })
+    }
+
+    #[allow(deprecated)]
+    pub fn apply_blur_region(
+        ns_view: id,
+        rects: &[Rect],
+        tint: Option<Color>,
+    ) -> Result<(), Error> {
+        on_main_thread(|| {
+            unsafe {
+                if NSAppKitVersionNumber < NSAppKitVersionNumber10_10 {
+                    return Err(Error::UnsupportedPlatformVersion(
+                        "\"apply_blur_region()\" is only available on macOS 10.10 or newer.",
+                    ));
+                }
+
+                let identifier = NSString::alloc(nil).init_str(REGION_VIEW_IDENTIFIER);
+                identifier.autorelease();
+
+                // drop the views of a previous call, `subviews` is a copy so it is safe to mutate while iterating
+                let subviews: id = msg_send![ns_view, subviews];
+                for i in 0..subviews.count() {
+                    let subview = subviews.objectAtIndex(i);
+                    let subview_identifier: id = msg_send![subview, identifier];
+                    if subview_identifier != nil {
+                        let same: BOOL = msg_send![subview_identifier, isEqualToString: identifier];
+                        if same == YES {
+                            NSVisualEffectView::removeFromSuperview(subview);
+                        }
+                    }
+                }
+
+                // rects are in physical pixels from the top-left corner, views are in points
+                let bounds = NSView::bounds(ns_view);
+                let window: id = msg_send![ns_view, window];
+                let scale: f64 = if window == nil {
+                    1.0
+                } else {
+                    msg_send![window, backingScaleFactor]
+                };
+                let flipped: BOOL = msg_send![ns_view, isFlipped];
+
+                let (material, _) = super::resolve_material(
+                    NSVisualEffectMaterial::UnderWindowBackground,
+                    NSAppKitVersionNumber,
+                );
+
+                for rect in rects.iter().filter(|r| !r.is_empty()) {
+                    let size = NSSize::new(rect.width as f64 / scale, rect.height as f64 / scale);
+                    let x = rect.x as f64 / scale;
+                    let y = if flipped == YES {
+                        rect.y as f64 / scale
+                    } else {
+                        bounds.size.height - rect.y as f64 / scale - size.height
+                    };
+                    let frame = NSRect::new(NSPoint::new(x, y), size);
+                    let radius = rect.effective_radius() as f64 / scale;
+
+                    let blurred_view =
+                        NSVisualEffectView::initWithFrame_(NSVisualEffectView::alloc(nil), frame);
+                    blurred_view.autorelease();
+
+                    blurred_view.setMaterial_(material);
+                    blurred_view.setCornerRadius_(radius);
+                    blurred_view.setBlendingMode_(NSVisualEffectBlendingMode::BehindWindow);
+                    blurred_view.setState_(NSVisualEffectState::FollowsWindowActiveState);
+                    let _: () = msg_send![blurred_view, setIdentifier: identifier];
+
+                    if let Some(tint) = tint {
+                        let tint_frame = NSRect::new(NSPoint::new(0.0, 0.0), size);
+                        let tint_view: id = msg_send![class!(NSView), alloc];
+                        let tint_view: id = msg_send![tint_view, initWithFrame: tint_frame];
+                        tint_view.autorelease();
+                        let _: () = msg_send![tint_view, setWantsLayer: YES];
+                        let color: id = msg_send![class!(NSColor), colorWithSRGBRed: tint.0 as f64 / 255.0
+                                                                 green: tint.1 as f64 / 255.0
+                                                                 blue: tint.2 as f64 / 255.0
+                                                                 alpha: tint.3 as f64 / 255.0];
+                        let cg_color: *const std::ffi::c_void = msg_send![color, CGColor];
+                        let layer: id = msg_send![tint_view, layer];
+                        let _: () = msg_send![layer, setBackgroundColor: cg_color];
+                        let _: () = msg_send![layer, setCornerRadius: radius];
+                        NSVisualEffectView::setAutoresizingMask_(
+                            tint_view,
+                            NSViewWidthSizable | NSViewHeightSizable,
+                        );
+                        let _: () = msg_send![blurred_view, addSubview: tint_view];
+                    }
+
+                    let _: () = msg_send![ns_view, addSubview: blurred_view positioned: NSWindowOrderingMode::NSWindowBelow relativeTo: 0];
+                }
+            }
+            Ok(())
+        })
+    }
+
+    pub fn set_dark_mode(ns_view: id, dark: Option<bool>) -> Result<(), Error> {
+        on_main_thread(|| {
+            unsafe {
+                if NSAppKitVersionNumber < NSAppKitVersionNumber10_14 {
+                    return Err(Error::UnsupportedPlatformVersion(
+                        "\"set_dark_mode()\" is only available on macOS 10.14 or newer.",
+                    ));
+                }
+
+                let window: id = msg_send![ns_view, window];
+                if window == nil {
+                    return Err(Error::UnsupportedPlatform(
+                        "\"set_dark_mode()\" needs a view that is part of a window.",
+                    ));
+                }
+
+                // a nil appearance makes the window follow the system one
+                let appearance: id = match dark {
+                    Some(dark) => {
+                        let name = NSString::alloc(nil).init_str(if dark {
+                            "NSAppearanceNameDarkAqua"
+                        } else {
+                            "NSAppearanceNameAqua"
+                        });
+                        name.autorelease();
+                        msg_send![class!(NSAppearance), appearanceNamed: name]
+                    }
+                    None => nil,
+                };
+                let _: () = msg_send![window, setAppearance: appearance];
+            }
+            Ok(())
+        })
+    }
+
+    pub fn set_opacity(ns_view: id, opacity: Option<f64>) -> Result<(), Error> {
+        on_main_thread(|| {
+            unsafe {
+                let window: id = msg_send![ns_view, window];
+                if window == nil {
+                    return Err(Error::UnsupportedPlatform(
+                        "\"set_opacity()\" needs a view that is part of a window.",
+                    ));
+                }
+                let alpha = opacity.map_or(1.0, |opacity| opacity.clamp(0.0, 1.0));
+                let _: () = msg_send![window, setAlphaValue: alpha];
+            }
+            Ok(())
+        })
+    }
+
+    pub fn window_state(ns_view: id) -> Result<WindowState, Error> {
+        on_main_thread(|| {
+            // NSWindowStyleMaskFullScreen
+            const FULL_SCREEN: u64 = 1 << 14;
+
+            unsafe {
+                let window: id = msg_send![ns_view, window];
+                if window == nil {
+                    return Err(Error::UnsupportedPlatform(
+                        "\"window_state()\" needs a view that is part of a window.",
+                    ));
+                }
+                let style_mask: u64 = msg_send![window, styleMask];
+                let zoomed: BOOL = msg_send![window, isZoomed];
+                Ok(WindowState {
+                    maximized: zoomed == YES,
+                    fullscreen: style_mask & FULL_SCREEN != 0,
+                })
+            }
+        })
+    }
+
+    pub fn diagnose(ns_view: id, report: &mut Report) {
+        unsafe {
+            let process_info: id = msg_send![class!(NSProcessInfo), processInfo];
+            let version: id = msg_send![process_info, operatingSystemVersionString];
+            report.os = Some(format!(
+                "macOS {} (AppKit {})",
+                to_string(version),
+                NSAppKitVersionNumber
+            ));
+            report.display_server = Some(DisplayServer::AppKit);
+            report.compositor = Some("WindowServer".into());
+
+            let window: id = msg_send![ns_view, window];
+            if window == nil {
+                report
+                    .errors
+                    .push("the view is not part of a window yet".into());
+            }
+
+            if NSAppKitVersionNumber >= NSAppKitVersionNumber10_10 {
+                report.protocols.push("NSVisualEffectView".into());
+                report
+                    .effects
+                    .extend(["apply_blur_region", "apply_vibrancy"]);
+            }
+            if NSAppKitVersionNumber >= NSAppKitVersionNumber10_14 {
+                report.protocols.push("NSAppearance".into());
+                report.effects.push("set_dark_mode");
+            }
+
+            let defaults: id = msg_send![class!(NSUserDefaults), standardUserDefaults];
+            let key = NSString::alloc(nil).init_str("AppleInterfaceStyle");
+            key.autorelease();
+            let style: id = msg_send![defaults, stringForKey: key];
+            report.dark_mode = Some(style != nil && to_string(style) == "Dark");
+
+            if NSAppKitVersionNumber >= NSAppKitVersionNumber10_10 {
+                let workspace: id = msg_send![class!(NSWorkspace), sharedWorkspace];
+                let contrast: BOOL =
+                    msg_send![workspace, accessibilityDisplayShouldIncreaseContrast];
+                let transparency: BOOL =
+                    msg_send![workspace, accessibilityDisplayShouldReduceTransparency];
+                report.high_contrast = Some(contrast == YES);
+                report.reduce_transparency = Some(transparency == YES);
+            }
         }
-        Ok(())
+    }
+
+    unsafe fn to_string(string: id) -> String {
+        if string == nil {
+            return String::new();
+        }
+        std::ffi::CStr::from_ptr(string.UTF8String())
+            .to_string_lossy()
+            .into_owned()
     }
 
     #[allow(non_upper_case_globals)]
@@ -243,3 +882,156 @@ mod internal {
         }
     }
 }
+
+#[cfg(test)]
+#[allow(deprecated)]
+mod tests {
+    use super::*;
+    use NSVisualEffectMaterial::*;
+
+    /// The `NSAppKitVersionNumber` of macOS `version`.
+    fn appkit(version: (u32, u32)) -> f64 {
+        APPKIT_VERSIONS
+            .iter()
+            .find(|(v, _)| *v == version)
+            .unwrap()
+            .1
+    }
+
+    #[test]
+    fn min_versions() {
+        for material in NSVisualEffectMaterial::ALL {
+            let min = material.min_macos_version();
+            assert_eq!(
+                resolve_material(material, appkit(min)),
+                (material, Fallback::None),
+                "{} on macOS {:?}",
+                material,
+                min
+            );
+
+            // one release earlier, it is replaced, unless there is nothing older to use
+            let earlier = resolve_material(material, appkit(min) - 1.0);
+            if material.fallback().is_some() {
+                assert_ne!(earlier.0, material, "{} before macOS {:?}", material, min);
+                assert_eq!(
+                    earlier.1,
+                    Fallback::Substituted {
+                        requested: material,
+                        min_macos_version: min,
+                    }
+                );
+            } else {
+                assert_eq!(min, (10, 10));
+                assert_eq!(earlier, (material, Fallback::None));
+            }
+        }
+    }
+
+    #[test]
+    fn fallback_chains() {
+        // the material each one ends up as on macOS 10.10, 10.11 and 10.13
+        let expected = [
+            (AppearanceBased, [AppearanceBased; 3]),
+            (Light, [Light; 3]),
+            (Dark, [Dark; 3]),
+            (Titlebar, [Titlebar; 3]),
+            (Selection, [Selection; 3]),
+            (Menu, [AppearanceBased, Menu, Menu]),
+            (Popover, [AppearanceBased, Popover, Popover]),
+            (Sidebar, [AppearanceBased, Sidebar, Sidebar]),
+            (MediumLight, [Light, MediumLight, MediumLight]),
+            (UltraDark, [Dark, UltraDark, UltraDark]),
+            (HeaderView, [Titlebar; 3]),
+            (Sheet, [AppearanceBased; 3]),
+            (WindowBackground, [AppearanceBased; 3]),
+            (HudWindow, [Dark, UltraDark, UltraDark]),
+            (FullScreenUI, [Dark, UltraDark, UltraDark]),
+            (Tooltip, [AppearanceBased, Popover, Popover]),
+            (ContentBackground, [AppearanceBased; 3]),
+            (UnderWindowBackground, [AppearanceBased; 3]),
+            (UnderPageBackground, [AppearanceBased; 3]),
+        ];
+        assert_eq!(
+            expected.iter().map(|(m, _)| *m).collect::<Vec<_>>(),
+            NSVisualEffectMaterial::ALL
+        );
+        for (material, resolved) in expected {
+            for (appkit_version, resolved) in [1343.0, 1404.0, 1561.0].iter().zip(resolved) {
+                assert_eq!(
+                    resolve_material(material, *appkit_version).0,
+                    resolved,
+                    "{} on AppKit {}",
+                    material,
+                    appkit_version
+                );
+            }
+            // every fallback is older than what it replaces
+            if let Some(fallback) = material.fallback() {
+                assert!(fallback.min_macos_version() < material.min_macos_version());
+            }
+        }
+
+        // before macOS 10.10 nothing is available, the chain stops at its end
+        assert_eq!(resolve_material(HudWindow, 1265.0).0, Dark);
+    }
+
+    #[test]
+    fn materials_round_trip() {
+        for material in NSVisualEffectMaterial::ALL {
+            assert_eq!(material.to_string(), material.name());
+            for name in [
+                material.name().to_string(),
+                material.apple_name().to_string(),
+                format!("NSVisualEffectMaterial{}", material.apple_name()),
+                material.name().to_uppercase(),
+            ] {
+                assert_eq!(name.parse::<NSVisualEffectMaterial>().unwrap(), material);
+            }
+            assert_eq!(
+                NSVisualEffectMaterial::try_from(material as u64).unwrap(),
+                material
+            );
+        }
+    }
+
+    #[test]
+    fn states_round_trip() {
+        for state in NSVisualEffectState::ALL {
+            assert_eq!(state.to_string(), state.name());
+            for name in [
+                state.name().to_string(),
+                state.apple_name().to_string(),
+                format!("NSVisualEffectState{}", state.apple_name()),
+            ] {
+                assert_eq!(name.parse::<NSVisualEffectState>().unwrap(), state);
+            }
+            assert_eq!(NSVisualEffectState::try_from(state as u64).unwrap(), state);
+        }
+    }
+
+    #[test]
+    fn unknown_names_and_values() {
+        for name in ["", "hud", "hud-windows", "NSVisualEffectMaterial", "blur"] {
+            assert!(
+                name.parse::<NSVisualEffectMaterial>().is_err(),
+                "{:?}",
+                name
+            );
+        }
+        for name in ["", "activ", "NSVisualEffectState", "follows-window"] {
+            assert!(name.parse::<NSVisualEffectState>().is_err(), "{:?}", name);
+        }
+        // the raw values skipped by AppKit, and past the last one
+        for value in [14, 16, 19, 20, 23, u64::MAX] {
+            assert!(
+                NSVisualEffectMaterial::try_from(value).is_err(),
+                "{}",
+                value
+            );
+        }
+        for value in [3, u64::MAX] {
+            assert!(NSVisualEffectState::try_from(value).is_err(), "{}", value);
+        }
+    }
+}
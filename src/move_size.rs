@@ -0,0 +1,355 @@
+// Copyright 2019-2022 Tauri Programme within The Commons Conservancy
+// SPDX-License-Identifier: Apache-2.0
+// SPDX-License-Identifier: MIT
+
+use std::time::{Duration, Instant};
+
+use crate::Color;
+
+/// How the effect of a window is swapped for a solid tint while the user moves or resizes
+/// it, where blur and acrylic lag on some Windows builds.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct MoveSizePolicy {
+    /// The color shown instead of the effect.
+    pub tint: Color,
+    /// How long a move or resize goes on before the effect is swapped, so clicking the
+    /// title bar doesn't flash the tint.
+    pub swap_delay: Duration,
+    /// How long after a move or resize the effect is restored, so it isn't restored between
+    /// consecutive ones.
+    pub restore_delay: Duration,
+    /// How long without [activity](MoveSizeMachine::on_move_size_activity) ends a move or
+    /// resize, for platforms that don't tell when it ends.
+    pub idle_timeout: Duration,
+}
+
+impl MoveSizePolicy {
+    /// Swaps the effect for `tint` after 100 ms and restores it 150 ms after the end.
+    pub fn new(tint: Color) -> Self {
+        Self {
+            tint,
+            swap_delay: Duration::from_millis(100),
+            restore_delay: Duration::from_millis(150),
+            idle_timeout: Duration::from_millis(300),
+        }
+    }
+}
+
+/// A change to make to the window, see [`MoveSizeMachine`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MoveSizeCommand {
+    /// Replace the effect with the tint.
+    Swap(Color),
+    /// Apply the effect again.
+    Restore,
+}
+
+/// Decides when to swap a window's effect for a tint as moves and resizes start and end,
+/// with the delays of a [`MoveSizePolicy`].
+///
+/// It never reads the clock: every input comes with the time it happened, and
+/// [`poll`](Self::poll) returns the commands that are due at a given time.
+///
+/// ```
+/// use std::time::{Duration, Instant};
+/// use window_vibrancy::{MoveSizeCommand, MoveSizeMachine, MoveSizePolicy};
+///
+/// let ms = Duration::from_millis;
+/// let t0 = Instant::now();
+/// let tint = (32, 32, 32, 255);
+/// let mut machine = MoveSizeMachine::new(MoveSizePolicy::new(tint));
+///
+/// // a click on the title bar ends before the swap delay, nothing happens
+/// machine.on_move_size_started(t0);
+/// machine.on_move_size_ended(t0 + ms(40));
+/// assert_eq!(machine.poll(t0 + ms(500)), None);
+/// assert_eq!(machine.deadline(), None);
+///
+/// // a drag swaps the effect once the swap delay elapsed
+/// machine.on_move_size_started(t0 + ms(1000));
+/// assert_eq!(machine.deadline(), Some(t0 + ms(1100)));
+/// assert_eq!(machine.poll(t0 + ms(1050)), None);
+/// assert_eq!(machine.poll(t0 + ms(1100)), Some(MoveSizeCommand::Swap(tint)));
+/// assert!(machine.is_swapped());
+///
+/// // a second drag right after the first keeps the tint
+/// machine.on_move_size_ended(t0 + ms(2000));
+/// machine.on_move_size_started(t0 + ms(2100));
+/// assert_eq!(machine.poll(t0 + ms(2200)), None);
+///
+/// // the effect comes back once the restore delay elapsed
+/// machine.on_move_size_ended(t0 + ms(3000));
+/// assert_eq!(machine.poll(t0 + ms(3100)), None);
+/// assert_eq!(machine.poll(t0 + ms(3150)), Some(MoveSizeCommand::Restore));
+/// assert_eq!(machine.poll(t0 + ms(9000)), None);
+/// ```
+///
+/// Where the end is not notified, like on X11, the move or resize ends once there was no
+/// activity for [`MoveSizePolicy::idle_timeout`]:
+///
+/// ```
+/// use std::time::{Duration, Instant};
+/// use window_vibrancy::{MoveSizeCommand, MoveSizeMachine, MoveSizePolicy};
+///
+/// let ms = Duration::from_millis;
+/// let t0 = Instant::now();
+/// let mut machine = MoveSizeMachine::new(MoveSizePolicy::new((0, 0, 0, 255)));
+///
+/// machine.on_move_size_started(t0);
+/// machine.on_move_size_activity(t0);
+/// assert_eq!(machine.poll(t0 + ms(100)), Some(MoveSizeCommand::Swap((0, 0, 0, 255))));
+/// machine.on_move_size_activity(t0 + ms(250));
+/// assert!(machine.is_moving(t0 + ms(500)));
+///
+/// // ended at 550 ms, restored 150 ms later
+/// assert_eq!(machine.poll(t0 + ms(600)), None);
+/// assert!(!machine.is_moving(t0 + ms(600)));
+/// assert_eq!(machine.deadline(), Some(t0 + ms(700)));
+/// assert_eq!(machine.poll(t0 + ms(700)), Some(MoveSizeCommand::Restore));
+///
+/// // activity alone doesn't start a move or resize
+/// machine.on_move_size_activity(t0 + ms(1000));
+/// assert_eq!(machine.poll(t0 + ms(2000)), None);
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct MoveSizeMachine {
+    policy: MoveSizePolicy,
+    /// When the ongoing move or resize started.
+    started: Option<Instant>,
+    /// The last activity of the ongoing move or resize, once there was some.
+    active: Option<Instant>,
+    /// When the last move or resize ended.
+    ended: Option<Instant>,
+    swapped: bool,
+}
+
+impl MoveSizeMachine {
+    pub fn new(policy: MoveSizePolicy) -> Self {
+        Self {
+            policy,
+            started: None,
+            active: None,
+            ended: None,
+            swapped: false,
+        }
+    }
+
+    pub fn policy(&self) -> &MoveSizePolicy {
+        &self.policy
+    }
+
+    /// Whether the effect is swapped for the tint, once the commands returned so far ran.
+    pub fn is_swapped(&self) -> bool {
+        self.swapped
+    }
+
+    /// Whether a move or resize is going on at `now`.
+    pub fn is_moving(&self, now: Instant) -> bool {
+        match self.implicit_end() {
+            Some(end) => now < end,
+            None => self.started.is_some(),
+        }
+    }
+
+    pub fn on_move_size_started(&mut self, now: Instant) {
+        self.started = Some(now);
+        self.active = None;
+        self.ended = None;
+    }
+
+    pub fn on_move_size_ended(&mut self, now: Instant) {
+        if self.started.take().is_some() {
+            self.active = None;
+            self.ended = Some(now);
+        }
+    }
+
+    /// Notifies the machine that the window moved or was resized. Once there was activity,
+    /// the ongoing move or resize also ends after [`MoveSizePolicy::idle_timeout`] without
+    /// more of it.
+    pub fn on_move_size_activity(&mut self, now: Instant) {
+        if self.started.is_some() {
+            self.active = Some(now);
+        }
+    }
+
+    /// When [`poll`](Self::poll) has something to do next, if ever.
+    pub fn deadline(&self) -> Option<Instant> {
+        match self.started {
+            Some(started) if !self.swapped => {
+                let swap = started + self.policy.swap_delay;
+                Some(self.implicit_end().map_or(swap, |end| end.min(swap)))
+            }
+            Some(_) => self.implicit_end(),
+            None if self.swapped => self.ended.map(|ended| ended + self.policy.restore_delay),
+            None => None,
+        }
+    }
+
+    /// The command due at `now`, if any. Call it until it returns `None`, in case several
+    /// are due.
+    pub fn poll(&mut self, now: Instant) -> Option<MoveSizeCommand> {
+        if let Some(started) = self.started {
+            let swap = started + self.policy.swap_delay;
+            match self.implicit_end() {
+                // the move or resize ended before the tint was due
+                Some(end) if end <= now && (self.swapped || end < swap) => {
+                    self.on_move_size_ended(end)
+                }
+                _ if !self.swapped && swap <= now => {
+                    self.swapped = true;
+                    return Some(MoveSizeCommand::Swap(self.policy.tint));
+                }
+                _ => return None,
+            }
+        }
+
+        match self.ended {
+            Some(ended) if self.swapped && ended + self.policy.restore_delay <= now => {
+                self.swapped = false;
+                Some(MoveSizeCommand::Restore)
+            }
+            _ => None,
+        }
+    }
+
+    fn implicit_end(&self) -> Option<Instant> {
+        self.active.map(|active| active + self.policy.idle_timeout)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Effect, EffectCommand, EffectMachine, FocusPolicy};
+
+    const TINT: Color = (32, 32, 32, 255);
+
+    fn ms(millis: u64) -> Duration {
+        Duration::from_millis(millis)
+    }
+
+    /// Runs the commands due at `now`.
+    fn poll_all(machine: &mut MoveSizeMachine, now: Instant) -> Vec<MoveSizeCommand> {
+        std::iter::from_fn(|| machine.poll(now)).collect()
+    }
+
+    #[test]
+    fn starts_and_ends_pair_up() {
+        let t0 = Instant::now();
+        let mut machine = MoveSizeMachine::new(MoveSizePolicy::new(TINT));
+
+        // an end without a start is ignored
+        machine.on_move_size_ended(t0);
+        assert_eq!(machine.deadline(), None);
+        assert!(!machine.is_moving(t0));
+
+        machine.on_move_size_started(t0);
+        assert!(machine.is_moving(t0 + ms(5000)));
+        assert_eq!(
+            poll_all(&mut machine, t0 + ms(100)),
+            [MoveSizeCommand::Swap(TINT)]
+        );
+        machine.on_move_size_ended(t0 + ms(200));
+        // the second end is ignored, it doesn't push the restore back
+        machine.on_move_size_ended(t0 + ms(300));
+        assert_eq!(machine.deadline(), Some(t0 + ms(350)));
+        assert_eq!(
+            poll_all(&mut machine, t0 + ms(350)),
+            [MoveSizeCommand::Restore]
+        );
+        assert!(!machine.is_swapped());
+    }
+
+    #[test]
+    fn starting_again_before_the_restore_keeps_the_tint() {
+        let t0 = Instant::now();
+        let mut machine = MoveSizeMachine::new(MoveSizePolicy::new(TINT));
+        machine.on_move_size_started(t0);
+        assert_eq!(
+            poll_all(&mut machine, t0 + ms(100)),
+            [MoveSizeCommand::Swap(TINT)]
+        );
+        machine.on_move_size_ended(t0 + ms(200));
+        machine.on_move_size_started(t0 + ms(300));
+        // neither restored nor swapped again, even once both delays elapsed
+        assert_eq!(machine.deadline(), None);
+        assert_eq!(poll_all(&mut machine, t0 + ms(1000)), []);
+        assert!(machine.is_swapped());
+
+        // a restart before the swap delay starts the delay over
+        let mut machine = MoveSizeMachine::new(MoveSizePolicy::new(TINT));
+        machine.on_move_size_started(t0);
+        machine.on_move_size_ended(t0 + ms(50));
+        machine.on_move_size_started(t0 + ms(80));
+        assert_eq!(machine.deadline(), Some(t0 + ms(180)));
+        assert_eq!(poll_all(&mut machine, t0 + ms(150)), []);
+        assert_eq!(
+            poll_all(&mut machine, t0 + ms(180)),
+            [MoveSizeCommand::Swap(TINT)]
+        );
+    }
+
+    #[test]
+    fn idle_timeout_restores() {
+        let t0 = Instant::now();
+        let mut machine = MoveSizeMachine::new(MoveSizePolicy::new(TINT));
+        machine.on_move_size_started(t0);
+        machine.on_move_size_activity(t0 + ms(50));
+        assert_eq!(
+            poll_all(&mut machine, t0 + ms(100)),
+            [MoveSizeCommand::Swap(TINT)]
+        );
+        assert_eq!(machine.deadline(), Some(t0 + ms(350)));
+
+        // late enough to be past both the idle timeout and the restore delay
+        assert_eq!(
+            poll_all(&mut machine, t0 + ms(1000)),
+            [MoveSizeCommand::Restore]
+        );
+        assert!(!machine.is_moving(t0 + ms(1000)));
+        assert_eq!(machine.deadline(), None);
+
+        // idle before the swap delay, nothing happens
+        let mut machine = MoveSizeMachine::new(MoveSizePolicy {
+            idle_timeout: ms(30),
+            ..MoveSizePolicy::new(TINT)
+        });
+        machine.on_move_size_started(t0);
+        machine.on_move_size_activity(t0);
+        assert_eq!(machine.deadline(), Some(t0 + ms(30)));
+        assert_eq!(poll_all(&mut machine, t0 + ms(500)), []);
+        assert_eq!(machine.deadline(), None);
+    }
+
+    #[test]
+    fn effect_changes_wait_for_the_restore() {
+        let t0 = Instant::now();
+        let mut machine = MoveSizeMachine::new(MoveSizePolicy::new(TINT));
+        let mut effects = EffectMachine::new(FocusPolicy::default());
+        let acrylic = Effect::Acrylic(None);
+        let mica = Effect::Mica { dark: None };
+        effects.set_effect(Some(acrylic));
+
+        machine.on_move_size_started(t0);
+        let swap = poll_all(&mut machine, t0 + ms(100));
+        assert_eq!(swap, [MoveSizeCommand::Swap(TINT)]);
+        assert_eq!(
+            effects.set_swap_tint(Some(TINT)),
+            [EffectCommand::Clear(acrylic)]
+        );
+        assert_eq!(effects.solid_color(), Some(TINT));
+
+        // held while swapped
+        assert_eq!(effects.set_effect(Some(mica)), []);
+        machine.on_move_size_ended(t0 + ms(200));
+        assert_eq!(poll_all(&mut machine, t0 + ms(300)), []);
+
+        assert_eq!(
+            poll_all(&mut machine, t0 + ms(350)),
+            [MoveSizeCommand::Restore]
+        );
+        assert_eq!(effects.set_swap_tint(None), [EffectCommand::Apply(mica)]);
+        assert_eq!(effects.solid_color(), None);
+    }
+}
@@ -0,0 +1,518 @@
+// Copyright 2019-2022 Tauri Programme within The Commons Conservancy
+// SPDX-License-Identifier: Apache-2.0
+// SPDX-License-Identifier: MIT
+
+use crate::{Color, Effect};
+
+/// The effect of a window while it is not focused, see [`FocusPolicy`].
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum InactiveEffect {
+    /// The active effect, with [`FocusPolicy::inactive_tint`] if set.
+    Same,
+    /// Another effect, with [`FocusPolicy::inactive_tint`] if set.
+    Effect(Effect),
+    /// No effect.
+    None,
+}
+
+/// How a window's effect changes when it loses focus, like macOS does for
+/// [`NSVisualEffectState::FollowsWindowActiveState`](crate::NSVisualEffectState).
+///
+/// The default keeps the active effect as is.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct FocusPolicy {
+    pub inactive_effect: InactiveEffect,
+    /// The tint given to blur and acrylic while the window is inactive.
+    pub inactive_tint: Option<Color>,
+}
+
+impl Default for FocusPolicy {
+    fn default() -> Self {
+        Self {
+            inactive_effect: InactiveEffect::Same,
+            inactive_tint: None,
+        }
+    }
+}
+
+impl FocusPolicy {
+    /// The effect for a window whose active effect is `active`.
+    pub fn effect(&self, active: Option<Effect>, focused: bool) -> Option<Effect> {
+        if focused {
+            return active;
+        }
+        let effect = match self.inactive_effect {
+            InactiveEffect::Same => active?,
+            InactiveEffect::Effect(effect) => effect,
+            InactiveEffect::None => return None,
+        };
+        Some(match (effect, self.inactive_tint) {
+            (Effect::Blur(_), Some(tint)) => Effect::Blur(Some(tint)),
+            (Effect::Acrylic(_), Some(tint)) => Effect::Acrylic(Some(tint)),
+            (effect, _) => effect,
+        })
+    }
+}
+
+/// Whether a window is maximized or fullscreen, see [`window_state`](crate::window_state).
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct WindowState {
+    pub maximized: bool,
+    pub fullscreen: bool,
+}
+
+/// What happens to a window's effect while it is maximized or fullscreen, see
+/// [`WindowStatePolicy`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum StateEffect {
+    /// The effect stays.
+    Keep,
+    /// The effect is cleared and the application paints this color instead, see
+    /// [`EffectMachine::solid_color`].
+    Solid(Color),
+    /// The effect is cleared.
+    Disable,
+}
+
+/// How a window's effect changes when it is maximized or fullscreen, where there is
+/// little or nothing behind the window to blur.
+///
+/// The default keeps the effect in both states.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct WindowStatePolicy {
+    pub maximized: StateEffect,
+    pub fullscreen: StateEffect,
+}
+
+impl Default for WindowStatePolicy {
+    fn default() -> Self {
+        Self {
+            maximized: StateEffect::Keep,
+            fullscreen: StateEffect::Keep,
+        }
+    }
+}
+
+impl WindowStatePolicy {
+    /// What happens in `state`, a fullscreen window being treated as fullscreen even
+    /// if it is maximized too.
+    pub fn state_effect(&self, state: WindowState) -> StateEffect {
+        if state.fullscreen {
+            self.fullscreen
+        } else if state.maximized {
+            self.maximized
+        } else {
+            StateEffect::Keep
+        }
+    }
+}
+
+/// A change to make to the window, see [`EffectMachine`].
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum EffectCommand {
+    Apply(Effect),
+    Clear(Effect),
+}
+
+/// Tracks the effect a window should have as its focus and state change, and the commands
+/// that take it there from the applied one.
+///
+/// It never touches the window, [`VibrancyController`](crate::VibrancyController) runs
+/// the commands.
+///
+/// ```
+/// use window_vibrancy::{
+///     Effect, EffectCommand, EffectMachine, FocusPolicy, InactiveEffect, StateEffect,
+///     WindowState, WindowStatePolicy,
+/// };
+///
+/// let mut machine = EffectMachine::new(FocusPolicy {
+///     inactive_effect: InactiveEffect::Same,
+///     inactive_tint: Some((32, 32, 32, 230)),
+/// });
+/// machine.set_state_policy(WindowStatePolicy {
+///     maximized: StateEffect::Solid((32, 32, 32, 255)),
+///     fullscreen: StateEffect::Disable,
+/// });
+/// let acrylic = Effect::Acrylic(Some((18, 18, 18, 125)));
+/// assert_eq!(machine.set_effect(Some(acrylic)), [EffectCommand::Apply(acrylic)]);
+///
+/// // re-applying acrylic with another tint replaces it, no need to clear it
+/// assert_eq!(
+///     machine.on_focus_changed(false),
+///     [EffectCommand::Apply(Effect::Acrylic(Some((32, 32, 32, 230))))]
+/// );
+/// assert_eq!(machine.on_focus_changed(false), []);
+/// assert_eq!(machine.on_focus_changed(true), [EffectCommand::Apply(acrylic)]);
+///
+/// // maximized, the application paints the solid color instead
+/// let maximized = WindowState {
+///     maximized: true,
+///     fullscreen: false,
+/// };
+/// assert_eq!(
+///     machine.on_window_state_changed(maximized),
+///     [EffectCommand::Clear(acrylic)]
+/// );
+/// assert_eq!(machine.solid_color(), Some((32, 32, 32, 255)));
+/// assert_eq!(
+///     machine.on_window_state_changed(WindowState::default()),
+///     [EffectCommand::Apply(acrylic)]
+/// );
+///
+/// machine.set_focus_policy(FocusPolicy {
+///     inactive_effect: InactiveEffect::Effect(Effect::Mica { dark: None }),
+///     inactive_tint: None,
+/// });
+/// assert_eq!(
+///     machine.on_focus_changed(false),
+///     [
+///         EffectCommand::Clear(acrylic),
+///         EffectCommand::Apply(Effect::Mica { dark: None })
+///     ]
+/// );
+/// ```
+#[derive(Clone, Debug, PartialEq)]
+pub struct EffectMachine {
+    focus_policy: FocusPolicy,
+    state_policy: WindowStatePolicy,
+    effect: Option<Effect>,
+    focused: bool,
+    state: WindowState,
+    swap_tint: Option<Color>,
+    /// Whether the system is in dark mode, once notified.
+    dark_mode: Option<bool>,
+    applied: Option<Effect>,
+    /// The effect the window had before the last commands.
+    previous: Option<Effect>,
+}
+
+impl EffectMachine {
+    /// A machine for a focused, restored window without effect.
+    pub fn new(focus_policy: FocusPolicy) -> Self {
+        Self {
+            focus_policy,
+            state_policy: WindowStatePolicy::default(),
+            effect: None,
+            focused: true,
+            state: WindowState::default(),
+            swap_tint: None,
+            dark_mode: None,
+            applied: None,
+            previous: None,
+        }
+    }
+
+    pub fn focus_policy(&self) -> &FocusPolicy {
+        &self.focus_policy
+    }
+
+    pub fn state_policy(&self) -> &WindowStatePolicy {
+        &self.state_policy
+    }
+
+    /// The effect of the window while it is focused and restored.
+    pub fn effect(&self) -> Option<Effect> {
+        self.effect
+    }
+
+    pub fn is_focused(&self) -> bool {
+        self.focused
+    }
+
+    pub fn window_state(&self) -> WindowState {
+        self.state
+    }
+
+    /// Whether the system is in dark mode, see [`on_theme_changed`](Self::on_theme_changed).
+    pub fn dark_mode(&self) -> Option<bool> {
+        self.dark_mode
+    }
+
+    /// The effect the window has once the commands returned so far ran.
+    pub fn applied(&self) -> Option<Effect> {
+        self.applied
+    }
+
+    /// The effect the window had before the commands returned last.
+    #[cfg_attr(not(target_os = "macos"), allow(dead_code))]
+    pub(crate) fn previous(&self) -> Option<Effect> {
+        self.previous
+    }
+
+    /// The tint replacing the effect during a move or resize, see [`set_swap_tint`](Self::set_swap_tint).
+    pub fn swap_tint(&self) -> Option<Color> {
+        self.swap_tint
+    }
+
+    /// The color the application should paint behind its content instead of the effect,
+    /// when the window has one and it is swapped for a tint, or the [`WindowStatePolicy`]
+    /// gives its state [`StateEffect::Solid`].
+    pub fn solid_color(&self) -> Option<Color> {
+        let color = match (self.swap_tint, self.state_policy.state_effect(self.state)) {
+            (Some(tint), _) => tint,
+            (None, StateEffect::Solid(color)) => color,
+            _ => return None,
+        };
+        self.focus_policy
+            .effect(self.effect, self.focused)
+            .map(|_| color)
+    }
+
+    pub fn set_focus_policy(&mut self, policy: FocusPolicy) -> Vec<EffectCommand> {
+        self.focus_policy = policy;
+        self.sync()
+    }
+
+    pub fn set_state_policy(&mut self, policy: WindowStatePolicy) -> Vec<EffectCommand> {
+        self.state_policy = policy;
+        self.sync()
+    }
+
+    pub fn set_effect(&mut self, effect: Option<Effect>) -> Vec<EffectCommand> {
+        self.effect = effect;
+        self.sync()
+    }
+
+    pub fn on_focus_changed(&mut self, focused: bool) -> Vec<EffectCommand> {
+        self.focused = focused;
+        self.sync()
+    }
+
+    pub fn on_window_state_changed(&mut self, state: WindowState) -> Vec<EffectCommand> {
+        self.state = state;
+        self.sync()
+    }
+
+    /// Notifies the machine that the system switched between dark and light mode. Mica and
+    /// Tabbed that follow the system are applied with its theme from then on.
+    pub fn on_theme_changed(&mut self, dark: bool) -> Vec<EffectCommand> {
+        self.dark_mode = Some(dark);
+        self.sync()
+    }
+
+    /// Replaces the effect with `tint` until it is set back to `None`, see
+    /// [`MoveSizeMachine`](crate::MoveSizeMachine).
+    pub fn set_swap_tint(&mut self, tint: Option<Color>) -> Vec<EffectCommand> {
+        self.swap_tint = tint;
+        self.sync()
+    }
+
+    /// Tells the machine that `failed`, one of the commands it returned last, failed and
+    /// that the ones after it did not run.
+    ///
+    /// The window keeps an effect that failed to clear, and a failed apply leaves the
+    /// previous effect if it was to replace it, but nothing once it was cleared.
+    pub fn on_command_failed(&mut self, failed: EffectCommand) {
+        self.applied = match failed {
+            EffectCommand::Clear(effect) => Some(effect),
+            EffectCommand::Apply(target) => self.previous.filter(|previous| {
+                std::mem::discriminant(previous) == std::mem::discriminant(&target)
+            }),
+        };
+    }
+
+    fn sync(&mut self) -> Vec<EffectCommand> {
+        let target = match (self.swap_tint, self.state_policy.state_effect(self.state)) {
+            (None, StateEffect::Keep) => self.focus_policy.effect(self.effect, self.focused),
+            _ => None,
+        };
+        let target = match (target, self.dark_mode) {
+            (Some(Effect::Mica { dark: None }), Some(dark)) => {
+                Some(Effect::Mica { dark: Some(dark) })
+            }
+            (Some(Effect::Tabbed { dark: None }), Some(dark)) => {
+                Some(Effect::Tabbed { dark: Some(dark) })
+            }
+            (target, _) => target,
+        };
+        if target == self.applied {
+            return Vec::new();
+        }
+
+        let mut commands = Vec::new();
+        if let Some(applied) = self.applied {
+            // applying an effect replaces one of the same kind, but not one of another kind
+            let replaced = target.map_or(false, |target| {
+                std::mem::discriminant(&target) == std::mem::discriminant(&applied)
+            });
+            if !replaced {
+                commands.push(EffectCommand::Clear(applied));
+            }
+        }
+        if let Some(target) = target {
+            commands.push(EffectCommand::Apply(target));
+        }
+        self.previous = self.applied;
+        self.applied = target;
+        commands
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ACRYLIC: Effect = Effect::Acrylic(Some((18, 18, 18, 125)));
+    const MICA: Effect = Effect::Mica { dark: None };
+    const MAXIMIZED: WindowState = WindowState {
+        maximized: true,
+        fullscreen: false,
+    };
+    const FULLSCREEN: WindowState = WindowState {
+        maximized: true,
+        fullscreen: true,
+    };
+
+    #[test]
+    fn focus_transitions() {
+        let mut machine = EffectMachine::new(FocusPolicy {
+            inactive_effect: InactiveEffect::None,
+            inactive_tint: None,
+        });
+        // nothing to apply until there is an effect
+        assert_eq!(machine.on_focus_changed(false), []);
+        assert_eq!(machine.set_effect(Some(ACRYLIC)), []);
+        assert_eq!(machine.applied(), None);
+        assert_eq!(
+            machine.on_focus_changed(true),
+            [EffectCommand::Apply(ACRYLIC)]
+        );
+        assert_eq!(
+            machine.on_focus_changed(false),
+            [EffectCommand::Clear(ACRYLIC)]
+        );
+
+        // a new policy applies right away
+        assert_eq!(
+            machine.set_focus_policy(FocusPolicy {
+                inactive_effect: InactiveEffect::Effect(Effect::Blur(None)),
+                inactive_tint: Some((32, 32, 32, 230)),
+            }),
+            [EffectCommand::Apply(Effect::Blur(Some((32, 32, 32, 230))))]
+        );
+        assert_eq!(
+            machine.set_effect(None),
+            [],
+            "the inactive effect doesn't depend on the active one"
+        );
+        assert_eq!(
+            machine.on_focus_changed(true),
+            [EffectCommand::Clear(Effect::Blur(Some((32, 32, 32, 230))))]
+        );
+        assert_eq!(machine.applied(), None);
+    }
+
+    #[test]
+    fn state_transitions() {
+        let mut machine = EffectMachine::new(FocusPolicy::default());
+        machine.set_state_policy(WindowStatePolicy {
+            maximized: StateEffect::Keep,
+            fullscreen: StateEffect::Solid((0, 0, 0, 255)),
+        });
+        assert_eq!(machine.set_effect(Some(MICA)), [EffectCommand::Apply(MICA)]);
+        assert_eq!(machine.on_window_state_changed(MAXIMIZED), []);
+        assert_eq!(machine.solid_color(), None);
+
+        assert_eq!(
+            machine.on_window_state_changed(FULLSCREEN),
+            [EffectCommand::Clear(MICA)]
+        );
+        assert_eq!(machine.solid_color(), Some((0, 0, 0, 255)));
+        // the effect changes while it is cleared, and is applied once it is back
+        assert_eq!(machine.set_effect(Some(ACRYLIC)), []);
+        assert_eq!(
+            machine.on_window_state_changed(MAXIMIZED),
+            [EffectCommand::Apply(ACRYLIC)]
+        );
+        assert_eq!(machine.solid_color(), None);
+
+        // a swap tint clears the effect whatever the state
+        assert_eq!(
+            machine.set_swap_tint(Some((32, 32, 32, 255))),
+            [EffectCommand::Clear(ACRYLIC)]
+        );
+        assert_eq!(machine.solid_color(), Some((32, 32, 32, 255)));
+        assert_eq!(machine.on_window_state_changed(FULLSCREEN), []);
+        assert_eq!(machine.solid_color(), Some((32, 32, 32, 255)));
+        assert_eq!(machine.set_swap_tint(None), []);
+        assert_eq!(machine.solid_color(), Some((0, 0, 0, 255)));
+        assert_eq!(
+            machine.on_window_state_changed(WindowState::default()),
+            [EffectCommand::Apply(ACRYLIC)]
+        );
+    }
+
+    #[test]
+    fn theme_transitions() {
+        let mut machine = EffectMachine::new(FocusPolicy::default());
+        assert_eq!(machine.set_effect(Some(MICA)), [EffectCommand::Apply(MICA)]);
+        let dark = Effect::Mica { dark: Some(true) };
+        assert_eq!(machine.on_theme_changed(true), [EffectCommand::Apply(dark)]);
+        assert_eq!(machine.on_theme_changed(true), []);
+        assert_eq!(machine.applied(), Some(dark));
+
+        // effects that don't follow the system stay
+        let tabbed = Effect::Tabbed { dark: Some(false) };
+        assert_eq!(
+            machine.set_effect(Some(tabbed)),
+            [EffectCommand::Clear(dark), EffectCommand::Apply(tabbed)]
+        );
+        assert_eq!(machine.on_theme_changed(false), []);
+        assert_eq!(
+            machine.set_effect(Some(Effect::Tabbed { dark: None })),
+            [],
+            "the system is in light mode"
+        );
+        assert_eq!(
+            machine.on_theme_changed(true),
+            [EffectCommand::Apply(Effect::Tabbed { dark: Some(true) })]
+        );
+    }
+
+    #[test]
+    fn failed_commands() {
+        let mut machine = EffectMachine::new(FocusPolicy::default());
+
+        // nothing was applied
+        let commands = machine.set_effect(Some(ACRYLIC));
+        machine.on_command_failed(commands[0]);
+        assert_eq!(machine.applied(), None);
+        assert_eq!(
+            machine.on_focus_changed(true),
+            [EffectCommand::Apply(ACRYLIC)]
+        );
+
+        // a failed replacement keeps the previous effect
+        let tinted = Effect::Acrylic(None);
+        assert_eq!(
+            machine.set_effect(Some(tinted)),
+            [EffectCommand::Apply(tinted)]
+        );
+        machine.on_command_failed(EffectCommand::Apply(tinted));
+        assert_eq!(machine.applied(), Some(ACRYLIC));
+        assert_eq!(
+            machine.on_focus_changed(true),
+            [EffectCommand::Apply(tinted)]
+        );
+
+        // the effect that failed to clear stays
+        let commands = machine.set_effect(Some(MICA));
+        assert_eq!(
+            commands,
+            [EffectCommand::Clear(tinted), EffectCommand::Apply(MICA)]
+        );
+        machine.on_command_failed(commands[0]);
+        assert_eq!(machine.applied(), Some(tinted));
+
+        // once cleared, a failed apply leaves nothing
+        let commands = machine.on_focus_changed(true);
+        assert_eq!(
+            commands,
+            [EffectCommand::Clear(tinted), EffectCommand::Apply(MICA)]
+        );
+        machine.on_command_failed(commands[1]);
+        assert_eq!(machine.applied(), None);
+        assert_eq!(machine.on_focus_changed(true), [EffectCommand::Apply(MICA)]);
+    }
+}
@@ -0,0 +1,294 @@
+// Copyright 2019-2022 Tauri Programme within The Commons Conservancy
+// SPDX-License-Identifier: Apache-2.0
+// SPDX-License-Identifier: MIT
+
+use std::collections::HashMap;
+
+use raw_window_handle::HasWindowHandle;
+
+use crate::{trace, user_config, BackgroundContrast, Color, Effect, Error, NSVisualEffectMaterial};
+
+/// What a window is for, mapped to the effect that feels native for it on each platform.
+///
+/// The names and meanings are the ones of [`NSVisualEffectMaterial`]. The default mapping:
+///
+/// | Preset             | Windows          | macOS                    | Linux                                  |
+/// |--------------------|------------------|--------------------------|----------------------------------------|
+/// | `Titlebar`         | Tabbed           | `Titlebar` vibrancy      | blur                                   |
+/// | `Sidebar`          | Mica             | `Sidebar` vibrancy       | blur, saturated                        |
+/// | `WindowBackground` | Mica             | `WindowBackground`       | blur                                   |
+/// | `Sheet`            | Acrylic          | `Sheet` vibrancy         | blur, saturated                        |
+/// | `Popover`          | Acrylic          | `Popover` vibrancy       | blur, Plasma panel contrast, radius 8  |
+/// | `Menu`             | Acrylic          | `Menu` vibrancy          | blur, Plasma panel contrast, radius 8  |
+/// | `HudWindow`        | Acrylic, dark    | `HudWindow` vibrancy     | blur, darkened, dark tint              |
+/// | `Tooltip`          | Blur             | `Tooltip` vibrancy       | blur, Plasma panel contrast, radius 4  |
+///
+/// See [`PresetEffects`] for what each platform's part means, and [`PresetMapping`] to
+/// override it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Preset {
+    Titlebar,
+    Sidebar,
+    WindowBackground,
+    Sheet,
+    Popover,
+    Menu,
+    HudWindow,
+    Tooltip,
+}
+
+impl Preset {
+    pub const ALL: [Preset; 8] = [
+        Preset::Titlebar,
+        Preset::Sidebar,
+        Preset::WindowBackground,
+        Preset::Sheet,
+        Preset::Popover,
+        Preset::Menu,
+        Preset::HudWindow,
+        Preset::Tooltip,
+    ];
+
+    /// The effects of the default mapping, see the table of [`Preset`].
+    pub fn default_effects(self) -> PresetEffects {
+        // the contrast Breeze gives Plasma panels and menus
+        const PANEL: BackgroundContrast = BackgroundContrast {
+            contrast: 0.2,
+            intensity: 2.0,
+            saturation: 1.7,
+        };
+        const SATURATED: BackgroundContrast = BackgroundContrast {
+            contrast: 1.0,
+            intensity: 1.0,
+            saturation: 1.5,
+        };
+        const DARKENED: BackgroundContrast = BackgroundContrast {
+            contrast: 0.8,
+            intensity: 0.6,
+            saturation: 1.2,
+        };
+
+        let (windows, material, linux) = match self {
+            Preset::Titlebar => (
+                Effect::Tabbed { dark: None },
+                NSVisualEffectMaterial::Titlebar,
+                LinuxPreset::blur(None),
+            ),
+            Preset::Sidebar => (
+                Effect::Mica { dark: None },
+                NSVisualEffectMaterial::Sidebar,
+                LinuxPreset::blur(Some(SATURATED)),
+            ),
+            Preset::WindowBackground => (
+                Effect::Mica { dark: None },
+                NSVisualEffectMaterial::WindowBackground,
+                LinuxPreset::blur(None),
+            ),
+            Preset::Sheet => (
+                Effect::Acrylic(None),
+                NSVisualEffectMaterial::Sheet,
+                LinuxPreset::blur(Some(SATURATED)),
+            ),
+            Preset::Popover => (
+                Effect::Acrylic(None),
+                NSVisualEffectMaterial::Popover,
+                LinuxPreset::blur(Some(PANEL)).with_radius(8.0),
+            ),
+            Preset::Menu => (
+                Effect::Acrylic(None),
+                NSVisualEffectMaterial::Menu,
+                LinuxPreset::blur(Some(PANEL)).with_radius(8.0),
+            ),
+            Preset::HudWindow => (
+                Effect::Acrylic(Some((18, 18, 18, 160))),
+                NSVisualEffectMaterial::HudWindow,
+                LinuxPreset {
+                    tint: Some((18, 18, 18, 160)),
+                    ..LinuxPreset::blur(Some(DARKENED))
+                },
+            ),
+            Preset::Tooltip => (
+                Effect::Blur(None),
+                NSVisualEffectMaterial::Tooltip,
+                LinuxPreset::blur(Some(PANEL)).with_radius(4.0),
+            ),
+        };
+        PresetEffects {
+            windows,
+            macos: Effect::Vibrancy {
+                material,
+                state: None,
+                radius: None,
+            },
+            linux,
+        }
+    }
+}
+
+/// What a [`Preset`] does on each platform. Only the part of the running platform is used.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct PresetEffects {
+    /// The effect applied on Windows.
+    pub windows: Effect,
+    /// The effect applied on macOS.
+    pub macos: Effect,
+    pub linux: LinuxPreset,
+}
+
+/// What a [`Preset`] does on Linux, where compositors only blur.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct LinuxPreset {
+    pub blur: bool,
+    /// The corner radius, on SwayFX.
+    pub radius: Option<f64>,
+    /// The colors of what is seen through the window, on KDE Plasma.
+    pub contrast: Option<BackgroundContrast>,
+    /// The color to draw behind the window's content. Compositors don't tint windows,
+    /// so this is left to the application.
+    pub tint: Option<Color>,
+}
+
+impl LinuxPreset {
+    fn blur(contrast: Option<BackgroundContrast>) -> Self {
+        Self {
+            blur: true,
+            radius: None,
+            contrast,
+            tint: None,
+        }
+    }
+
+    fn with_radius(self, radius: f64) -> Self {
+        Self {
+            radius: Some(radius),
+            ..self
+        }
+    }
+}
+
+/// The default mapping of presets to effects, with some presets overridden.
+///
+/// ```no_run
+/// use window_vibrancy::{Effect, Preset, PresetMapping};
+///
+/// # let window: &dyn raw_window_handle::HasWindowHandle = unsafe { std::mem::zeroed() };
+/// let mut sidebar = Preset::Sidebar.default_effects();
+/// sidebar.windows = Effect::Acrylic(Some((32, 32, 32, 96)));
+/// let mapping = PresetMapping::default().with(Preset::Sidebar, sidebar);
+/// mapping.apply(&window, Preset::Sidebar).unwrap();
+/// ```
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct PresetMapping {
+    overrides: HashMap<Preset, PresetEffects>,
+}
+
+impl PresetMapping {
+    /// Replaces what `preset` does.
+    pub fn with(mut self, preset: Preset, effects: PresetEffects) -> Self {
+        self.set(preset, effects);
+        self
+    }
+
+    /// Replaces what `preset` does.
+    pub fn set(&mut self, preset: Preset, effects: PresetEffects) {
+        self.overrides.insert(preset, effects);
+    }
+
+    /// Goes back to the default effects of `preset`.
+    pub fn reset(&mut self, preset: Preset) {
+        self.overrides.remove(&preset);
+    }
+
+    pub fn get(&self, preset: Preset) -> PresetEffects {
+        self.overrides
+            .get(&preset)
+            .copied()
+            .unwrap_or_else(|| preset.default_effects())
+    }
+
+    /// Applies the effects this mapping gives `preset` on the running platform.
+    ///
+    /// ## Platform-specific
+    ///
+    /// - **Linux**: Only supported on SwayFX and KDE Plasma. [`LinuxPreset::tint`] is not drawn.
+    pub fn apply(&self, window: impl HasWindowHandle, preset: Preset) -> Result<(), Error> {
+        #[allow(unused)]
+        let effects = self.get(preset);
+        let handle = window.window_handle()?.as_raw();
+        match handle {
+            // `apply_effect` goes through the `apply_*` functions, which are traced themselves
+            #[cfg(target_os = "windows")]
+            raw_window_handle::RawWindowHandle::Win32(_) => {
+                crate::apply_effect(&window, &effects.windows)
+            }
+            #[cfg(target_os = "macos")]
+            raw_window_handle::RawWindowHandle::AppKit(_) => {
+                crate::apply_effect(&window, &effects.macos)
+            }
+            _ => trace::instrument("apply_preset", handle, || match handle {
+                #[cfg(target_os = "linux")]
+                handle @ (raw_window_handle::RawWindowHandle::Xlib(_)
+                | raw_window_handle::RawWindowHandle::Xcb(_)
+                | raw_window_handle::RawWindowHandle::Wayland(_)) => {
+                    if !user_config::permits("apply_preset", user_config::EffectLevel::Blur) {
+                        return Ok(());
+                    }
+                    crate::linux::apply_preset(handle, &effects.linux)
+                }
+                _ => Err(Error::UnsupportedPlatform(
+                    "\"apply_preset()\" is only supported on Windows, macOS and Linux.",
+                )),
+            }),
+        }
+    }
+
+    /// Clears the effects this mapping gives `preset`, see [`clear_effect`](crate::clear_effect).
+    ///
+    /// ## Platform-specific
+    ///
+    /// - **macOS**: Vibrancy can't be cleared.
+    pub fn clear(&self, window: impl HasWindowHandle, preset: Preset) -> Result<(), Error> {
+        #[allow(unused)]
+        let effects = self.get(preset);
+        let handle = window.window_handle()?.as_raw();
+        match handle {
+            // `clear_effect` goes through the `clear_*` functions, which are traced themselves
+            #[cfg(target_os = "windows")]
+            raw_window_handle::RawWindowHandle::Win32(_) => {
+                crate::clear_effect(&window, &effects.windows)
+            }
+            #[cfg(target_os = "macos")]
+            raw_window_handle::RawWindowHandle::AppKit(_) => {
+                crate::clear_effect(&window, &effects.macos)
+            }
+            _ => trace::instrument("clear_preset", handle, || match handle {
+                #[cfg(target_os = "linux")]
+                handle @ (raw_window_handle::RawWindowHandle::Xlib(_)
+                | raw_window_handle::RawWindowHandle::Xcb(_)
+                | raw_window_handle::RawWindowHandle::Wayland(_)) => {
+                    crate::linux::clear_preset(handle)
+                }
+                _ => Err(Error::UnsupportedPlatform(
+                    "\"clear_preset()\" is only supported on Windows, macOS and Linux.",
+                )),
+            }),
+        }
+    }
+}
+
+/// Applies the default effects of `preset`, see [`Preset`] for what they are on each platform.
+///
+/// ```no_run
+/// use window_vibrancy::{apply_preset, Preset};
+///
+/// # let window: &dyn raw_window_handle::HasWindowHandle = unsafe { std::mem::zeroed() };
+/// apply_preset(&window, Preset::Sidebar).unwrap();
+/// ```
+pub fn apply_preset(window: impl HasWindowHandle, preset: Preset) -> Result<(), Error> {
+    PresetMapping::default().apply(window, preset)
+}
+
+/// Clears the default effects of `preset`.
+pub fn clear_preset(window: impl HasWindowHandle, preset: Preset) -> Result<(), Error> {
+    PresetMapping::default().clear(window, preset)
+}
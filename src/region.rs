@@ -0,0 +1,481 @@
+// Copyright 2019-2022 Tauri Programme within The Commons Conservancy
+// SPDX-License-Identifier: Apache-2.0
+// SPDX-License-Identifier: MIT
+
+/// A rectangle of the window, in physical pixels relative to the top-left corner of its client area.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct Rect {
+    pub x: i32,
+    pub y: i32,
+    pub width: u32,
+    pub height: u32,
+    /// Radius of the rounded corners, `0` for square corners.
+    pub corner_radius: u32,
+}
+
+impl Rect {
+    pub fn new(x: i32, y: i32, width: u32, height: u32) -> Self {
+        Self {
+            x,
+            y,
+            width,
+            height,
+            corner_radius: 0,
+        }
+    }
+
+    pub fn with_corner_radius(mut self, corner_radius: u32) -> Self {
+        self.corner_radius = corner_radius;
+        self
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.width == 0 || self.height == 0
+    }
+
+    /// The corner radius actually usable for this rectangle, radii bigger
+    /// than half of the shortest side are clamped like CSS does.
+    pub(crate) fn effective_radius(&self) -> u32 {
+        self.corner_radius.min(self.width / 2).min(self.height / 2)
+    }
+}
+
+/// Decomposes rounded rectangles into square ones, for protocols that only know about those.
+///
+/// Each rounded corner is approximated by horizontal strips, one per distinct inset.
+pub(crate) fn flatten(rects: &[Rect]) -> Vec<Rect> {
+    let mut flat = Vec::with_capacity(rects.len());
+    for rect in rects.iter().filter(|r| !r.is_empty()) {
+        let radius = rect.effective_radius();
+        if radius == 0 {
+            flat.push(Rect::new(rect.x, rect.y, rect.width, rect.height));
+            continue;
+        }
+
+        let strips = corner_strips(radius);
+        for &(offset, height, inset) in &strips {
+            flat.push(Rect::new(
+                rect.x + inset as i32,
+                rect.y + offset as i32,
+                rect.width - 2 * inset,
+                height,
+            ));
+        }
+        if rect.height > 2 * radius {
+            flat.push(Rect::new(
+                rect.x,
+                rect.y + radius as i32,
+                rect.width,
+                rect.height - 2 * radius,
+            ));
+        }
+        for &(offset, height, inset) in strips.iter().rev() {
+            flat.push(Rect::new(
+                rect.x + inset as i32,
+                rect.y + (rect.height - offset - height) as i32,
+                rect.width - 2 * inset,
+                height,
+            ));
+        }
+    }
+    flat
+}
+
+/// Horizontal strips `(offset, height, inset)` covering the top `radius` rows of a rounded corner.
+fn corner_strips(radius: u32) -> Vec<(u32, u32, u32)> {
+    let r = radius as f64;
+    let mut strips: Vec<(u32, u32, u32)> = Vec::new();
+    for row in 0..radius {
+        // distance from the circle center to the middle of this pixel row
+        let dy = r - row as f64 - 0.5;
+        let inset = (r - (r * r - dy * dy).sqrt()).round() as u32;
+        match strips.last_mut() {
+            Some(last) if last.2 == inset => last.1 += 1,
+            _ => strips.push((row, 1, inset)),
+        }
+    }
+    strips
+}
+
+/// Computes the parts of a `width` x `height` window that are not covered by `translucent`.
+///
+/// This is what compositors expect as the opaque region of a window whose effect only covers
+/// `translucent`, e.g. for `wl_surface.set_opaque_region` when the toolkit owns the surface.
+/// The result is made of non-overlapping rectangles. Rounded rectangles of `translucent` count
+/// as their whole bounding box, since what is drawn in their corners is unknown.
+pub fn opaque_region(width: u32, height: u32, translucent: &[Rect]) -> Vec<Rect> {
+    // in i64, so that neither the window size nor the ends of the rects overflow
+    let (width, height) = (i64::from(width), i64::from(height));
+    let holes: Vec<(i64, i64, i64, i64)> = translucent
+        .iter()
+        .map(|r| {
+            let (x, y) = (i64::from(r.x), i64::from(r.y));
+            (
+                x.max(0),
+                y.max(0),
+                (x + i64::from(r.width)).min(width),
+                (y + i64::from(r.height)).min(height),
+            )
+        })
+        .filter(|&(x0, y0, x1, y1)| x0 < x1 && y0 < y1)
+        .collect();
+
+    let mut edges: Vec<i64> = vec![0, height];
+    for &(_, y0, _, y1) in &holes {
+        edges.push(y0);
+        edges.push(y1);
+    }
+    edges.sort_unstable();
+    edges.dedup();
+
+    // `Rect` offsets are `i32`, what starts past `i32::MAX` is clamped to it
+    let offset = |value: i64| value.min(i64::from(i32::MAX)) as i32;
+    let mut opaque: Vec<Rect> = Vec::new();
+    // rects of the previous band, extended downwards while the next band has the same spans
+    let mut previous: Vec<(usize, (i64, i64))> = Vec::new();
+    for band in edges.windows(2) {
+        let (top, bottom) = (band[0], band[1]);
+        let mut covered: Vec<(i64, i64)> = holes
+            .iter()
+            .filter(|&&(_, y0, _, y1)| y0 <= top && bottom <= y1)
+            .map(|&(x0, _, x1, _)| (x0, x1))
+            .collect();
+        covered.sort_unstable();
+
+        let mut spans = Vec::new();
+        let mut x = 0;
+        for (x0, x1) in covered {
+            if x0 > x {
+                spans.push((x, x0));
+            }
+            x = x.max(x1);
+        }
+        if x < width {
+            spans.push((x, width));
+        }
+
+        let same_spans = previous.len() == spans.len()
+            && previous
+                .iter()
+                .zip(&spans)
+                .all(|((_, previous), span)| previous == span);
+        if same_spans {
+            for &(i, _) in &previous {
+                opaque[i].height += (bottom - top) as u32;
+            }
+        } else {
+            previous.clear();
+            for (x0, x1) in spans {
+                previous.push((opaque.len(), (x0, x1)));
+                opaque.push(Rect::new(
+                    offset(x0),
+                    offset(top),
+                    (x1 - x0) as u32,
+                    (bottom - top) as u32,
+                ));
+            }
+        }
+    }
+    opaque
+}
+
+/// The parts of `rects` inside a `width` x `height` window, as square rects: what the
+/// compositor ends up blurring.
+pub(crate) fn visible(rects: &[Rect], width: u32, height: u32) -> Vec<Rect> {
+    flatten(rects)
+        .iter()
+        .filter_map(|r| clip(r, width, height))
+        .collect()
+}
+
+/// The part of a square `rect` inside a `width` x `height` window, if any.
+fn clip(rect: &Rect, width: u32, height: u32) -> Option<Rect> {
+    let (x0, y0) = (i64::from(rect.x).max(0), i64::from(rect.y).max(0));
+    let x1 = (i64::from(rect.x) + i64::from(rect.width)).min(i64::from(width));
+    let y1 = (i64::from(rect.y) + i64::from(rect.height)).min(i64::from(height));
+    if x0 < x1 && y0 < y1 {
+        Some(Rect::new(
+            x0 as i32,
+            y0 as i32,
+            (x1 - x0) as u32,
+            (y1 - y0) as u32,
+        ))
+    } else {
+        None
+    }
+}
+
+/// Encodes `rects` as the `CARDINAL[][4]` list of `x, y, width, height` used by X11 region properties,
+/// clipped to a `width` x `height` window since cardinals can't be negative.
+#[cfg_attr(not(target_os = "linux"), allow(dead_code))]
+pub(crate) fn to_x11_cardinals(rects: &[Rect], width: u32, height: u32) -> Vec<u32> {
+    visible(rects, width, height)
+        .iter()
+        .flat_map(|r| [r.x as u32, r.y as u32, r.width, r.height])
+        .collect()
+}
+
+/// Encodes `rects` as the `x, y, width, height` arguments of `wl_region.add`. The compositor clips
+/// regions to the surface, whose size isn't known here.
+#[cfg_attr(not(target_os = "linux"), allow(dead_code))]
+pub(crate) fn to_wayland_rects(rects: &[Rect]) -> Vec<[i32; 4]> {
+    flatten(rects)
+        .iter()
+        .filter(|r| !r.is_empty())
+        .map(|r| {
+            let clamp = |length: u32| length.min(i32::MAX as u32) as i32;
+            [r.x, r.y, clamp(r.width), clamp(r.height)]
+        })
+        .collect()
+}
+
+/// A length along one axis of the window.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Length {
+    /// Logical pixels, multiplied by the window's scale factor.
+    Logical(f64),
+    /// Percentage of the window's width or height, `100.0` being all of it.
+    Percent(f64),
+}
+
+impl Length {
+    fn resolve(self, extent: f64, scale: f64) -> f64 {
+        match self {
+            Length::Logical(value) => value * scale,
+            Length::Percent(value) => value / 100.0 * extent,
+        }
+    }
+}
+
+/// A rectangle positioned relative to the window edges, resolved like CSS absolute positioning.
+///
+/// Missing offsets default to `0` unless the size and the opposite offset are both set,
+/// so `AnchoredRect { right: Some(..), width: Some(..), .. }` sticks to the right edge.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct AnchoredRect {
+    pub left: Option<Length>,
+    pub top: Option<Length>,
+    pub right: Option<Length>,
+    pub bottom: Option<Length>,
+    pub width: Option<Length>,
+    pub height: Option<Length>,
+    /// Corner radius in logical pixels.
+    pub corner_radius: f64,
+}
+
+impl AnchoredRect {
+    /// The whole window.
+    pub fn fill() -> Self {
+        Self::default()
+    }
+
+    /// A full-height strip along the left edge, e.g. a sidebar.
+    pub fn left(width: Length) -> Self {
+        Self {
+            width: Some(width),
+            ..Default::default()
+        }
+    }
+
+    /// A full-height strip along the right edge.
+    pub fn right(width: Length) -> Self {
+        Self {
+            right: Some(Length::Logical(0.0)),
+            width: Some(width),
+            ..Default::default()
+        }
+    }
+
+    /// A full-width strip along the top edge, e.g. a title bar.
+    pub fn top(height: Length) -> Self {
+        Self {
+            height: Some(height),
+            ..Default::default()
+        }
+    }
+
+    /// A full-width strip along the bottom edge.
+    pub fn bottom(height: Length) -> Self {
+        Self {
+            bottom: Some(Length::Logical(0.0)),
+            height: Some(height),
+            ..Default::default()
+        }
+    }
+
+    pub fn with_corner_radius(mut self, corner_radius: f64) -> Self {
+        self.corner_radius = corner_radius;
+        self
+    }
+
+    /// Resolves this rectangle for a window of `width` x `height` physical pixels.
+    pub fn resolve(&self, width: u32, height: u32, scale: f64) -> Rect {
+        let (x0, x1) = resolve_axis(self.left, self.width, self.right, width as f64, scale);
+        let (y0, y1) = resolve_axis(self.top, self.height, self.bottom, height as f64, scale);
+        let (x0, x1) = (x0.round() as i32, x1.round() as i32);
+        let (y0, y1) = (y0.round() as i32, y1.round() as i32);
+        Rect::new(x0, y0, (x1 - x0).max(0) as u32, (y1 - y0).max(0) as u32)
+            .with_corner_radius((self.corner_radius * scale).round().max(0.0) as u32)
+    }
+}
+
+/// Returns the start and end of a rect along one axis.
+fn resolve_axis(
+    start: Option<Length>,
+    size: Option<Length>,
+    end: Option<Length>,
+    extent: f64,
+    scale: f64,
+) -> (f64, f64) {
+    let resolve = |l: Option<Length>| l.map(|l| l.resolve(extent, scale));
+    match (resolve(start), resolve(size), resolve(end)) {
+        (None, Some(size), Some(end)) => (extent - end - size, extent - end),
+        (start, Some(size), _) => {
+            let start = start.unwrap_or(0.0);
+            (start, start + size)
+        }
+        (start, None, end) => (start.unwrap_or(0.0), extent - end.unwrap_or(0.0)),
+    }
+}
+
+/// A blur region described relative to the window, so it can follow resizes.
+///
+/// ```
+/// use window_vibrancy::{AnchoredRect, Length, RegionLayout};
+///
+/// // a 240px sidebar and a 32px title bar
+/// let layout = RegionLayout::new()
+///     .with(AnchoredRect::left(Length::Logical(240.0)))
+///     .with(AnchoredRect::top(Length::Logical(32.0)));
+/// let rects = layout.resolve(1600, 1200, 2.0);
+/// assert_eq!(rects[0].width, 480);
+/// assert_eq!(rects[1].height, 64);
+/// ```
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct RegionLayout {
+    pub rects: Vec<AnchoredRect>,
+}
+
+impl RegionLayout {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with(mut self, rect: AnchoredRect) -> Self {
+        self.rects.push(rect);
+        self
+    }
+
+    /// Resolves the layout for a window of `width` x `height` physical pixels.
+    pub fn resolve(&self, width: u32, height: u32, scale: f64) -> Vec<Rect> {
+        self.rects
+            .iter()
+            .map(|r| r.resolve(width, height, scale))
+            .filter(|r| !r.is_empty())
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn corner_strips_merge_rows_with_the_same_inset() {
+        assert_eq!(corner_strips(1), [(0, 1, 0)]);
+        assert_eq!(corner_strips(4), [(0, 1, 2), (1, 1, 1), (2, 2, 0)]);
+        let strips = corner_strips(16);
+        assert_eq!(strips.iter().map(|s| s.1).sum::<u32>(), 16);
+        assert!(strips.windows(2).all(|w| w[0].2 > w[1].2));
+    }
+
+    #[test]
+    fn flatten_keeps_square_rects_and_drops_empty_ones() {
+        let rects = [Rect::new(-5, 10, 20, 30), Rect::new(0, 0, 0, 10)];
+        assert_eq!(flatten(&rects), [Rect::new(-5, 10, 20, 30)]);
+    }
+
+    #[test]
+    fn flatten_decomposes_rounded_corners() {
+        let rect = Rect::new(10, 20, 100, 50).with_corner_radius(4);
+        assert_eq!(
+            flatten(&[rect]),
+            [
+                Rect::new(12, 20, 96, 1),
+                Rect::new(11, 21, 98, 1),
+                Rect::new(10, 22, 100, 2),
+                Rect::new(10, 24, 100, 42),
+                Rect::new(10, 66, 100, 2),
+                Rect::new(11, 68, 98, 1),
+                Rect::new(12, 69, 96, 1),
+            ]
+        );
+
+        // the radius is clamped to half of the shortest side, so there is no middle part
+        let flat = flatten(&[Rect::new(0, 0, 8, 8).with_corner_radius(100)]);
+        assert_eq!(flat.iter().map(|r| r.height).sum::<u32>(), 8);
+        assert!(flat.iter().all(|r| r.x >= 0 && r.x as u32 + r.width <= 8));
+    }
+
+    #[test]
+    fn x11_cardinals_are_clipped_to_the_window() {
+        let rects = [
+            Rect::new(-10, -20, 50, 40),
+            Rect::new(90, 90, 50, 50),
+            Rect::new(200, 0, 10, 10),
+            Rect::new(-30, 0, 10, 10),
+        ];
+        assert_eq!(
+            to_x11_cardinals(&rects, 100, 100),
+            [0, 0, 40, 20, 90, 90, 10, 10]
+        );
+    }
+
+    #[test]
+    fn x11_cardinals_of_rounded_rects() {
+        let rect = Rect::new(0, 0, 10, 4).with_corner_radius(2);
+        assert_eq!(
+            to_x11_cardinals(&[rect], 10, 4),
+            [1, 0, 8, 1, 0, 1, 10, 1, 0, 2, 10, 1, 1, 3, 8, 1]
+        );
+    }
+
+    #[test]
+    fn wayland_rects_keep_negative_offsets() {
+        let rects = [Rect::new(-10, -20, 50, 40), Rect::new(0, 0, 10, 0)];
+        assert_eq!(to_wayland_rects(&rects), [[-10, -20, 50, 40]]);
+        assert_eq!(
+            to_wayland_rects(&[Rect::new(0, 0, u32::MAX, 1)]),
+            [[0, 0, i32::MAX, 1]]
+        );
+    }
+
+    #[test]
+    fn opaque_region_is_the_complement() {
+        let opaque = opaque_region(
+            100,
+            50,
+            &[AnchoredRect::left(Length::Logical(30.0)).resolve(100, 50, 1.0)],
+        );
+        assert_eq!(opaque, [Rect::new(30, 0, 70, 50)]);
+        assert_eq!(opaque_region(100, 50, &[]), [Rect::new(0, 0, 100, 50)]);
+    }
+
+    #[test]
+    fn opaque_region_of_huge_sizes() {
+        // the end of the rect is past `i32::MAX`
+        let hole = Rect::new(1, 0, i32::MAX as u32, 10);
+        assert_eq!(
+            opaque_region(100, 50, &[hole]),
+            [Rect::new(0, 0, 1, 10), Rect::new(0, 10, 100, 40)]
+        );
+        assert_eq!(
+            opaque_region(u32::MAX, 1, &[Rect::new(0, 0, 10, 1)]),
+            [Rect::new(10, 0, u32::MAX - 10, 1)]
+        );
+        assert_eq!(
+            opaque_region(u32::MAX, 1, &[Rect::new(0, 0, u32::MAX - 10, 1)]),
+            [Rect::new(i32::MAX, 0, 10, 1)]
+        );
+    }
+}
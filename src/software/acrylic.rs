@@ -0,0 +1,347 @@
+// Copyright 2019-2022 Tauri Programme within The Commons Conservancy
+// SPDX-License-Identifier: Apache-2.0
+// SPDX-License-Identifier: MIT
+
+// The Fluent acrylic recipe, as implemented by WinUI's `AcrylicBrush`:
+// blurred backdrop -> exclusion -> luminosity layer -> color (tint) layer -> noise.
+// https://github.com/microsoft/microsoft-ui-xaml/blob/main/dev/Materials/Acrylic/AcrylicBrush.cpp
+
+use super::{box_blur, Image, ACRYLIC_BLUR_SIGMA};
+use crate::Color;
+
+/// Side of the square texture returned by [`noise_texture`].
+pub const NOISE_TEXTURE_SIZE: u32 = 256;
+
+/// The layers drawn over the blurred backdrop by Windows acrylic.
+///
+/// Use it for surfaces `apply_acrylic` can't reach, like popups drawn inside a window:
+///
+/// ```
+/// use window_vibrancy::software::{AcrylicLayers, Image};
+///
+/// let backdrop = Image::filled(4, 4, (40, 90, 160, 255));
+/// let acrylic = AcrylicLayers::new((32, 32, 32, 255), 0.8).render(&backdrop);
+/// // the same seed always gives the same noise
+/// assert_eq!(acrylic, AcrylicLayers::new((32, 32, 32, 255), 0.8).render(&backdrop));
+/// ```
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct AcrylicLayers {
+    /// Tint color, its alpha is ignored in favor of `tint_opacity`.
+    pub tint: Color,
+    /// Opacity of the tint, between `0.0` and `1.0`.
+    pub tint_opacity: f32,
+    /// Opacity of the luminosity layer, derived from the tint when `None`.
+    pub luminosity_opacity: Option<f32>,
+    /// Opacity of the white exclusion layer that keeps light backdrops from washing out the tint.
+    pub exclusion_opacity: f32,
+    /// Opacity of the noise texture.
+    pub noise_opacity: f32,
+    /// Seed of the noise texture.
+    pub seed: u64,
+}
+
+impl AcrylicLayers {
+    pub fn new(tint: Color, tint_opacity: f32) -> Self {
+        Self {
+            tint,
+            tint_opacity,
+            luminosity_opacity: None,
+            exclusion_opacity: 0.1,
+            noise_opacity: 0.02,
+            seed: 0,
+        }
+    }
+
+    /// The color of the luminosity layer, with its opacity as alpha.
+    ///
+    /// Without an explicit `luminosity_opacity`, the tint's HSV value is clamped to `[0.125, 0.965]`
+    /// and the tint opacity is mapped to `[0.15, 1.03]`, capped to `1.0`.
+    pub fn luminosity_color(&self) -> Color {
+        let tint = rgb(self.tint);
+        if let Some(opacity) = self.luminosity_opacity {
+            return with_alpha(tint, opacity.clamp(0.0, 1.0));
+        }
+
+        let (h, s, v) = rgb_to_hsv(tint);
+        let luminosity = hsv_to_rgb(h, s, v.clamp(0.125, 0.965));
+        let opacity = self.tint_opacity.clamp(0.0, 1.0) * (1.03 - 0.15) + 0.15;
+        with_alpha(luminosity, opacity.min(1.0))
+    }
+
+    /// The color of the tint layer, with its effective opacity as alpha.
+    ///
+    /// When the luminosity opacity is derived from the tint, the tint opacity is scaled down
+    /// depending on its brightness so light tints don't hide the backdrop entirely.
+    pub fn tint_color(&self) -> Color {
+        let mut opacity = self.tint_opacity.clamp(0.0, 1.0);
+        if self.luminosity_opacity.is_none() {
+            opacity *= tint_opacity_modifier(self.tint);
+        }
+        with_alpha(rgb(self.tint), opacity)
+    }
+
+    /// Precomputes the layers into a per-pixel blend function.
+    pub fn build(&self) -> AcrylicBlend {
+        let luminosity = self.luminosity_color();
+        let tint = self.tint_color();
+        AcrylicBlend {
+            exclusion_opacity: self.exclusion_opacity.clamp(0.0, 1.0),
+            luminosity: (rgb(luminosity), luminosity.3 as f32 / 255.0),
+            tint: (rgb(tint), tint.3 as f32 / 255.0),
+            noise_opacity: self.noise_opacity.clamp(0.0, 1.0),
+            noise: noise_texture(self.seed),
+        }
+    }
+
+    /// Blurs `background` and draws the acrylic layers over it.
+    pub fn render(&self, background: &Image) -> Image {
+        let mut image = background.clone();
+        box_blur(&mut image, ACRYLIC_BLUR_SIGMA);
+        self.build().apply(&mut image);
+        image
+    }
+}
+
+/// The acrylic layers of [`AcrylicLayers`], ready to be applied pixel by pixel.
+#[derive(Clone, Debug, PartialEq)]
+pub struct AcrylicBlend {
+    exclusion_opacity: f32,
+    luminosity: ([f32; 3], f32),
+    tint: ([f32; 3], f32),
+    noise_opacity: f32,
+    noise: Image,
+}
+
+impl AcrylicBlend {
+    /// Blends the layers over the already blurred `backdrop` pixel at `(x, y)`.
+    ///
+    /// The position only selects the noise texel, the texture is tiled.
+    pub fn blend(&self, x: u32, y: u32, backdrop: Color) -> Color {
+        let mut c = rgb(backdrop);
+
+        // exclusion with white is an inversion
+        let excluded = [1.0 - c[0], 1.0 - c[1], 1.0 - c[2]];
+        c = mix(c, excluded, self.exclusion_opacity);
+
+        let (luminosity, opacity) = self.luminosity;
+        c = mix(c, set_lum(c, lum(luminosity)), opacity);
+
+        let (tint, opacity) = self.tint;
+        c = mix(c, set_lum(tint, lum(c)), opacity);
+
+        let n = self
+            .noise
+            .pixel(x % NOISE_TEXTURE_SIZE, y % NOISE_TEXTURE_SIZE)
+            .0 as f32
+            / 255.0;
+        c = mix(c, [n, n, n], self.noise_opacity);
+
+        with_alpha(c, backdrop.3 as f32 / 255.0)
+    }
+
+    /// Blends the layers over every pixel of the already blurred `image`.
+    pub fn apply(&self, image: &mut Image) {
+        for y in 0..image.height {
+            for x in 0..image.width {
+                let blended = self.blend(x, y, image.pixel(x, y));
+                image.set_pixel(x, y, blended);
+            }
+        }
+    }
+}
+
+/// A tileable, opaque, grayscale noise texture of [`NOISE_TEXTURE_SIZE`] pixels,
+/// the same for a given `seed`.
+pub fn noise_texture(seed: u64) -> Image {
+    let mut state = seed;
+    let mut image = Image::new(NOISE_TEXTURE_SIZE, NOISE_TEXTURE_SIZE);
+    for pixel in image.data.chunks_exact_mut(4) {
+        let value = (splitmix64(&mut state) >> 56) as u8;
+        pixel.copy_from_slice(&[value, value, value, 255]);
+    }
+    image
+}
+
+/// <https://prng.di.unimi.it/splitmix64.c>
+fn splitmix64(state: &mut u64) -> u64 {
+    *state = state.wrapping_add(0x9e3779b97f4a7c15);
+    let mut z = *state;
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+    z ^ (z >> 31)
+}
+
+/// How much of the requested tint opacity is kept: 45% for white, 90% for mid gray,
+/// 85% for black, with saturated colors suppressed less.
+fn tint_opacity_modifier(tint: Color) -> f32 {
+    const MID_POINT: f32 = 0.5;
+    const WHITE_MAX_OPACITY: f32 = 0.45;
+    const MID_POINT_MAX_OPACITY: f32 = 0.9;
+    const BLACK_MAX_OPACITY: f32 = 0.85;
+
+    let (_, s, v) = rgb_to_hsv(rgb(tint));
+    if v == MID_POINT {
+        return MID_POINT_MAX_OPACITY;
+    }
+
+    let (lowest_max_opacity, max_deviation) = if v > MID_POINT {
+        (WHITE_MAX_OPACITY, 1.0 - MID_POINT)
+    } else {
+        (BLACK_MAX_OPACITY, MID_POINT)
+    };
+    let mut max_suppression = MID_POINT_MAX_OPACITY - lowest_max_opacity;
+    if s > 0.0 {
+        max_suppression *= (1.0 - s * 2.0).max(0.0);
+    }
+    let normalized_deviation = (v - MID_POINT).abs() / max_deviation;
+    MID_POINT_MAX_OPACITY - max_suppression * normalized_deviation
+}
+
+fn rgb(color: Color) -> [f32; 3] {
+    [
+        color.0 as f32 / 255.0,
+        color.1 as f32 / 255.0,
+        color.2 as f32 / 255.0,
+    ]
+}
+
+fn with_alpha(c: [f32; 3], alpha: f32) -> Color {
+    let to_u8 = |v: f32| (v * 255.0).round().clamp(0.0, 255.0) as u8;
+    (to_u8(c[0]), to_u8(c[1]), to_u8(c[2]), to_u8(alpha))
+}
+
+fn mix(a: [f32; 3], b: [f32; 3], t: f32) -> [f32; 3] {
+    [
+        a[0] + (b[0] - a[0]) * t,
+        a[1] + (b[1] - a[1]) * t,
+        a[2] + (b[2] - a[2]) * t,
+    ]
+}
+
+fn rgb_to_hsv(c: [f32; 3]) -> (f32, f32, f32) {
+    let max = c[0].max(c[1]).max(c[2]);
+    let min = c[0].min(c[1]).min(c[2]);
+    let delta = max - min;
+    let h = if delta == 0.0 {
+        0.0
+    } else if max == c[0] {
+        60.0 * ((c[1] - c[2]) / delta).rem_euclid(6.0)
+    } else if max == c[1] {
+        60.0 * ((c[2] - c[0]) / delta + 2.0)
+    } else {
+        60.0 * ((c[0] - c[1]) / delta + 4.0)
+    };
+    let s = if max == 0.0 { 0.0 } else { delta / max };
+    (h, s, max)
+}
+
+fn hsv_to_rgb(h: f32, s: f32, v: f32) -> [f32; 3] {
+    let c = v * s;
+    let x = c * (1.0 - ((h / 60.0).rem_euclid(2.0) - 1.0).abs());
+    let m = v - c;
+    let (r, g, b) = match (h / 60.0) as u32 {
+        0 => (c, x, 0.0),
+        1 => (x, c, 0.0),
+        2 => (0.0, c, x),
+        3 => (0.0, x, c),
+        4 => (x, 0.0, c),
+        _ => (c, 0.0, x),
+    };
+    [r + m, g + m, b + m]
+}
+
+// Non-separable blend modes helpers.
+// https://www.w3.org/TR/compositing-1/#blendingnonseparable
+
+fn lum(c: [f32; 3]) -> f32 {
+    0.3 * c[0] + 0.59 * c[1] + 0.11 * c[2]
+}
+
+fn clip_color(c: [f32; 3]) -> [f32; 3] {
+    let l = lum(c);
+    let n = c[0].min(c[1]).min(c[2]);
+    let x = c[0].max(c[1]).max(c[2]);
+    let mut out = c;
+    if n < 0.0 {
+        for v in &mut out {
+            *v = l + (*v - l) * l / (l - n);
+        }
+    }
+    if x > 1.0 {
+        for v in &mut out {
+            *v = l + (*v - l) * (1.0 - l) / (x - l);
+        }
+    }
+    out
+}
+
+fn set_lum(c: [f32; 3], l: f32) -> [f32; 3] {
+    let d = l - lum(c);
+    clip_color([c[0] + d, c[1] + d, c[2] + d])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// FNV-1a, to pin the whole texture.
+    fn hash(image: &Image) -> u64 {
+        image
+            .as_rgba()
+            .iter()
+            .fold(0xcbf2_9ce4_8422_2325, |hash, &byte| {
+                (hash ^ u64::from(byte)).wrapping_mul(0x0100_0000_01b3)
+            })
+    }
+
+    #[test]
+    fn noise_texture_is_pinned() {
+        let noise = noise_texture(0);
+        assert_eq!(
+            (0..4).map(|x| noise.pixel(x, 0)).collect::<Vec<_>>(),
+            [
+                (226, 226, 226, 255),
+                (110, 110, 110, 255),
+                (6, 6, 6, 255),
+                (248, 248, 248, 255)
+            ]
+        );
+        assert_eq!(hash(&noise), 4660504699819194342);
+        assert_eq!(hash(&noise_texture(42)), 4743518126239934648);
+        assert_ne!(noise, noise_texture(1));
+    }
+
+    #[test]
+    fn luminosity_and_tint_are_pinned() {
+        let layers = AcrylicLayers::new((32, 32, 32, 255), 0.8);
+        // 0.8 * (1.03 - 0.15) + 0.15 = 0.854
+        assert_eq!(layers.luminosity_color(), (32, 32, 32, 218));
+        // dark grays keep about 86% of the tint opacity
+        assert_eq!(layers.tint_color(), (32, 32, 32, 176));
+
+        let light = AcrylicLayers::new((243, 243, 243, 255), 0.5);
+        assert_eq!(light.luminosity_color(), (243, 243, 243, 150));
+        // light grays keep about half of it
+        assert_eq!(light.tint_color(), (243, 243, 243, 63));
+
+        let saturated = AcrylicLayers::new((0, 120, 215, 255), 0.6);
+        assert_eq!(saturated.luminosity_color(), (0, 120, 215, 173));
+        // saturated colors are not suppressed past the mid point
+        assert_eq!(saturated.tint_color(), (0, 120, 215, 138));
+
+        let explicit = AcrylicLayers {
+            luminosity_opacity: Some(0.3),
+            ..AcrylicLayers::new((255, 0, 0, 255), 0.5)
+        };
+        assert_eq!(explicit.luminosity_color(), (255, 0, 0, 77));
+        // an explicit luminosity opacity keeps the tint opacity as is
+        assert_eq!(explicit.tint_color(), (255, 0, 0, 128));
+    }
+
+    #[test]
+    fn blend_is_pinned() {
+        let blend = AcrylicLayers::new((32, 32, 32, 255), 0.8).build();
+        assert_eq!(blend.blend(0, 0, (40, 90, 160, 255)), (35, 46, 62, 255));
+        assert_eq!(blend.blend(7, 3, (250, 250, 250, 128)), (62, 62, 62, 128));
+    }
+}
@@ -0,0 +1,311 @@
+// Copyright 2019-2022 Tauri Programme within The Commons Conservancy
+// SPDX-License-Identifier: Apache-2.0
+// SPDX-License-Identifier: MIT
+
+use super::Image;
+
+/// How to blur an image.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum BlurKind {
+    /// An exact separable Gaussian, its cost grows with the radius.
+    Gaussian,
+    /// Three successive box blurs approximating a Gaussian, in constant time per pixel.
+    Box,
+}
+
+/// Blurs `image` in place with a Gaussian of standard deviation `sigma`, in pixels.
+pub fn blur(image: &mut Image, sigma: f32, kind: BlurKind) {
+    match kind {
+        BlurKind::Gaussian => gaussian_blur(image, sigma),
+        BlurKind::Box => box_blur(image, sigma),
+    }
+}
+
+/// Blurs `image` in place with a separable Gaussian of standard deviation `sigma`.
+///
+/// Colors are blurred premultiplied so transparent pixels don't darken their surroundings,
+/// and pixels past the edges repeat the edge pixels, like compositors do.
+pub fn gaussian_blur(image: &mut Image, sigma: f32) {
+    if sigma <= 0.0 || image.width == 0 || image.height == 0 {
+        return;
+    }
+
+    let radius = (sigma * 3.0).ceil() as isize;
+    let mut kernel: Vec<f32> = (-radius..=radius)
+        .map(|i| (-((i * i) as f32) / (2.0 * sigma * sigma)).exp())
+        .collect();
+    let sum: f32 = kernel.iter().sum();
+    kernel.iter_mut().for_each(|w| *w /= sum);
+
+    let mut buffer = Premultiplied::from_image(image);
+    buffer.convolve(&kernel, true);
+    buffer.convolve(&kernel, false);
+    buffer.write_to(image);
+}
+
+/// Approximates [`gaussian_blur`] with three box blurs, which is much faster for big `sigma`.
+pub fn box_blur(image: &mut Image, sigma: f32) {
+    if sigma <= 0.0 || image.width == 0 || image.height == 0 {
+        return;
+    }
+
+    let mut buffer = Premultiplied::from_image(image);
+    for size in box_sizes(sigma, 3) {
+        let radius = (size / 2) as isize;
+        buffer.box_pass(radius, true);
+        buffer.box_pass(radius, false);
+    }
+    buffer.write_to(image);
+}
+
+/// Odd box widths whose successive application approximates a Gaussian of `sigma`.
+///
+/// <http://blog.ivank.net/fastest-gaussian-blur.html>
+fn box_sizes(sigma: f32, passes: usize) -> Vec<usize> {
+    let n = passes as f32;
+    let ideal = ((12.0 * sigma * sigma / n) + 1.0).sqrt();
+    let mut lower = ideal.floor() as usize;
+    if lower % 2 == 0 {
+        lower = lower.saturating_sub(1);
+    }
+    let lower = lower.max(1);
+    let upper = lower + 2;
+
+    let l = lower as f32;
+    let ideal_count = (12.0 * sigma * sigma - n * l * l - 4.0 * n * l - 3.0 * n) / (-4.0 * l - 4.0);
+    let lower_count = ideal_count.round().max(0.0) as usize;
+
+    (0..passes)
+        .map(|i| if i < lower_count { lower } else { upper })
+        .collect()
+}
+
+/// Premultiplied RGBA in `f32`, so multiple passes don't accumulate rounding errors.
+struct Premultiplied {
+    width: usize,
+    height: usize,
+    data: Vec<[f32; 4]>,
+}
+
+impl Premultiplied {
+    fn from_image(image: &Image) -> Self {
+        let data = image
+            .data
+            .chunks_exact(4)
+            .map(|p| {
+                let a = p[3] as f32 / 255.0;
+                [
+                    p[0] as f32 * a,
+                    p[1] as f32 * a,
+                    p[2] as f32 * a,
+                    p[3] as f32,
+                ]
+            })
+            .collect();
+        Self {
+            width: image.width as usize,
+            height: image.height as usize,
+            data,
+        }
+    }
+
+    fn write_to(&self, image: &mut Image) {
+        for (out, p) in image.data.chunks_exact_mut(4).zip(&self.data) {
+            let a = p[3] / 255.0;
+            let unpremultiply = |c: f32| {
+                if a > 0.0 {
+                    (c / a).round().clamp(0.0, 255.0) as u8
+                } else {
+                    0
+                }
+            };
+            out[0] = unpremultiply(p[0]);
+            out[1] = unpremultiply(p[1]);
+            out[2] = unpremultiply(p[2]);
+            out[3] = p[3].round().clamp(0.0, 255.0) as u8;
+        }
+    }
+
+    /// The lines along which a pass runs, as `(count, length, start(line), stride)`.
+    fn lines(&self, horizontal: bool) -> (usize, usize, usize, usize) {
+        if horizontal {
+            (self.height, self.width, self.width, 1)
+        } else {
+            (self.width, self.height, 1, self.width)
+        }
+    }
+
+    fn convolve(&mut self, kernel: &[f32], horizontal: bool) {
+        let radius = (kernel.len() / 2) as isize;
+        let (count, length, line_step, stride) = self.lines(horizontal);
+        let mut line = vec![[0.0; 4]; length];
+
+        for l in 0..count {
+            let start = l * line_step;
+            for (i, out) in line.iter_mut().enumerate() {
+                let mut acc = [0.0; 4];
+                for (k, w) in kernel.iter().enumerate() {
+                    let j = (i as isize + k as isize - radius).clamp(0, length as isize - 1);
+                    let p = self.data[start + j as usize * stride];
+                    for c in 0..4 {
+                        acc[c] += p[c] * w;
+                    }
+                }
+                *out = acc;
+            }
+            for (i, p) in line.iter().enumerate() {
+                self.data[start + i * stride] = *p;
+            }
+        }
+    }
+
+    fn box_pass(&mut self, radius: isize, horizontal: bool) {
+        if radius == 0 {
+            return;
+        }
+        let (count, length, line_step, stride) = self.lines(horizontal);
+        let size = (2 * radius + 1) as f32;
+        let mut line = vec![[0.0; 4]; length];
+
+        for l in 0..count {
+            let start = l * line_step;
+            let at =
+                |j: isize| self.data[start + j.clamp(0, length as isize - 1) as usize * stride];
+
+            // running sum over the window [i - radius, i + radius]
+            let mut acc = [0.0f32; 4];
+            for j in -radius..=radius {
+                let p = at(j);
+                for c in 0..4 {
+                    acc[c] += p[c];
+                }
+            }
+            for (i, out) in line.iter_mut().enumerate() {
+                let i = i as isize;
+                for c in 0..4 {
+                    out[c] = acc[c] / size;
+                }
+                let (leaving, entering) = (at(i - radius), at(i + radius + 1));
+                for c in 0..4 {
+                    acc[c] += entering[c] - leaving[c];
+                }
+            }
+            for (i, p) in line.iter().enumerate() {
+                self.data[start + i * stride] = *p;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Color;
+
+    /// FNV-1a, to pin bigger outputs.
+    fn hash(image: &Image) -> u64 {
+        image
+            .as_rgba()
+            .iter()
+            .fold(0xcbf2_9ce4_8422_2325, |hash, &byte| {
+                (hash ^ u64::from(byte)).wrapping_mul(0x0100_0000_01b3)
+            })
+    }
+
+    fn impulse(width: u32, height: u32) -> Image {
+        let mut image = Image::filled(width, height, (0, 0, 0, 255));
+        image.set_pixel(width / 2, height / 2, (255, 255, 255, 255));
+        image
+    }
+
+    fn gradient(width: u32, height: u32) -> Image {
+        let mut image = Image::new(width, height);
+        for y in 0..height {
+            for x in 0..width {
+                let color: Color = ((x * 16) as u8, (y * 16) as u8, ((x + y) * 8) as u8, 255);
+                image.set_pixel(x, y, color);
+            }
+        }
+        image
+    }
+
+    fn row(image: &Image, y: u32) -> Vec<u8> {
+        (0..image.width()).map(|x| image.pixel(x, y).0).collect()
+    }
+
+    #[test]
+    fn gaussian_impulse_response() {
+        let mut image = impulse(7, 1);
+        gaussian_blur(&mut image, 1.0);
+        assert_eq!(row(&image, 0), [1, 14, 62, 102, 62, 14, 1]);
+        assert!((0..7).all(|x| image.pixel(x, 0).3 == 255));
+    }
+
+    #[test]
+    fn gaussian_is_separable() {
+        let mut image = impulse(5, 5);
+        gaussian_blur(&mut image, 1.0);
+        assert_eq!(row(&image, 2), [5, 25, 41, 25, 5]);
+        assert_eq!(row(&image, 1), [3, 15, 25, 15, 3]);
+        // past the edges the outer weights pile up on the edge pixels
+        assert_eq!(row(&image, 0), [1, 3, 5, 3, 1]);
+    }
+
+    #[test]
+    fn box_impulse_response() {
+        let mut image = impulse(9, 1);
+        box_blur(&mut image, 1.0);
+        // sigma 1 gives boxes of 1, 1 and 3 pixels
+        assert_eq!(row(&image, 0), [0, 0, 0, 85, 85, 85, 0, 0, 0]);
+    }
+
+    #[test]
+    fn box_sizes_approximate_the_gaussian() {
+        assert_eq!(box_sizes(1.0, 3), [1, 1, 3]);
+        assert_eq!(box_sizes(15.0, 3), [29, 29, 31]);
+    }
+
+    #[test]
+    fn blurs_are_pinned() {
+        let mut gaussian = gradient(16, 16);
+        gaussian_blur(&mut gaussian, 3.0);
+        let mut boxed = gradient(16, 16);
+        box_blur(&mut boxed, 3.0);
+        assert_eq!(hash(&gaussian), 16634209007103481335);
+        assert_eq!(hash(&boxed), 2524548422518550727);
+        // the box approximation stays close to the Gaussian
+        let worst = gaussian
+            .as_rgba()
+            .iter()
+            .zip(boxed.as_rgba())
+            .map(|(a, b)| (i16::from(*a) - i16::from(*b)).abs())
+            .max();
+        assert!(worst.unwrap() <= 6, "{:?}", worst);
+    }
+
+    #[test]
+    fn transparent_pixels_do_not_darken() {
+        for kind in [BlurKind::Gaussian, BlurKind::Box] {
+            let mut image = Image::new(3, 1);
+            image.set_pixel(1, 0, (255, 0, 0, 255));
+            blur(&mut image, 1.0, kind);
+            for x in 0..3 {
+                let (r, g, b, a) = image.pixel(x, 0);
+                assert!(a > 0 && a < 255, "{:?}", kind);
+                assert_eq!((r, g, b), (255, 0, 0), "{:?}", kind);
+            }
+        }
+    }
+
+    #[test]
+    fn uniform_images_and_zero_sigma_are_unchanged() {
+        let mut uniform = Image::filled(8, 8, (10, 200, 30, 128));
+        gaussian_blur(&mut uniform, 2.0);
+        box_blur(&mut uniform, 2.0);
+        assert_eq!(uniform, Image::filled(8, 8, (10, 200, 30, 128)));
+
+        let mut image = gradient(4, 4);
+        blur(&mut image, 0.0, BlurKind::Gaussian);
+        assert_eq!(image, gradient(4, 4));
+    }
+}
@@ -0,0 +1,112 @@
+// Copyright 2019-2022 Tauri Programme within The Commons Conservancy
+// SPDX-License-Identifier: Apache-2.0
+// SPDX-License-Identifier: MIT
+
+use super::{box_blur, mica_layers, Image};
+use crate::Color;
+
+/// Blur strength used by [`render_blur`], close to the one of `apply_blur` on Windows 10.
+pub const BLUR_SIGMA: f32 = 8.0;
+/// Blur strength used by [`render_acrylic`], Fluent's acrylic uses a 30px blur radius.
+pub const ACRYLIC_BLUR_SIGMA: f32 = 15.0;
+/// Blur strength used by [`render_mica`], which is meant to only keep the wallpaper's colors.
+pub const MICA_BLUR_SIGMA: f32 = 60.0;
+
+/// Draws `color` over every pixel of `image`, with its alpha as opacity.
+pub fn tint(image: &mut Image, color: Color) {
+    let a = color.3 as f32 / 255.0;
+    if a == 0.0 {
+        return;
+    }
+    let tint = [color.0 as f32, color.1 as f32, color.2 as f32];
+
+    for pixel in image.data.chunks_exact_mut(4) {
+        // source-over with straight alpha
+        let dst_a = pixel[3] as f32 / 255.0;
+        let out_a = a + dst_a * (1.0 - a);
+        for c in 0..3 {
+            let out = (tint[c] * a + pixel[c] as f32 * dst_a * (1.0 - a)) / out_a;
+            pixel[c] = out.round().clamp(0.0, 255.0) as u8;
+        }
+        pixel[3] = (out_a * 255.0).round() as u8;
+    }
+}
+
+/// The tint `SetWindowCompositionAttribute` ends up drawing for `color`.
+///
+/// Acrylic misbehaves with a fully transparent tint, so its alpha is at least `1`.
+pub(crate) fn swca_tint(color: Option<Color>, acrylic: bool) -> Color {
+    let mut color = color.unwrap_or_default();
+    if acrylic && color.3 == 0 {
+        color.3 = 1;
+    }
+    color
+}
+
+/// Renders what `apply_blur` looks like over `background`.
+pub fn render_blur(background: &Image, color: Option<Color>) -> Image {
+    let mut image = background.clone();
+    box_blur(&mut image, BLUR_SIGMA);
+    tint(&mut image, swca_tint(color, false));
+    image
+}
+
+/// Renders what `apply_acrylic` looks like over `background`, through `SetWindowCompositionAttribute`.
+pub fn render_acrylic(background: &Image, color: Option<Color>) -> Image {
+    let mut image = background.clone();
+    box_blur(&mut image, ACRYLIC_BLUR_SIGMA);
+    tint(&mut image, swca_tint(color, true));
+    image
+}
+
+/// Renders what `apply_mica` looks like over `background`, usually the desktop wallpaper.
+pub fn render_mica(background: &Image, dark: bool) -> Image {
+    let mut image = background.clone();
+    box_blur(&mut image, MICA_BLUR_SIGMA);
+    mica_layers(dark).build().apply(&mut image);
+    image
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn swca_tint_keeps_acrylic_visible() {
+        assert_eq!(swca_tint(None, false), (0, 0, 0, 0));
+        assert_eq!(swca_tint(None, true), (0, 0, 0, 1));
+        assert_eq!(swca_tint(Some((10, 20, 30, 0)), true), (10, 20, 30, 1));
+        assert_eq!(swca_tint(Some((10, 20, 30, 0)), false), (10, 20, 30, 0));
+        assert_eq!(swca_tint(Some((10, 20, 30, 200)), true), (10, 20, 30, 200));
+    }
+
+    #[test]
+    fn tint_is_source_over() {
+        let mut image = Image::filled(1, 1, (0, 0, 255, 255));
+        tint(&mut image, (255, 0, 0, 128));
+        assert_eq!(image.pixel(0, 0), (128, 0, 127, 255));
+
+        let mut transparent = Image::new(1, 1);
+        tint(&mut transparent, (255, 0, 0, 128));
+        assert_eq!(transparent.pixel(0, 0), (255, 0, 0, 128));
+
+        let mut unchanged = Image::filled(1, 1, (1, 2, 3, 4));
+        tint(&mut unchanged, (255, 255, 255, 0));
+        assert_eq!(unchanged.pixel(0, 0), (1, 2, 3, 4));
+    }
+
+    #[test]
+    fn acrylic_without_tint_still_draws_a_black_film() {
+        let white = Image::filled(4, 4, (255, 255, 255, 255));
+        // blur over a uniform image only leaves the tint
+        assert_eq!(render_blur(&white, None), white);
+        assert_eq!(
+            render_acrylic(&white, None),
+            Image::filled(4, 4, (254, 254, 254, 255))
+        );
+        assert_eq!(
+            render_acrylic(&white, Some((18, 18, 18, 125))),
+            Image::filled(4, 4, (139, 139, 139, 255))
+        );
+    }
+}
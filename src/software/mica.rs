@@ -0,0 +1,185 @@
+// Copyright 2019-2022 Tauri Programme within The Commons Conservancy
+// SPDX-License-Identifier: Apache-2.0
+// SPDX-License-Identifier: MIT
+
+// Mica is the desktop wallpaper, blurred until only its colors remain,
+// with a luminosity blend and a tint toward the theme's base color.
+// https://learn.microsoft.com/en-us/windows/apps/design/style/mica
+
+use super::{box_blur, AcrylicLayers, Image, MICA_BLUR_SIGMA};
+use crate::Color;
+
+/// Longest side of the image returned by [`mica_background`].
+pub const MICA_BACKGROUND_SIZE: u32 = 64;
+
+/// The Mica base color and opacity, for light and dark themes.
+const MICA_LIGHT: (Color, f32) = ((243, 243, 243, 255), 0.5);
+const MICA_DARK: (Color, f32) = ((32, 32, 32, 255), 0.8);
+
+/// The layers Mica draws over the blurred wallpaper.
+pub fn mica_layers(dark: bool) -> AcrylicLayers {
+    let (tint, opacity) = if dark { MICA_DARK } else { MICA_LIGHT };
+    AcrylicLayers {
+        luminosity_opacity: Some(1.0),
+        exclusion_opacity: 0.0,
+        noise_opacity: 0.0,
+        ..AcrylicLayers::new(tint, opacity)
+    }
+}
+
+/// Renders a low resolution Mica background from `wallpaper`, at most [`MICA_BACKGROUND_SIZE`]
+/// pixels wide or high.
+///
+/// Mica has no details left, so stretching this image with bilinear filtering over the window
+/// looks the same as rendering it at full size, for a fraction of the cost.
+pub fn mica_background(wallpaper: &Image, dark: bool) -> Image {
+    let scale = MICA_BACKGROUND_SIZE as f32 / wallpaper.width.max(wallpaper.height).max(1) as f32;
+    let scale = scale.min(1.0);
+    let mut image = downsample(
+        wallpaper,
+        ((wallpaper.width as f32 * scale).round() as u32).max(1),
+        ((wallpaper.height as f32 * scale).round() as u32).max(1),
+    );
+    // the same relative blur as `render_mica` on a 1920px wide image
+    let sigma = image.width.max(image.height) as f32 * MICA_BLUR_SIGMA / 1920.0;
+    box_blur(&mut image, sigma);
+    mica_layers(dark).build().apply(&mut image);
+    image
+}
+
+/// The average color of Mica over `wallpaper`, for a flat approximation of the effect.
+pub fn mica_tint_from_wallpaper(wallpaper: &Image, dark: bool) -> Color {
+    let background = mica_background(wallpaper, dark);
+    let count = (background.width as u64 * background.height as u64).max(1);
+    let mut sum = [0u64; 4];
+    for pixel in background.data.chunks_exact(4) {
+        for c in 0..4 {
+            sum[c] += pixel[c] as u64;
+        }
+    }
+    (
+        (sum[0] / count) as u8,
+        (sum[1] / count) as u8,
+        (sum[2] / count) as u8,
+        (sum[3] / count) as u8,
+    )
+}
+
+/// Shrinks `image` to `width` x `height` by averaging the pixels each output pixel covers.
+pub fn downsample(image: &Image, width: u32, height: u32) -> Image {
+    let mut out = Image::new(width, height);
+    if image.width == 0 || image.height == 0 {
+        return out;
+    }
+
+    for y in 0..height {
+        let y0 = (y as u64 * image.height as u64 / height as u64) as u32;
+        let y1 = (((y + 1) as u64 * image.height as u64 / height as u64) as u32).max(y0 + 1);
+        for x in 0..width {
+            let x0 = (x as u64 * image.width as u64 / width as u64) as u32;
+            let x1 = (((x + 1) as u64 * image.width as u64 / width as u64) as u32).max(x0 + 1);
+
+            // average premultiplied, so transparent pixels don't bleed their color
+            let mut sum = [0u64; 4];
+            for sy in y0..y1.min(image.height) {
+                for sx in x0..x1.min(image.width) {
+                    let p = image.pixel(sx, sy);
+                    let a = p.3 as u64;
+                    sum[0] += p.0 as u64 * a;
+                    sum[1] += p.1 as u64 * a;
+                    sum[2] += p.2 as u64 * a;
+                    sum[3] += a;
+                }
+            }
+            let count = ((y1.min(image.height) - y0) * (x1.min(image.width) - x0)) as u64;
+            // fully transparent areas have all sums at 0 and stay transparent black
+            let alpha = sum[3].max(1);
+            out.set_pixel(
+                x,
+                y,
+                (
+                    (sum[0] / alpha) as u8,
+                    (sum[1] / alpha) as u8,
+                    (sum[2] / alpha) as u8,
+                    (sum[3] / count) as u8,
+                ),
+            );
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn downsample_averages_what_each_pixel_covers() {
+        let mut image = Image::new(4, 2);
+        for x in 0..4 {
+            image.set_pixel(x, 0, (x as u8 * 40, 0, 0, 255));
+            image.set_pixel(x, 1, (0, 200, 0, 255));
+        }
+        let small = downsample(&image, 2, 1);
+        assert_eq!((small.width(), small.height()), (2, 1));
+        assert_eq!(small.pixel(0, 0), (10, 100, 0, 255));
+        assert_eq!(small.pixel(1, 0), (50, 100, 0, 255));
+
+        // transparent pixels don't darken their neighbours
+        let mut image = Image::filled(2, 1, (0, 0, 0, 0));
+        image.set_pixel(1, 0, (200, 100, 50, 255));
+        assert_eq!(downsample(&image, 1, 1).pixel(0, 0), (200, 100, 50, 127));
+
+        // growing repeats pixels
+        let grown = downsample(&Image::filled(1, 1, (1, 2, 3, 4)), 3, 2);
+        assert_eq!(grown, Image::filled(3, 2, (1, 2, 3, 4)));
+    }
+
+    #[test]
+    fn background_size() {
+        let background = mica_background(&Image::filled(1920, 1080, (0, 0, 0, 255)), true);
+        assert_eq!((background.width(), background.height()), (64, 36));
+        let background = mica_background(&Image::filled(10, 3000, (0, 0, 0, 255)), true);
+        assert_eq!((background.width(), background.height()), (1, 64));
+        // small wallpapers are not upscaled
+        let background = mica_background(&Image::filled(20, 10, (0, 0, 0, 255)), true);
+        assert_eq!((background.width(), background.height()), (20, 10));
+    }
+
+    #[test]
+    fn solid_wallpaper_tint() {
+        let wallpaper = Image::filled(300, 200, (0, 90, 180, 255));
+        for dark in [false, true] {
+            let mut expected = Image::filled(1, 1, (0, 90, 180, 255));
+            mica_layers(dark).build().apply(&mut expected);
+            assert_eq!(
+                mica_tint_from_wallpaper(&wallpaper, dark),
+                expected.pixel(0, 0)
+            );
+            // the whole background is that color
+            assert_eq!(
+                mica_background(&wallpaper, dark),
+                Image::filled(64, 43, expected.pixel(0, 0))
+            );
+        }
+        assert_eq!(
+            mica_tint_from_wallpaper(&wallpaper, false),
+            (239, 244, 249, 255)
+        );
+        assert_eq!(
+            mica_tint_from_wallpaper(&wallpaper, true),
+            (26, 34, 41, 255)
+        );
+    }
+
+    #[test]
+    fn empty_and_single_pixel_wallpapers() {
+        for (width, height) in [(0, 0), (0, 10), (10, 0), (1, 1)] {
+            let wallpaper = Image::filled(width, height, (10, 20, 30, 255));
+            let background = mica_background(&wallpaper, false);
+            assert!(background.width() >= 1 && background.height() >= 1);
+            mica_tint_from_wallpaper(&wallpaper, true);
+        }
+        assert_eq!(downsample(&Image::new(0, 0), 2, 2), Image::new(2, 2));
+    }
+}
@@ -0,0 +1,122 @@
+// Copyright 2019-2022 Tauri Programme within The Commons Conservancy
+// SPDX-License-Identifier: Apache-2.0
+// SPDX-License-Identifier: MIT
+
+//! CPU rendering of the effects, for when the compositor can't do it.
+//!
+//! These take a snapshot of what is behind the window (or any background the app draws itself)
+//! and produce what the effect would look like, to be drawn into the app's own framebuffer.
+//! They are also a reference for what the native effects do, without needing a window.
+//!
+//! ```
+//! use window_vibrancy::software::{render_acrylic, Image};
+//!
+//! let background = Image::from_rgba(2, 1, vec![255, 0, 0, 255, 0, 0, 255, 255]).unwrap();
+//! let acrylic = render_acrylic(&background, Some((18, 18, 18, 125)));
+//! assert_eq!(acrylic.width(), 2);
+//! ```
+
+mod acrylic;
+mod blur;
+mod compose;
+mod mica;
+
+pub use acrylic::{noise_texture, AcrylicBlend, AcrylicLayers, NOISE_TEXTURE_SIZE};
+pub use blur::{blur, box_blur, gaussian_blur, BlurKind};
+#[cfg(target_os = "windows")]
+pub(crate) use compose::swca_tint;
+pub use compose::{
+    render_acrylic, render_blur, render_mica, tint, ACRYLIC_BLUR_SIGMA, BLUR_SIGMA, MICA_BLUR_SIGMA,
+};
+pub use mica::{
+    downsample, mica_background, mica_layers, mica_tint_from_wallpaper, MICA_BACKGROUND_SIZE,
+};
+
+use crate::Color;
+
+/// An 8-bit RGBA image with straight (not premultiplied) alpha, rows from top to bottom.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct Image {
+    width: u32,
+    height: u32,
+    data: Vec<u8>,
+}
+
+impl Image {
+    /// A fully transparent image.
+    pub fn new(width: u32, height: u32) -> Self {
+        Self {
+            width,
+            height,
+            data: vec![0; width as usize * height as usize * 4],
+        }
+    }
+
+    /// Wraps RGBA pixels, returns `None` if `data` is not `width * height * 4` bytes long.
+    pub fn from_rgba(width: u32, height: u32, data: Vec<u8>) -> Option<Self> {
+        if data.len() == width as usize * height as usize * 4 {
+            Some(Self {
+                width,
+                height,
+                data,
+            })
+        } else {
+            None
+        }
+    }
+
+    /// An image filled with `color`.
+    pub fn filled(width: u32, height: u32, color: Color) -> Self {
+        let mut image = Self::new(width, height);
+        for pixel in image.data.chunks_exact_mut(4) {
+            pixel.copy_from_slice(&[color.0, color.1, color.2, color.3]);
+        }
+        image
+    }
+
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    pub fn as_rgba(&self) -> &[u8] {
+        &self.data
+    }
+
+    pub fn as_rgba_mut(&mut self) -> &mut [u8] {
+        &mut self.data
+    }
+
+    pub fn into_rgba(self) -> Vec<u8> {
+        self.data
+    }
+
+    /// # Panics
+    ///
+    /// Panics if the pixel is out of bounds.
+    pub fn pixel(&self, x: u32, y: u32) -> Color {
+        let i = self.index(x, y);
+        (
+            self.data[i],
+            self.data[i + 1],
+            self.data[i + 2],
+            self.data[i + 3],
+        )
+    }
+
+    /// # Panics
+    ///
+    /// Panics if the pixel is out of bounds.
+    pub fn set_pixel(&mut self, x: u32, y: u32, color: Color) {
+        let i = self.index(x, y);
+        self.data[i..i + 4].copy_from_slice(&[color.0, color.1, color.2, color.3]);
+    }
+
+    fn index(&self, x: u32, y: u32) -> usize {
+        assert!(x < self.width && y < self.height, "pixel out of bounds");
+        (y as usize * self.width as usize + x as usize) * 4
+    }
+}
@@ -0,0 +1,78 @@
+// Copyright 2019-2022 Tauri Programme within The Commons Conservancy
+// SPDX-License-Identifier: Apache-2.0
+// SPDX-License-Identifier: MIT
+
+//! Diagnostics, emitted through `tracing` or `log` when their feature is enabled.
+//! `tracing` wins when both are, and without either everything compiles away.
+
+use raw_window_handle::RawWindowHandle;
+
+use crate::Error;
+
+#[cfg(feature = "tracing")]
+macro_rules! trace_debug {
+    ($($arg:tt)+) => { tracing::debug!($($arg)+) };
+}
+#[cfg(all(feature = "log", not(feature = "tracing")))]
+macro_rules! trace_debug {
+    ($($arg:tt)+) => { log::debug!($($arg)+) };
+}
+#[cfg(not(any(feature = "log", feature = "tracing")))]
+macro_rules! trace_debug {
+    // keeps the arguments type checked and used, without evaluating them
+    ($($arg:tt)+) => {
+        if false {
+            let _ = format_args!($($arg)+);
+        }
+    };
+}
+
+#[cfg(feature = "tracing")]
+macro_rules! trace_warn {
+    ($($arg:tt)+) => { tracing::warn!($($arg)+) };
+}
+#[cfg(all(feature = "log", not(feature = "tracing")))]
+macro_rules! trace_warn {
+    ($($arg:tt)+) => { log::warn!($($arg)+) };
+}
+#[cfg(not(any(feature = "log", feature = "tracing")))]
+macro_rules! trace_warn {
+    ($($arg:tt)+) => {
+        if false {
+            let _ = format_args!($($arg)+);
+        }
+    };
+}
+
+pub(crate) use {trace_debug, trace_warn};
+
+/// Runs the platform call of `operation`, in a span with the kind of window it targets,
+/// and reports its result.
+pub(crate) fn instrument<T>(
+    operation: &'static str,
+    handle: RawWindowHandle,
+    f: impl FnOnce() -> Result<T, Error>,
+) -> Result<T, Error> {
+    #[cfg(feature = "tracing")]
+    let _span =
+        tracing::debug_span!("window_vibrancy", operation, handle = handle_kind(&handle)).entered();
+    trace_debug!("{}() on a {} window", operation, handle_kind(&handle));
+
+    let result = f();
+    match &result {
+        Ok(_) => trace_debug!("{}() succeeded", operation),
+        Err(e) => trace_warn!("{}() failed: {}", operation, e),
+    }
+    result
+}
+
+fn handle_kind(handle: &RawWindowHandle) -> &'static str {
+    match handle {
+        RawWindowHandle::Win32(_) => "Win32",
+        RawWindowHandle::AppKit(_) => "AppKit",
+        RawWindowHandle::Xlib(_) => "Xlib",
+        RawWindowHandle::Xcb(_) => "Xcb",
+        RawWindowHandle::Wayland(_) => "Wayland",
+        _ => "unsupported",
+    }
+}
@@ -0,0 +1,428 @@
+// Copyright 2019-2022 Tauri Programme within The Commons Conservancy
+// SPDX-License-Identifier: Apache-2.0
+// SPDX-License-Identifier: MIT
+
+// End-user overrides, so users on slow machines can turn the effects of every app down.
+// The installed configuration is behind a lock, read by every `apply_*` function.
+
+#[cfg(feature = "user-config")]
+pub use config::{EffectLevel, UserConfig};
+
+/// Whether the installed user configuration lets `operation` touch the window.
+#[cfg(feature = "user-config")]
+pub(crate) fn permits(operation: &'static str, level: EffectLevel) -> bool {
+    match UserConfig::installed() {
+        Some(config) if !config.permits(level) => {
+            crate::trace::trace_debug!(
+                "{}() skipped, the user configuration limits effects to {:?}",
+                operation,
+                config.max
+            );
+            false
+        }
+        _ => true,
+    }
+}
+
+/// The tint to use instead of `color`, when the user configuration forces one.
+#[cfg(feature = "user-config")]
+pub(crate) fn tint(color: Option<crate::Color>) -> Option<crate::Color> {
+    UserConfig::installed()
+        .and_then(|config| config.tint)
+        .or(color)
+}
+
+#[cfg(not(feature = "user-config"))]
+pub(crate) fn permits(_operation: &'static str, _level: EffectLevel) -> bool {
+    true
+}
+
+#[cfg(not(feature = "user-config"))]
+pub(crate) fn tint(color: Option<crate::Color>) -> Option<crate::Color> {
+    color
+}
+
+#[cfg(not(feature = "user-config"))]
+#[derive(Clone, Copy)]
+pub(crate) enum EffectLevel {
+    Opacity,
+    Blur,
+    All,
+}
+
+#[cfg(feature = "user-config")]
+mod config {
+    use std::{
+        collections::HashMap,
+        env, fs, io,
+        path::{Path, PathBuf},
+        ptr,
+        str::FromStr,
+        sync::{
+            atomic::{AtomicPtr, Ordering},
+            Once, RwLock,
+        },
+    };
+
+    use serde::Deserialize;
+
+    use crate::{descriptor::parse_hex_color, Color, Error};
+
+    /// The environment variable overriding the configuration file.
+    const ENV: &str = "WINDOW_VIBRANCY";
+
+    /// How far effects may go, from no effect at all to every effect.
+    #[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Deserialize)]
+    #[serde(rename_all = "lowercase")]
+    pub enum EffectLevel {
+        /// No effect is applied.
+        Off,
+        /// Only the `opacity(..)` step of an [`EffectDescriptor`](crate::EffectDescriptor).
+        Opacity,
+        /// Blur too: [`apply_blur`](crate::apply_blur), [`apply_blur_region`](crate::apply_blur_region),
+        /// [`apply_vibrancy`](crate::apply_vibrancy) and the Linux presets.
+        Blur,
+        /// Acrylic, Mica and Tabbed too.
+        All,
+    }
+
+    impl FromStr for EffectLevel {
+        type Err = Error;
+
+        fn from_str(s: &str) -> Result<Self, Error> {
+            match s.to_ascii_lowercase().as_str() {
+                "off" => Ok(EffectLevel::Off),
+                "opacity" => Ok(EffectLevel::Opacity),
+                "blur" => Ok(EffectLevel::Blur),
+                "all" => Ok(EffectLevel::All),
+                _ => Err(Error::Config(format!(
+                    "unknown effect level \"{}\", expected off, opacity, blur or all",
+                    s
+                ))),
+            }
+        }
+    }
+
+    /// What the user allows the effects of an application to do.
+    ///
+    /// Nothing reads it until it is [installed](UserConfig::install), applications opt in with:
+    ///
+    /// ```no_run
+    /// window_vibrancy::UserConfig::load("com.example.editor")
+    ///     .unwrap_or_default()
+    ///     .install();
+    /// ```
+    ///
+    /// The configuration file, `$XDG_CONFIG_HOME/window-vibrancy/config.toml`, has global
+    /// settings and per-application tables overriding them:
+    ///
+    /// ```toml
+    /// # "on" or "off", "off" is `max = "off"`
+    /// effects = "on"
+    /// # "off", "opacity", "blur" or "all"
+    /// max = "opacity"
+    /// # replaces the color given to blur and acrylic
+    /// tint = "#202020c0"
+    ///
+    /// [app."com.example.editor"]
+    /// max = "all"
+    /// ```
+    ///
+    /// The `WINDOW_VIBRANCY` environment variable overrides both, with `off`, `on` or
+    /// comma-separated settings like `max=blur,tint=#00000080`.
+    ///
+    /// Effects above [`max`](UserConfig::max) are skipped: the `apply_*` functions return
+    /// `Ok` without touching the window. `clear_*` functions and `set_dark_mode` are not
+    /// affected.
+    #[derive(Clone, Copy, Debug, PartialEq, Eq)]
+    pub struct UserConfig {
+        pub max: EffectLevel,
+        /// The tint to use instead of the one the application asks for.
+        pub tint: Option<Color>,
+    }
+
+    impl Default for UserConfig {
+        fn default() -> Self {
+            Self {
+                max: EffectLevel::All,
+                tint: None,
+            }
+        }
+    }
+
+    #[derive(Default, Deserialize)]
+    #[serde(deny_unknown_fields)]
+    struct Table {
+        effects: Option<Switch>,
+        max: Option<EffectLevel>,
+        tint: Option<String>,
+    }
+
+    #[derive(Default, Deserialize)]
+    #[serde(deny_unknown_fields)]
+    struct File {
+        effects: Option<Switch>,
+        max: Option<EffectLevel>,
+        tint: Option<String>,
+        #[serde(default)]
+        app: HashMap<String, Table>,
+    }
+
+    #[derive(Clone, Copy, Deserialize)]
+    #[serde(rename_all = "lowercase")]
+    enum Switch {
+        On,
+        Off,
+    }
+
+    /// The installed configuration, see [`installed_config`].
+    static INSTALLED_CONFIG: AtomicPtr<RwLock<Option<UserConfig>>> =
+        AtomicPtr::new(ptr::null_mut());
+
+    /// The lock holding the installed configuration, created on first use as `RwLock::new`
+    /// can't be called in a `static` on the minimum supported Rust version.
+    fn installed_config() -> &'static RwLock<Option<UserConfig>> {
+        static CREATE: Once = Once::new();
+        CREATE.call_once(|| {
+            let lock = Box::into_raw(Box::new(RwLock::new(None)));
+            INSTALLED_CONFIG.store(lock, Ordering::Release);
+        });
+        // set once above and never freed
+        unsafe { &*INSTALLED_CONFIG.load(Ordering::Acquire) }
+    }
+
+    impl UserConfig {
+        /// `$XDG_CONFIG_HOME/window-vibrancy/config.toml`, `~/.config` being the default of
+        /// `$XDG_CONFIG_HOME`, or `%APPDATA%\window-vibrancy\config.toml` on Windows.
+        pub fn path() -> Option<PathBuf> {
+            let config_home = env::var_os("XDG_CONFIG_HOME")
+                .filter(|dir| !dir.is_empty())
+                .map(PathBuf::from);
+            #[cfg(target_os = "windows")]
+            let config_home = config_home.or_else(|| env::var_os("APPDATA").map(PathBuf::from));
+            #[cfg(not(target_os = "windows"))]
+            let config_home = config_home
+                .or_else(|| env::var_os("HOME").map(|home| Path::new(&home).join(".config")));
+            config_home.map(|dir| dir.join("window-vibrancy").join("config.toml"))
+        }
+
+        /// Reads the configuration file of [`path`](Self::path), if there is one, then the
+        /// `WINDOW_VIBRANCY` environment variable.
+        pub fn load(app_id: &str) -> Result<Self, Error> {
+            Self::from_sources(
+                Self::path().as_deref(),
+                env::var(ENV).ok().as_deref(),
+                app_id,
+            )
+        }
+
+        /// Reads the configuration file at `file`, if it exists, then applies the `env` override.
+        pub fn from_sources(
+            file: Option<&Path>,
+            env: Option<&str>,
+            app_id: &str,
+        ) -> Result<Self, Error> {
+            let toml = match file.map(fs::read_to_string) {
+                Some(Ok(toml)) => Some(toml),
+                Some(Err(e)) if e.kind() != io::ErrorKind::NotFound => return Err(e.into()),
+                _ => None,
+            };
+            let mut config = match (toml, file) {
+                (Some(toml), Some(file)) => Self::from_toml(&toml, app_id)
+                    .map_err(|e| Error::Config(format!("{}: {}", file.display(), e)))?,
+                _ => Self::default(),
+            };
+            if let Some(env) = env.filter(|env| !env.trim().is_empty()) {
+                config = config.with_override(env)?;
+            }
+            Ok(config)
+        }
+
+        /// Parses a configuration file, keeping the global settings overridden by the table of
+        /// `app_id`.
+        pub fn from_toml(toml: &str, app_id: &str) -> Result<Self, Error> {
+            let file: File = toml::from_str(toml)
+                .map_err(|e| Error::Config(format!("invalid user configuration: {}", e)))?;
+            let mut config = Self::default();
+            config.merge(&Table {
+                effects: file.effects,
+                max: file.max,
+                tint: file.tint,
+            })?;
+            if let Some(table) = file.app.get(app_id) {
+                config.merge(table)?;
+            }
+            Ok(config)
+        }
+
+        /// Applies settings written like the `WINDOW_VIBRANCY` environment variable.
+        pub fn with_override(mut self, settings: &str) -> Result<Self, Error> {
+            let mut table = Table::default();
+            for setting in settings.split(',').map(str::trim).filter(|s| !s.is_empty()) {
+                let (key, value) = match setting.split_once('=') {
+                    Some((key, value)) => (key.trim(), value.trim()),
+                    None => ("effects", setting),
+                };
+                match key {
+                    "effects" => {
+                        table.effects = Some(match value.to_ascii_lowercase().as_str() {
+                            "on" => Switch::On,
+                            "off" => Switch::Off,
+                            _ => {
+                                return Err(Error::Config(format!(
+                                    "invalid {} setting \"{}\", expected on or off",
+                                    ENV, setting
+                                )))
+                            }
+                        })
+                    }
+                    "max" => table.max = Some(value.parse()?),
+                    "tint" => table.tint = Some(value.to_string()),
+                    _ => {
+                        return Err(Error::Config(format!(
+                            "unknown {} setting \"{}\", expected effects, max or tint",
+                            ENV, key
+                        )))
+                    }
+                }
+            }
+            self.merge(&table)?;
+            Ok(self)
+        }
+
+        fn merge(&mut self, table: &Table) -> Result<(), Error> {
+            match (table.effects, table.max) {
+                (Some(Switch::Off), _) => self.max = EffectLevel::Off,
+                (_, Some(max)) => self.max = max,
+                (Some(Switch::On), None) => self.max = EffectLevel::All,
+                (None, None) => {}
+            }
+            if let Some(tint) = &table.tint {
+                self.tint = Some(parse_hex_color(tint).ok_or_else(|| {
+                    Error::Config(format!(
+                        "invalid tint \"{}\", expected #rgb, #rgba, #rrggbb or #rrggbbaa",
+                        tint
+                    ))
+                })?);
+            }
+            Ok(())
+        }
+
+        /// Whether effects of `level` are allowed.
+        pub fn permits(&self, level: EffectLevel) -> bool {
+            level <= self.max
+        }
+
+        /// Makes every `apply_*` function of the crate follow this configuration.
+        pub fn install(self) {
+            *installed_config()
+                .write()
+                .unwrap_or_else(|e| e.into_inner()) = Some(self);
+        }
+
+        /// Stops following the installed configuration.
+        pub fn uninstall() {
+            *installed_config()
+                .write()
+                .unwrap_or_else(|e| e.into_inner()) = None;
+        }
+
+        pub fn installed() -> Option<Self> {
+            *installed_config().read().unwrap_or_else(|e| e.into_inner())
+        }
+    }
+}
+
+#[cfg(all(test, feature = "user-config"))]
+mod tests {
+    use std::{env, fs};
+
+    use super::*;
+    use crate::Error;
+
+    const TOML: &str = r##"
+        max = "blur"
+        tint = "#202020c0"
+
+        [app."com.example.editor"]
+        max = "all"
+
+        [app."com.example.player"]
+        effects = "off"
+    "##;
+
+    fn config(max: EffectLevel, tint: Option<crate::Color>) -> UserConfig {
+        UserConfig { max, tint }
+    }
+
+    #[test]
+    fn apps_override_global_settings() {
+        let gray = Some((32, 32, 32, 192));
+        let parse = |app| UserConfig::from_toml(TOML, app).unwrap();
+        assert_eq!(parse("com.example.other"), config(EffectLevel::Blur, gray));
+        assert_eq!(parse("com.example.editor"), config(EffectLevel::All, gray));
+        assert_eq!(parse("com.example.player"), config(EffectLevel::Off, gray));
+    }
+
+    #[test]
+    fn env_overrides_the_file() {
+        let path = env::temp_dir().join(format!(
+            "window-vibrancy-user-config-{}.toml",
+            std::process::id()
+        ));
+        fs::write(&path, TOML).unwrap();
+        let load = |env, app| UserConfig::from_sources(Some(&path), env, app).unwrap();
+
+        assert_eq!(
+            load(None, "com.example.editor"),
+            config(EffectLevel::All, Some((32, 32, 32, 192)))
+        );
+        assert_eq!(
+            load(Some("max=opacity, tint=#fff"), "com.example.editor"),
+            config(EffectLevel::Opacity, Some((255, 255, 255, 255)))
+        );
+        // `on` lifts the limit of the file, and of the app
+        assert_eq!(load(Some("on"), "com.example.player").max, EffectLevel::All);
+        assert_eq!(
+            load(Some("off"), "com.example.editor").max,
+            EffectLevel::Off
+        );
+        assert_eq!(load(Some("  "), "com.example.other").max, EffectLevel::Blur);
+        fs::remove_file(&path).unwrap();
+
+        // a missing file is no configuration
+        assert_eq!(load(None, "com.example.editor"), UserConfig::default());
+    }
+
+    #[test]
+    fn invalid_settings() {
+        let message = |result: Result<UserConfig, Error>| match result {
+            Err(Error::Config(message)) => message,
+            other => panic!("expected a configuration error, got {:?}", other),
+        };
+        assert!(message(UserConfig::default().with_override("tint=gray"))
+            .contains("expected #rgb, #rgba, #rrggbb or #rrggbbaa"));
+        assert!(message(UserConfig::default().with_override("max=some"))
+            .contains("unknown effect level"));
+        assert!(message(UserConfig::default().with_override("speed=1"))
+            .contains("unknown WINDOW_VIBRANCY setting"));
+        assert!(
+            message(UserConfig::from_toml("maximum = \"all\"", "app")).contains("unknown field")
+        );
+    }
+
+    #[test]
+    fn installs() {
+        let installed = config(EffectLevel::Opacity, Some((1, 2, 3, 4)));
+        installed.install();
+        assert_eq!(UserConfig::installed(), Some(installed));
+        assert!(!permits("apply_blur", EffectLevel::Blur));
+        assert!(permits("set_opacity", EffectLevel::Opacity));
+        assert_eq!(tint(Some((9, 9, 9, 9))), Some((1, 2, 3, 4)));
+
+        UserConfig::uninstall();
+        assert_eq!(UserConfig::installed(), None);
+        assert!(permits("apply_blur", EffectLevel::Blur));
+        assert_eq!(tint(None), None);
+    }
+}
@@ -7,27 +7,40 @@
 #![allow(non_camel_case_types)]
 #![allow(clippy::upper_case_acronyms)]
 
-use std::ffi::c_void;
+use std::{cell::RefCell, ffi::c_void, num::NonZeroIsize, time::Instant};
+
+use raw_window_handle::{HandleError, HasWindowHandle, Win32WindowHandle, WindowHandle};
+use windows_sys::Win32::UI::{
+    Accessibility::{HCF_HIGHCONTRASTON, HIGHCONTRASTW},
+    Shell::{DefSubclassProc, GetWindowSubclass, RemoveWindowSubclass, SetWindowSubclass},
+    WindowsAndMessaging::{
+        GetLayeredWindowAttributes, GetPropW, GetWindowLongW, GetWindowRect, IsWindow, IsZoomed,
+        KillTimer, RemovePropW, SetLayeredWindowAttributes, SetPropW, SetTimer, SetWindowLongW,
+        SystemParametersInfoW, GWL_EXSTYLE, LWA_ALPHA, LWA_COLORKEY, SPI_GETHIGHCONTRAST,
+        WM_ENTERSIZEMOVE, WM_EXITSIZEMOVE, WM_NCDESTROY, WM_TIMER, WS_EX_LAYERED,
+    },
+};
 pub use windows_sys::Win32::{
     Foundation::*,
     Graphics::{Dwm::*, Gdi::*},
-    System::LibraryLoader::*,
+    System::{LibraryLoader::*, Registry::*},
 };
 
-use crate::{Color, Error};
+use crate::{
+    diagnostics::{DisplayServer, Report},
+    region::Rect,
+    trace::{trace_debug, trace_warn},
+    Color, Effect, Error, MoveSizeCommand, MoveSizeMachine, MoveSizePolicy, WindowState,
+};
 
 pub fn apply_blur(hwnd: HWND, color: Option<Color>) -> Result<(), Error> {
     if is_win7() {
-        let bb = DWM_BLURBEHIND {
-            dwFlags: DWM_BB_ENABLE,
-            fEnable: true.into(),
-            hRgnBlur: HRGN::default(),
-            fTransitionOnMaximized: 0,
-        };
+        strategy("DwmEnableBlurBehindWindow");
         unsafe {
-            let _ = DwmEnableBlurBehindWindow(hwnd, &bb);
+            let _ = DwmEnableBlurBehindWindow(hwnd, &blur_behind(true, HRGN::default()));
         }
     } else if is_swca_supported() {
+        strategy("SetWindowCompositionAttribute with ACCENT_ENABLE_BLURBEHIND");
         unsafe {
             SetWindowCompositionAttribute(hwnd, ACCENT_STATE::ACCENT_ENABLE_BLURBEHIND, color);
         }
@@ -39,18 +52,44 @@ pub fn apply_blur(hwnd: HWND, color: Option<Color>) -> Result<(), Error> {
     Ok(())
 }
 
+pub fn apply_blur_region(hwnd: HWND, rects: &[Rect]) -> Result<(), Error> {
+    if is_win7() {
+        strategy("DwmEnableBlurBehindWindow with a blur region");
+        unsafe {
+            let region = CreateRectRgn(0, 0, 0, 0);
+            for rect in rects.iter().filter(|r| !r.is_empty()) {
+                let (left, top) = (rect.x, rect.y);
+                let (right, bottom) = (left + rect.width as i32, top + rect.height as i32);
+                let radius = rect.effective_radius() as i32;
+                let part = if radius > 0 {
+                    // the bottom-right edges of round rect regions are exclusive
+                    CreateRoundRectRgn(left, top, right + 1, bottom + 1, radius * 2, radius * 2)
+                } else {
+                    CreateRectRgn(left, top, right, bottom)
+                };
+                CombineRgn(region, region, part, RGN_OR);
+                DeleteObject(part);
+            }
+
+            let _ = DwmEnableBlurBehindWindow(hwnd, &blur_behind(true, region));
+            DeleteObject(region);
+        }
+    } else {
+        return Err(Error::UnsupportedPlatformVersion(
+            "\"apply_blur_region()\" is only available on Windows 7.",
+        ));
+    }
+    Ok(())
+}
+
 pub fn clear_blur(hwnd: HWND) -> Result<(), Error> {
     if is_win7() {
-        let bb = DWM_BLURBEHIND {
-            dwFlags: DWM_BB_ENABLE,
-            fEnable: false.into(),
-            hRgnBlur: HRGN::default(),
-            fTransitionOnMaximized: 0,
-        };
+        strategy("DwmEnableBlurBehindWindow");
         unsafe {
-            let _ = DwmEnableBlurBehindWindow(hwnd, &bb);
+            let _ = DwmEnableBlurBehindWindow(hwnd, &blur_behind(false, HRGN::default()));
         }
     } else if is_swca_supported() {
+        strategy("SetWindowCompositionAttribute with ACCENT_DISABLED");
         unsafe {
             SetWindowCompositionAttribute(hwnd, ACCENT_STATE::ACCENT_DISABLED, None);
         }
@@ -62,8 +101,28 @@ pub fn clear_blur(hwnd: HWND) -> Result<(), Error> {
     Ok(())
 }
 
+/// The blur behind the window, or of `region` only if it is not null.
+///
+/// DWM's own transition to an opaque frame for maximized windows is left off: it only
+/// covers maximized windows, and only on Windows 7. What happens to the effect of a
+/// maximized window is up to the [`WindowStatePolicy`](crate::WindowStatePolicy) of a
+/// [`VibrancyController`](crate::VibrancyController), which clears it on every version.
+fn blur_behind(enable: bool, region: HRGN) -> DWM_BLURBEHIND {
+    DWM_BLURBEHIND {
+        dwFlags: if region == HRGN::default() {
+            DWM_BB_ENABLE
+        } else {
+            DWM_BB_ENABLE | DWM_BB_BLURREGION
+        },
+        fEnable: enable.into(),
+        hRgnBlur: region,
+        fTransitionOnMaximized: false.into(),
+    }
+}
+
 pub fn apply_acrylic(hwnd: HWND, color: Option<Color>) -> Result<(), Error> {
     if is_backdroptype_supported() {
+        strategy("DWMWA_SYSTEMBACKDROP_TYPE with DWMSBT_TRANSIENTWINDOW");
         unsafe {
             DwmSetWindowAttribute(
                 hwnd,
@@ -73,6 +132,7 @@ pub fn apply_acrylic(hwnd: HWND, color: Option<Color>) -> Result<(), Error> {
             );
         }
     } else if is_swca_supported() {
+        strategy("SetWindowCompositionAttribute with ACCENT_ENABLE_ACRYLICBLURBEHIND");
         unsafe {
             SetWindowCompositionAttribute(
                 hwnd,
@@ -90,6 +150,7 @@ pub fn apply_acrylic(hwnd: HWND, color: Option<Color>) -> Result<(), Error> {
 
 pub fn clear_acrylic(hwnd: HWND) -> Result<(), Error> {
     if is_backdroptype_supported() {
+        strategy("DWMWA_SYSTEMBACKDROP_TYPE with DWMSBT_DISABLE");
         unsafe {
             DwmSetWindowAttribute(
                 hwnd,
@@ -99,6 +160,7 @@ pub fn clear_acrylic(hwnd: HWND) -> Result<(), Error> {
             );
         }
     } else if is_swca_supported() {
+        strategy("SetWindowCompositionAttribute with ACCENT_DISABLED");
         unsafe {
             SetWindowCompositionAttribute(hwnd, ACCENT_STATE::ACCENT_DISABLED, None);
         }
@@ -123,6 +185,7 @@ pub fn apply_mica(hwnd: HWND, dark: Option<bool>) -> Result<(), Error> {
     }
 
     if is_backdroptype_supported() {
+        strategy("DWMWA_SYSTEMBACKDROP_TYPE with DWMSBT_MAINWINDOW");
         unsafe {
             DwmSetWindowAttribute(
                 hwnd,
@@ -132,6 +195,7 @@ pub fn apply_mica(hwnd: HWND, dark: Option<bool>) -> Result<(), Error> {
             );
         }
     } else if is_undocumented_mica_supported() {
+        strategy("the undocumented DWMWA_MICA_EFFECT");
         unsafe {
             DwmSetWindowAttribute(hwnd, DWMWA_MICA_EFFECT as _, &1 as *const _ as _, 4);
         }
@@ -145,6 +209,7 @@ pub fn apply_mica(hwnd: HWND, dark: Option<bool>) -> Result<(), Error> {
 
 pub fn clear_mica(hwnd: HWND) -> Result<(), Error> {
     if is_backdroptype_supported() {
+        strategy("DWMWA_SYSTEMBACKDROP_TYPE with DWMSBT_DISABLE");
         unsafe {
             DwmSetWindowAttribute(
                 hwnd,
@@ -154,6 +219,7 @@ pub fn clear_mica(hwnd: HWND) -> Result<(), Error> {
             );
         }
     } else if is_undocumented_mica_supported() {
+        strategy("the undocumented DWMWA_MICA_EFFECT");
         unsafe {
             DwmSetWindowAttribute(hwnd, DWMWA_MICA_EFFECT as _, &0 as *const _ as _, 4);
         }
@@ -178,6 +244,7 @@ pub fn apply_tabbed(hwnd: HWND, dark: Option<bool>) -> Result<(), Error> {
     }
 
     if is_backdroptype_supported() {
+        strategy("DWMWA_SYSTEMBACKDROP_TYPE with DWMSBT_TABBEDWINDOW");
         unsafe {
             DwmSetWindowAttribute(
                 hwnd,
@@ -196,6 +263,7 @@ pub fn apply_tabbed(hwnd: HWND, dark: Option<bool>) -> Result<(), Error> {
 
 pub fn clear_tabbed(hwnd: HWND) -> Result<(), Error> {
     if is_backdroptype_supported() {
+        strategy("DWMWA_SYSTEMBACKDROP_TYPE with DWMSBT_DISABLE");
         unsafe {
             DwmSetWindowAttribute(
                 hwnd,
@@ -212,6 +280,415 @@ pub fn clear_tabbed(hwnd: HWND) -> Result<(), Error> {
     Ok(())
 }
 
+pub fn set_dark_mode(hwnd: HWND, dark: Option<bool>) -> Result<(), Error> {
+    if !is_at_least_build(17763) {
+        return Err(Error::UnsupportedPlatformVersion(
+            "\"set_dark_mode()\" is only available on Windows 10 v1809 or newer and Windows 11.",
+        ));
+    }
+
+    let dark = dark.unwrap_or_else(is_system_dark_mode);
+    let attribute = if is_at_least_build(18985) {
+        DWMWA_USE_IMMERSIVE_DARK_MODE
+    } else {
+        DWMWA_USE_IMMERSIVE_DARK_MODE_BEFORE_20H1
+    };
+    strategy(if attribute == DWMWA_USE_IMMERSIVE_DARK_MODE {
+        "DWMWA_USE_IMMERSIVE_DARK_MODE"
+    } else {
+        "DWMWA_USE_IMMERSIVE_DARK_MODE before 20H1"
+    });
+    unsafe {
+        DwmSetWindowAttribute(hwnd, attribute as _, &(dark as u32) as *const _ as _, 4);
+    }
+    Ok(())
+}
+
+/// The layered state a window had before `set_opacity`, restored by `set_opacity(None)`:
+/// `1 | was_layered << 1 | LWA_* flags << 2 | alpha << 8`, and the color key on its own.
+const LAYERED_PROP: &str = "window-vibrancy-layered";
+const COLOR_KEY_PROP: &str = "window-vibrancy-color-key";
+
+pub fn set_opacity(hwnd: HWND, opacity: Option<f64>) -> Result<(), Error> {
+    strategy("SetLayeredWindowAttributes");
+    let layered_prop = encode_wide(LAYERED_PROP);
+    let color_key_prop = encode_wide(COLOR_KEY_PROP);
+    unsafe {
+        let ex_style = GetWindowLongW(hwnd, GWL_EXSTYLE) as u32;
+        match opacity {
+            Some(opacity) => {
+                if GetPropW(hwnd, layered_prop.as_ptr()) == 0 {
+                    let was_layered = ex_style & WS_EX_LAYERED != 0;
+                    let (mut key, mut alpha, mut flags) = (0, 255, 0);
+                    // fails for windows drawn with `UpdateLayeredWindow`, which have nothing to restore
+                    if was_layered {
+                        GetLayeredWindowAttributes(hwnd, &mut key, &mut alpha, &mut flags);
+                    }
+                    let saved = 1
+                        | (was_layered as isize) << 1
+                        | ((flags & (LWA_COLORKEY | LWA_ALPHA)) as isize) << 2
+                        | (alpha as isize) << 8;
+                    SetPropW(hwnd, layered_prop.as_ptr(), saved);
+                    SetPropW(hwnd, color_key_prop.as_ptr(), key as isize);
+                }
+                SetWindowLongW(hwnd, GWL_EXSTYLE, (ex_style | WS_EX_LAYERED) as i32);
+                let alpha = (opacity.clamp(0.0, 1.0) * 255.0).round() as u8;
+                if SetLayeredWindowAttributes(hwnd, 0, alpha, LWA_ALPHA) == 0 {
+                    return Err(std::io::Error::last_os_error().into());
+                }
+            }
+            None => {
+                let saved = RemovePropW(hwnd, layered_prop.as_ptr());
+                let key = RemovePropW(hwnd, color_key_prop.as_ptr()) as u32;
+                if saved == 0 {
+                    // the opacity was never set
+                    return Ok(());
+                }
+                if saved & 2 == 0 {
+                    SetWindowLongW(hwnd, GWL_EXSTYLE, (ex_style & !WS_EX_LAYERED) as i32);
+                } else {
+                    let flags = ((saved >> 2) & 3) as u32;
+                    let alpha = (saved >> 8) as u8;
+                    if flags != 0 && SetLayeredWindowAttributes(hwnd, key, alpha, flags) == 0 {
+                        return Err(std::io::Error::last_os_error().into());
+                    }
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+pub fn window_state(hwnd: HWND) -> Result<WindowState, Error> {
+    unsafe {
+        let maximized = IsZoomed(hwnd) != 0;
+
+        // Windows has no fullscreen state, fullscreen windows are the ones covering their monitor
+        let mut rect: RECT = std::mem::zeroed();
+        if GetWindowRect(hwnd, &mut rect) == 0 {
+            return Err(std::io::Error::last_os_error().into());
+        }
+        let mut info: MONITORINFO = std::mem::zeroed();
+        info.cbSize = std::mem::size_of::<MONITORINFO>() as u32;
+        let monitor = MonitorFromWindow(hwnd, MONITOR_DEFAULTTONEAREST);
+        if GetMonitorInfoW(monitor, &mut info) == 0 {
+            return Err(std::io::Error::last_os_error().into());
+        }
+        let screen = info.rcMonitor;
+        // a maximized window overflows its monitor by its borders, but never covers the taskbar
+        let fullscreen = !maximized
+            && rect.left <= screen.left
+            && rect.top <= screen.top
+            && rect.right >= screen.right
+            && rect.bottom >= screen.bottom;
+
+        Ok(WindowState {
+            maximized,
+            fullscreen,
+        })
+    }
+}
+
+/// Identifies the subclass of `enable_move_size_swap`, and its timer.
+const MOVE_SIZE_SUBCLASS_ID: usize = 0x5756_4d53;
+
+/// What the move and resize subclass of a window knows.
+struct MoveSizeSwap {
+    machine: MoveSizeMachine,
+    /// The effect to swap and restore.
+    effect: Option<Effect>,
+    /// Whether the tint is drawn by `ACCENT_ENABLE_GRADIENT`.
+    gradient: bool,
+}
+
+/// An `HWND` for the public functions, which apply the user configuration.
+struct Hwnd(HWND);
+
+impl HasWindowHandle for Hwnd {
+    fn window_handle(&self) -> Result<WindowHandle<'_>, HandleError> {
+        let hwnd = NonZeroIsize::new(self.0).ok_or(HandleError::Unavailable)?;
+        Ok(unsafe { WindowHandle::borrow_raw(Win32WindowHandle::new(hwnd).into()) })
+    }
+}
+
+/// Subclasses the window to swap `effect` for the tint of `policy` between
+/// `WM_ENTERSIZEMOVE` and `WM_EXITSIZEMOVE`. Moves and resizes run a modal loop
+/// that blocks the application's event loop, so the subclass handles them itself.
+pub fn enable_move_size_swap(
+    hwnd: HWND,
+    policy: MoveSizePolicy,
+    effect: Option<Effect>,
+) -> Result<(), Error> {
+    disable_move_size_swap(hwnd);
+    let swap = Box::into_raw(Box::new(RefCell::new(MoveSizeSwap {
+        machine: MoveSizeMachine::new(policy),
+        effect,
+        gradient: false,
+    })));
+    unsafe {
+        if SetWindowSubclass(
+            hwnd,
+            Some(move_size_subclass_proc),
+            MOVE_SIZE_SUBCLASS_ID,
+            swap as usize,
+        ) == 0
+        {
+            drop(Box::from_raw(swap));
+            return Err(std::io::Error::last_os_error().into());
+        }
+    }
+    Ok(())
+}
+
+/// Removes the subclass of `enable_move_size_swap`, restoring the effect if it is swapped.
+pub fn disable_move_size_swap(hwnd: HWND) {
+    if let Some(swap) = move_size_swap(hwnd) {
+        unsafe {
+            RemoveWindowSubclass(hwnd, Some(move_size_subclass_proc), MOVE_SIZE_SUBCLASS_ID);
+            KillTimer(hwnd, MOVE_SIZE_SUBCLASS_ID);
+            let swap = Box::from_raw(swap as *const _ as *mut RefCell<MoveSizeSwap>);
+            let swapped = swap.borrow().machine.is_swapped();
+            if swapped {
+                run_move_size_command(hwnd, &swap, MoveSizeCommand::Restore);
+            }
+        }
+    }
+}
+
+/// Tells the subclass of `enable_move_size_swap`, if any, which effect the window has now.
+pub fn set_move_size_effect(hwnd: HWND, effect: Option<Effect>) {
+    if let Some(swap) = move_size_swap(hwnd) {
+        swap.borrow_mut().effect = effect;
+    }
+}
+
+/// Whether the subclass of `enable_move_size_swap`, if any, swapped the effect for its tint.
+pub fn is_move_size_swapped(hwnd: HWND) -> bool {
+    move_size_swap(hwnd).map_or(false, |swap| swap.borrow().machine.is_swapped())
+}
+
+fn move_size_swap<'a>(hwnd: HWND) -> Option<&'a RefCell<MoveSizeSwap>> {
+    let mut data: usize = 0;
+    unsafe {
+        if GetWindowSubclass(
+            hwnd,
+            Some(move_size_subclass_proc),
+            MOVE_SIZE_SUBCLASS_ID,
+            &mut data,
+        ) == 0
+        {
+            return None;
+        }
+        Some(&*(data as *const RefCell<MoveSizeSwap>))
+    }
+}
+
+unsafe extern "system" fn move_size_subclass_proc(
+    hwnd: HWND,
+    msg: u32,
+    wparam: WPARAM,
+    lparam: LPARAM,
+    _id: usize,
+    data: usize,
+) -> LRESULT {
+    let swap = &*(data as *const RefCell<MoveSizeSwap>);
+    match msg {
+        WM_ENTERSIZEMOVE => {
+            swap.borrow_mut()
+                .machine
+                .on_move_size_started(Instant::now());
+            poll_move_size(hwnd, swap);
+        }
+        WM_EXITSIZEMOVE => {
+            swap.borrow_mut().machine.on_move_size_ended(Instant::now());
+            poll_move_size(hwnd, swap);
+        }
+        WM_TIMER if wparam == MOVE_SIZE_SUBCLASS_ID => {
+            poll_move_size(hwnd, swap);
+            return 0;
+        }
+        WM_NCDESTROY => {
+            RemoveWindowSubclass(hwnd, Some(move_size_subclass_proc), MOVE_SIZE_SUBCLASS_ID);
+            KillTimer(hwnd, MOVE_SIZE_SUBCLASS_ID);
+            drop(Box::from_raw(data as *mut RefCell<MoveSizeSwap>));
+        }
+        _ => {}
+    }
+    DefSubclassProc(hwnd, msg, wparam, lparam)
+}
+
+/// Runs the commands that are due and sets a timer for the next ones. `WM_TIMER`
+/// is dispatched by the modal loop too.
+unsafe fn poll_move_size(hwnd: HWND, swap: &RefCell<MoveSizeSwap>) {
+    KillTimer(hwnd, MOVE_SIZE_SUBCLASS_ID);
+    let now = Instant::now();
+    // not borrowed while the commands run, changing the effect may send messages to the window
+    loop {
+        let command = swap.borrow_mut().machine.poll(now);
+        match command {
+            Some(command) => run_move_size_command(hwnd, swap, command),
+            None => break,
+        }
+    }
+    let deadline = swap.borrow().machine.deadline();
+    if let Some(deadline) = deadline {
+        let delay = deadline.saturating_duration_since(now).as_millis().max(1);
+        SetTimer(hwnd, MOVE_SIZE_SUBCLASS_ID, delay as u32, None);
+    }
+}
+
+fn run_move_size_command(hwnd: HWND, swap: &RefCell<MoveSizeSwap>, command: MoveSizeCommand) {
+    let (effect, gradient) = {
+        let swap = swap.borrow();
+        (swap.effect, swap.gradient)
+    };
+    let result = match (command, effect) {
+        // without an effect there is nothing to swap, but one may be set before the restore
+        (MoveSizeCommand::Swap(_), None) => Ok(()),
+        (MoveSizeCommand::Swap(tint), Some(effect)) => {
+            let result = crate::clear_effect(Hwnd(hwnd), &effect);
+            if is_swca_supported() {
+                strategy("SetWindowCompositionAttribute with ACCENT_ENABLE_GRADIENT");
+                unsafe {
+                    SetWindowCompositionAttribute(
+                        hwnd,
+                        ACCENT_STATE::ACCENT_ENABLE_GRADIENT,
+                        Some(tint),
+                    );
+                }
+                swap.borrow_mut().gradient = true;
+            }
+            result
+        }
+        (MoveSizeCommand::Restore, effect) => {
+            if gradient {
+                unsafe {
+                    SetWindowCompositionAttribute(hwnd, ACCENT_STATE::ACCENT_DISABLED, None);
+                }
+                swap.borrow_mut().gradient = false;
+            }
+            effect.map_or(Ok(()), |effect| crate::apply_effect(Hwnd(hwnd), &effect))
+        }
+    };
+    if let Err(e) = result {
+        trace_warn!("{:?} during a move or resize failed: {}", command, e);
+    }
+}
+
+fn is_system_dark_mode() -> bool {
+    personalize_value("AppsUseLightTheme") == Some(0)
+}
+
+/// Reads a DWORD of the user's personalization settings.
+fn personalize_value(name: &str) -> Option<u32> {
+    let subkey = encode_wide("Software\\Microsoft\\Windows\\CurrentVersion\\Themes\\Personalize");
+    let name = encode_wide(name);
+    let mut value: u32 = 0;
+    let mut size = std::mem::size_of_val(&value) as u32;
+    let status = unsafe {
+        RegGetValueW(
+            HKEY_CURRENT_USER,
+            subkey.as_ptr(),
+            name.as_ptr(),
+            RRF_RT_REG_DWORD,
+            std::ptr::null_mut(),
+            &mut value as *mut _ as _,
+            &mut size,
+        )
+    };
+    if status == ERROR_SUCCESS {
+        Some(value)
+    } else {
+        None
+    }
+}
+
+fn is_high_contrast() -> Option<bool> {
+    let mut high_contrast = HIGHCONTRASTW {
+        cbSize: std::mem::size_of::<HIGHCONTRASTW>() as u32,
+        dwFlags: 0,
+        lpszDefaultScheme: std::ptr::null_mut(),
+    };
+    let ok = unsafe {
+        SystemParametersInfoW(
+            SPI_GETHIGHCONTRAST,
+            high_contrast.cbSize,
+            &mut high_contrast as *mut _ as _,
+            0,
+        )
+    };
+    if ok != 0 {
+        Some(high_contrast.dwFlags & HCF_HIGHCONTRASTON != 0)
+    } else {
+        None
+    }
+}
+
+pub fn diagnose(hwnd: HWND, report: &mut Report) {
+    let v = windows_version::OsVersion::current();
+    report.os = Some(format!("Windows {}.{}.{}", v.major, v.minor, v.build));
+    report.display_server = Some(DisplayServer::Win32);
+    if unsafe { IsWindow(hwnd) } == 0 {
+        report
+            .errors
+            .push("the window handle is not a window".into());
+    }
+
+    let mut composition: BOOL = 0;
+    unsafe {
+        DwmIsCompositionEnabled(&mut composition);
+    }
+    report.compositor = Some(if composition != 0 {
+        "DWM".into()
+    } else {
+        "DWM (composition disabled)".into()
+    });
+
+    if is_win7() {
+        report.protocols.push("DwmEnableBlurBehindWindow".into());
+        report
+            .effects
+            .extend(["apply_blur", "clear_blur", "apply_blur_region"]);
+    }
+    if is_swca_supported() {
+        report
+            .protocols
+            .push("SetWindowCompositionAttribute".into());
+        report.effects.extend([
+            "apply_blur",
+            "clear_blur",
+            "apply_acrylic",
+            "clear_acrylic",
+            "set_dark_mode",
+        ]);
+    }
+    if is_undocumented_mica_supported() {
+        report.protocols.push("DWMWA_MICA_EFFECT".into());
+        report.effects.extend(["apply_mica", "clear_mica"]);
+    }
+    if is_backdroptype_supported() {
+        report.protocols.push("DWMWA_SYSTEMBACKDROP_TYPE".into());
+        report.effects.extend([
+            "apply_acrylic",
+            "clear_acrylic",
+            "apply_mica",
+            "clear_mica",
+            "apply_tabbed",
+            "clear_tabbed",
+        ]);
+    }
+    report.effects.sort_unstable();
+    report.effects.dedup();
+
+    report.dark_mode = Some(is_system_dark_mode());
+    report.high_contrast = is_high_contrast();
+    report.reduce_transparency = personalize_value("EnableTransparency").map(|value| value == 0);
+}
+
+fn encode_wide(string: &str) -> Vec<u16> {
+    string.encode_utf16().chain(std::iter::once(0)).collect()
+}
+
 fn get_function_impl(library: &str, function: &str) -> Option<FARPROC> {
     assert_eq!(library.chars().last(), Some('\0'));
     assert_eq!(function.chars().last(), Some('\0'));
@@ -251,6 +728,7 @@ struct WINDOWCOMPOSITIONATTRIBDATA {
 #[repr(C)]
 enum ACCENT_STATE {
     ACCENT_DISABLED = 0,
+    ACCENT_ENABLE_GRADIENT = 1,
     ACCENT_ENABLE_BLURBEHIND = 3,
     ACCENT_ENABLE_ACRYLICBLURBEHIND = 4,
 }
@@ -266,13 +744,9 @@ unsafe fn SetWindowCompositionAttribute(
     if let Some(set_window_composition_attribute) =
         get_function!("user32.dll", SetWindowCompositionAttribute)
     {
-        let mut color = color.unwrap_or_default();
-
         let is_acrylic = accent_state == ACCENT_STATE::ACCENT_ENABLE_ACRYLICBLURBEHIND;
-        if is_acrylic && color.3 == 0 {
-            // acrylic doesn't like to have 0 alpha
-            color.3 = 1;
-        }
+        // acrylic doesn't like to have 0 alpha
+        let color = crate::software::swca_tint(color, is_acrylic);
 
         let mut policy = ACCENT_POLICY {
             AccentState: accent_state as _,
@@ -295,6 +769,7 @@ unsafe fn SetWindowCompositionAttribute(
 }
 
 const DWMWA_MICA_EFFECT: DWMWINDOWATTRIBUTE = 1029;
+const DWMWA_USE_IMMERSIVE_DARK_MODE_BEFORE_20H1: DWMWINDOWATTRIBUTE = 19;
 const DWMWA_SYSTEMBACKDROP_TYPE: DWMWINDOWATTRIBUTE = 38;
 
 #[allow(unused)]
@@ -306,6 +781,18 @@ enum DWM_SYSTEMBACKDROP_TYPE {
     DWMSBT_TABBEDWINDOW = 4,    // Tabbed
 }
 
+/// Reports which API an operation goes through, with the Windows version it was picked for.
+fn strategy(api: &str) {
+    let v = windows_version::OsVersion::current();
+    trace_debug!(
+        "using {} on Windows {}.{}.{}",
+        api,
+        v.major,
+        v.minor,
+        v.build
+    );
+}
+
 fn is_win7() -> bool {
     let v = windows_version::OsVersion::current();
     v.major == 6 && v.minor == 1